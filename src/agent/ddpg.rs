@@ -0,0 +1,327 @@
+use std::{cell::RefCell, rc::Rc};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tch::{Kind, Tensor};
+
+use super::dqn::{ActivationFunction, Policy, ReplayMemory, Transition};
+use crate::{
+    ml::{
+        tensor::{mean_squared_error, GradientClip, TensorStorage},
+        ComputeModel,
+    },
+    simulator::task::{SimulatorTask, TaskId, TimeUnit},
+};
+
+/// Deterministic policy that outputs, for each actionable task, a factor in `[-1, 1]`
+/// scaling the change of its L-mode budget. An alternative to the discrete action space,
+/// which grows combinatorially with the number of tasks.
+#[derive(Debug)]
+pub struct ContinuousPolicy {
+    body: Policy,
+}
+
+impl ContinuousPolicy {
+    pub fn new(
+        storage: &mut TensorStorage,
+        number_features: usize,
+        number_tasks: usize,
+        hidden_sizes: Vec<usize>,
+        activation: ActivationFunction,
+    ) -> Self {
+        Self {
+            body: Policy::new(
+                storage,
+                number_features,
+                number_tasks,
+                hidden_sizes,
+                activation,
+            ),
+        }
+    }
+}
+
+impl ComputeModel for ContinuousPolicy {
+    fn forward(&self, storage: &TensorStorage, input: &Tensor) -> Tensor {
+        self.body.forward(storage, input).tanh()
+    }
+}
+
+/// Estimates the value of taking a continuous action in a state.
+#[derive(Debug)]
+pub struct Critic {
+    body: Policy,
+}
+
+impl Critic {
+    pub fn new(
+        storage: &mut TensorStorage,
+        number_features: usize,
+        number_tasks: usize,
+        hidden_sizes: Vec<usize>,
+        activation: ActivationFunction,
+    ) -> Self {
+        Self {
+            body: Policy::new(
+                storage,
+                number_features + number_tasks,
+                1,
+                hidden_sizes,
+                activation,
+            ),
+        }
+    }
+
+    /// Values of a batch of states and actions, as a `[N, 1]` tensor.
+    pub fn value(&self, storage: &TensorStorage, states: &Tensor, actions: &Tensor) -> Tensor {
+        self.body
+            .forward(storage, &Tensor::cat(&[states, actions], 1))
+    }
+}
+
+/// Trains a `ContinuousPolicy` with DDPG: the critic learns the value of the actions taken,
+/// and the policy follows the gradient of the critic. As in the DQN agent, the target
+/// networks are copies of the trained ones, refreshed every `update_freq` steps.
+pub struct Ddpg {
+    actor: ContinuousPolicy,
+    critic: Critic,
+    memory_actor: TensorStorage,
+    memory_critic: TensorStorage,
+    memory_target_actor: TensorStorage,
+    memory_target_critic: TensorStorage,
+    replay_memory: ReplayMemory<Tensor>,
+    gamma: f32,
+    learning_rate: f32,
+    update_freq: usize,
+    steps: usize,
+    rng: StdRng, // used for the exploration noise
+}
+
+impl Ddpg {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        number_features: usize,
+        number_tasks: usize,
+        hidden_sizes: Vec<usize>,
+        activation: ActivationFunction,
+        capacity: usize,
+        gamma: f32,
+        learning_rate: f32,
+        update_freq: usize,
+    ) -> Self {
+        assert!(update_freq > 0, "Update frequency must be greater than 0.");
+        let mut memory_actor = TensorStorage::default();
+        let mut memory_critic = TensorStorage::default();
+        let actor = ContinuousPolicy::new(
+            &mut memory_actor,
+            number_features,
+            number_tasks,
+            hidden_sizes.clone(),
+            activation,
+        );
+        let critic = Critic::new(
+            &mut memory_critic,
+            number_features,
+            number_tasks,
+            hidden_sizes,
+            activation,
+        );
+        let mut memory_target_actor = TensorStorage::default();
+        memory_target_actor.copy(&memory_actor);
+        let mut memory_target_critic = TensorStorage::default();
+        memory_target_critic.copy(&memory_critic);
+
+        Self {
+            actor,
+            critic,
+            memory_actor,
+            memory_critic,
+            memory_target_actor,
+            memory_target_critic,
+            replay_memory: ReplayMemory::new(capacity, 0),
+            gamma,
+            learning_rate,
+            update_freq,
+            steps: 0,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.replay_memory.reseed(seed);
+    }
+
+    pub fn replay_memory_size(&self) -> usize {
+        self.replay_memory.transitions.len()
+    }
+
+    /// Scaling factors for the state, perturbed by uniform noise of the given amplitude
+    /// to explore, and kept within `[-1, 1]`.
+    pub fn act(&mut self, state: &Tensor, noise: f32) -> Vec<f32> {
+        let action = tch::no_grad(|| self.actor.forward(&self.memory_actor, state));
+        Vec::<f32>::try_from(&action.flatten(0, -1))
+            .unwrap()
+            .into_iter()
+            .map(|factor| {
+                let noise = if noise > 0.0 {
+                    self.rng.gen_range(-noise..=noise)
+                } else {
+                    0.0
+                };
+                (factor + noise).clamp(-1.0, 1.0)
+            })
+            .collect()
+    }
+
+    pub fn push(&mut self, state: &Tensor, action: &[f32], reward: f32, state_: &Tensor) {
+        self.replay_memory.add(Transition::new(
+            state,
+            Tensor::from_slice(action),
+            reward,
+            state_,
+        ));
+    }
+
+    /// Trains the critic and then the policy on a random batch of transitions.
+    /// Returns the loss of the critic, or `None` if fewer than `batch_size`
+    /// transitions were collected.
+    pub fn train_step(&mut self, batch_size: usize) -> Option<f64> {
+        if self.replay_memory.transitions.len() < batch_size {
+            return None;
+        }
+        let (states, actions, rewards, states_) = self.replay_memory.sample_batch(batch_size)?;
+
+        // The critic regresses on the value of the next state under the target policy.
+        let expected_values = tch::no_grad(|| {
+            let actions_ = self.actor.forward(&self.memory_target_actor, &states_);
+            rewards
+                + self.gamma
+                    * self
+                        .critic
+                        .value(&self.memory_target_critic, &states_, &actions_)
+        });
+        let values = self.critic.value(&self.memory_critic, &states, &actions);
+        let critic_loss = mean_squared_error(&expected_values, &values);
+        critic_loss.backward();
+        self.memory_critic
+            .apply_grads_adam(self.learning_rate, GradientClip::None);
+
+        // The policy maximizes the value estimated by the critic, which is left untouched.
+        let actor_loss = -self
+            .critic
+            .value(
+                &self.memory_critic,
+                &states,
+                &self.actor.forward(&self.memory_actor, &states),
+            )
+            .mean(Kind::Float);
+        actor_loss.backward();
+        self.memory_actor
+            .apply_grads_adam(self.learning_rate, GradientClip::None);
+        self.memory_critic.zero_grads();
+
+        self.steps += 1;
+        if self.steps.is_multiple_of(self.update_freq) {
+            self.memory_target_actor.copy(&self.memory_actor);
+            self.memory_target_critic.copy(&self.memory_critic);
+        }
+        Some(critic_loss.double_value(&[]))
+    }
+}
+
+/// Changes the L-mode budget of each task by its factor times `max_change` of its H-mode
+/// budget, keeping it between the floor of the task and its H-mode budget.
+/// Ids of tasks not in the set are ignored.
+pub fn apply_scaling(
+    tasks: &mut [Rc<RefCell<SimulatorTask>>],
+    task_ids: &[TaskId],
+    factors: &[f32],
+    max_change: f32,
+) {
+    assert_eq!(task_ids.len(), factors.len());
+    for (id, factor) in task_ids.iter().zip(factors) {
+        let Some(task) = tasks.iter().find(|t| t.borrow().task.props().id == *id) else {
+            continue;
+        };
+        let mut task = task.borrow_mut();
+        let props = task.task.props();
+        let change = (factor.clamp(-1.0, 1.0) * max_change * props.wcet_h as f32) as i64;
+        let floor = task.wcet_l_floor.min(props.wcet_l);
+        let wcet_l = (props.wcet_l as i64 + change).clamp(floor as i64, props.wcet_h as i64);
+        task.task.props_mut().wcet_l = wcet_l as TimeUnit;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::{apply_scaling, Ddpg};
+    use crate::{
+        agent::dqn::ActivationFunction,
+        simulator::task::{SimulatorTask, Task, TaskProps},
+    };
+
+    #[test]
+    fn continuous_policy() {
+        let mut ddpg = Ddpg::new(6, 3, vec![8], ActivationFunction::ReLU, 100, 0.9, 0.001, 5);
+        ddpg.reseed(0);
+        let state = tch::Tensor::from_slice(&[0.5f32; 6]);
+
+        let action = ddpg.act(&state, 0.0);
+        assert_eq!(action.len(), 3);
+        assert!(action.iter().all(|f| (-1.0..=1.0).contains(f)));
+        let noisy = ddpg.act(&state, 5.0);
+        assert!(noisy.iter().all(|f| (-1.0..=1.0).contains(f)));
+
+        assert_eq!(ddpg.train_step(4), None);
+        for _ in 0..4 {
+            ddpg.push(&state, &action, 1.0, &state);
+        }
+        assert_eq!(ddpg.replay_memory_size(), 4);
+        assert!(ddpg.train_step(4).unwrap().is_finite());
+    }
+
+    #[test]
+    #[should_panic(expected = "Update frequency must be greater than 0.")]
+    fn zero_update_freq() {
+        Ddpg::new(6, 3, vec![8], ActivationFunction::ReLU, 100, 0.9, 0.001, 0);
+    }
+
+    #[test]
+    fn scaling_bounds() {
+        let mut task = SimulatorTask::new(
+            Task::LTask(TaskProps {
+                id: 1,
+                wcet_l: 50,
+                wcet_h: 100,
+                offset: 0,
+                period: 200,
+            }),
+            1,
+            1,
+        );
+        task.wcet_l_floor = 40;
+        let mut tasks = vec![Rc::new(RefCell::new(task))];
+        let wcet_l = |tasks: &[Rc<RefCell<SimulatorTask>>]| tasks[0].borrow().task.props().wcet_l;
+
+        apply_scaling(&mut tasks, &[1], &[0.5], 0.2);
+        assert_eq!(wcet_l(&tasks), 60);
+        apply_scaling(&mut tasks, &[1], &[-0.5], 0.2);
+        assert_eq!(wcet_l(&tasks), 50);
+
+        // The budget never leaves [wcet_l_floor, wcet_h].
+        for _ in 0..10 {
+            apply_scaling(&mut tasks, &[1], &[1.0], 0.2);
+        }
+        assert_eq!(wcet_l(&tasks), 100);
+        for _ in 0..10 {
+            apply_scaling(&mut tasks, &[1], &[-3.0], 0.2);
+        }
+        assert_eq!(wcet_l(&tasks), 40);
+
+        // Unknown tasks are skipped.
+        apply_scaling(&mut tasks, &[2, 1], &[1.0, 0.5], 0.2);
+        assert_eq!(wcet_l(&tasks), 50);
+    }
+}
@@ -1,7 +1,10 @@
-use std::collections::VecDeque;
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+};
 
-use rand::Rng;
-use tch::Tensor;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tch::{Kind, TchError, Tensor};
 
 use crate::ml::{linear::LinearLayer, tensor::TensorStorage, ComputeModel};
 
@@ -12,10 +15,26 @@ pub enum ActivationFunction {
     Sigmoid,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PolicyArchitecture {
+    Standard,
+    Dueling,
+}
+
+impl ActivationFunction {
+    fn apply(&self, input: &Tensor) -> Tensor {
+        match self {
+            ActivationFunction::Tanh => input.tanh(),
+            ActivationFunction::ReLU => input.relu(),
+            ActivationFunction::Sigmoid => input.sigmoid(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Policy {
     layers: Vec<LinearLayer>,
-    activation: ActivationFunction,
+    activations: Vec<ActivationFunction>, // one per hidden layer
 }
 
 impl Policy {
@@ -25,8 +44,30 @@ impl Policy {
         number_actions: usize,
         hidden_sizes: Vec<usize>,
         activation: ActivationFunction,
+    ) -> Policy {
+        let activations = vec![activation; hidden_sizes.len()];
+        Self::new_with_activations(
+            storage,
+            number_features,
+            number_actions,
+            hidden_sizes,
+            activations,
+        )
+    }
+
+    pub fn new_with_activations(
+        storage: &mut TensorStorage,
+        number_features: usize,
+        number_actions: usize,
+        hidden_sizes: Vec<usize>,
+        activations: Vec<ActivationFunction>,
     ) -> Policy {
         assert!(!hidden_sizes.is_empty());
+        assert_eq!(
+            activations.len(),
+            hidden_sizes.len(),
+            "There must be one activation function per hidden layer."
+        );
         let mut layers = Vec::new();
 
         for (i, size) in hidden_sizes.iter().enumerate() {
@@ -49,7 +90,10 @@ impl Policy {
             number_actions as i64,
         ));
 
-        Self { layers, activation }
+        Self {
+            layers,
+            activations,
+        }
     }
 }
 
@@ -61,11 +105,7 @@ impl ComputeModel for Policy {
             if i > 0 {
                 o = self.layers[i].forward(storage, &o);
             }
-            o = match self.activation {
-                ActivationFunction::Tanh => o.tanh(),
-                ActivationFunction::ReLU => o.relu(),
-                ActivationFunction::Sigmoid => o.sigmoid(),
-            };
+            o = self.activations[i].apply(&o);
         }
 
         o = self.layers.last().unwrap().forward(storage, &o);
@@ -73,16 +113,104 @@ impl ComputeModel for Policy {
     }
 }
 
+/// As per "Dueling Network Architectures for Deep Reinforcement Learning" (2016).
+/// The last hidden representation is split into a value stream V(s) and an
+/// advantage stream A(s,a), which are recombined as Q = V + (A - mean(A)).
+/// This helps when many actions have a similar value.
 #[derive(Debug)]
-pub struct Transition {
-    state: Tensor,
-    action: i64,
-    reward: f32,
-    state_: Tensor,
+pub struct DuelingPolicy {
+    layers: Vec<LinearLayer>,
+    value: LinearLayer,
+    advantage: LinearLayer,
+    activation: ActivationFunction,
 }
 
-impl Transition {
-    pub fn new(state: &Tensor, action: i64, reward: f32, state_: &Tensor) -> Self {
+impl DuelingPolicy {
+    pub fn new(
+        storage: &mut TensorStorage,
+        number_features: usize,
+        number_actions: usize,
+        hidden_sizes: Vec<usize>,
+        activation: ActivationFunction,
+    ) -> DuelingPolicy {
+        assert!(!hidden_sizes.is_empty());
+        let mut layers = Vec::new();
+
+        for (i, size) in hidden_sizes.iter().enumerate() {
+            let input_size = if i == 0 {
+                number_features
+            } else {
+                hidden_sizes[i - 1]
+            };
+            layers.push(LinearLayer::new(storage, input_size as i64, *size as i64));
+        }
+
+        let last_hidden_size = hidden_sizes[hidden_sizes.len() - 1] as i64;
+        let value = LinearLayer::new(storage, last_hidden_size, 1);
+        let advantage = LinearLayer::new(storage, last_hidden_size, number_actions as i64);
+
+        Self {
+            layers,
+            value,
+            advantage,
+            activation,
+        }
+    }
+}
+
+impl ComputeModel for DuelingPolicy {
+    fn forward(&self, storage: &TensorStorage, input: &Tensor) -> Tensor {
+        let mut o = input.shallow_clone();
+        for layer in &self.layers {
+            o = self.activation.apply(&layer.forward(storage, &o));
+        }
+
+        let value = self.value.forward(storage, &o);
+        let advantage = self.advantage.forward(storage, &o);
+        let mean_advantage = advantage.mean_dim(vec![-1], true, Kind::Float);
+        value + (advantage - mean_advantage)
+    }
+}
+
+/// An action as kept in a transition: the index of a discrete action,
+/// or the factors of a continuous one.
+pub trait StoredAction: Sized {
+    fn copy(&self) -> Self;
+
+    /// Stacks the actions of a batch into a `[N, ...]` tensor.
+    fn batch(actions: &[Self]) -> Tensor;
+}
+
+impl StoredAction for i64 {
+    fn copy(&self) -> Self {
+        *self
+    }
+
+    fn batch(actions: &[Self]) -> Tensor {
+        Tensor::from_slice(actions).unsqueeze(1)
+    }
+}
+
+impl StoredAction for Tensor {
+    fn copy(&self) -> Self {
+        self.shallow_clone()
+    }
+
+    fn batch(actions: &[Self]) -> Tensor {
+        Tensor::stack(actions, 0)
+    }
+}
+
+#[derive(Debug)]
+pub struct Transition<A = i64> {
+    pub(crate) state: Tensor,
+    pub(crate) action: A,
+    pub(crate) reward: f32,
+    pub(crate) state_: Tensor,
+}
+
+impl<A: StoredAction> Transition<A> {
+    pub fn new(state: &Tensor, action: A, reward: f32, state_: &Tensor) -> Self {
         Self {
             state: state.shallow_clone(),
             action,
@@ -92,62 +220,147 @@ impl Transition {
     }
 }
 
-pub struct ReplayMemory {
-    pub transitions: VecDeque<Transition>,
+pub struct ReplayMemory<A = i64> {
+    pub transitions: VecDeque<Transition<A>>,
     pub capacity: usize,
     pub min_size: usize,
+    rng: StdRng, // used to sample batches
 }
 
-impl ReplayMemory {
+impl<A: StoredAction> ReplayMemory<A> {
     pub fn new(capacity: usize, min_size: usize) -> Self {
         Self {
             transitions: VecDeque::new(),
             capacity,
             min_size,
+            rng: StdRng::from_entropy(),
         }
     }
 
-    pub fn add(&mut self, transition: Transition) {
+    /// Makes the sampled batches reproducible from now on.
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn add(&mut self, transition: Transition<A>) {
         self.transitions.push_back(transition);
         if self.transitions.len() > self.capacity {
             self.transitions.pop_front();
         }
     }
 
-    pub fn add_initial(&mut self, transition: Transition) -> bool {
-        if self.transitions.len() < self.min_size {
+    /// Copies the stored transitions, e.g. to seed another agent's memory.
+    pub fn transitions(&self) -> Vec<Transition<A>> {
+        self.transitions
+            .iter()
+            .map(|t| Transition::new(&t.state, t.action.copy(), t.reward, &t.state_))
+            .collect()
+    }
+
+    /// Fills the memory up to its minimum size, returning whether it got there.
+    /// The memory is never deemed filled while empty, so that batches can be sampled.
+    pub fn add_initial(&mut self, transition: Transition<A>) -> bool {
+        let min_size = self.min_size.max(1);
+        if self.transitions.len() < min_size {
             self.add(transition);
         }
-        self.transitions.len() >= self.min_size
+        self.transitions.len() >= min_size
     }
 
-    pub fn sample_batch(&self, size: usize) -> (Tensor, Tensor, Tensor, Tensor) {
+    /// Samples `size` transitions with replacement, as (states, actions, rewards, next states).
+    /// Returns None if the memory is empty or no transitions are asked for.
+    pub fn sample_batch(&mut self, size: usize) -> Option<(Tensor, Tensor, Tensor, Tensor)> {
+        if self.transitions.is_empty() || size == 0 {
+            return None;
+        }
         let index: Vec<usize> = (0..size)
-            .map(|_| rand::thread_rng().gen_range(0..self.transitions.len()))
+            .map(|_| self.rng.gen_range(0..self.transitions.len()))
             .collect();
         let mut states: Vec<Tensor> = Vec::new();
-        let mut actions: Vec<i64> = Vec::new();
+        let mut actions: Vec<A> = Vec::new();
         let mut rewards: Vec<f32> = Vec::new();
         let mut states_: Vec<Tensor> = Vec::new();
         index.iter().for_each(|i| {
             let transition = self.transitions.get(*i).unwrap();
             states.push(transition.state.shallow_clone());
-            actions.push(transition.action);
+            actions.push(transition.action.copy());
             rewards.push(transition.reward);
             states_.push(transition.state_.shallow_clone());
         });
-        (
+        Some((
             Tensor::stack(&states, 0),
-            Tensor::from_slice(actions.as_slice()).unsqueeze(1),
+            A::batch(&actions),
             Tensor::from_slice(rewards.as_slice()).unsqueeze(1),
             Tensor::stack(&states_, 0),
+        ))
+    }
+}
+
+impl ReplayMemory {
+    /// Stores the transitions in a file, so that training can be resumed in another process.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), TchError> {
+        if self.transitions.is_empty() {
+            return Tensor::save_multi::<&str, &Tensor, _>(&[], path);
+        }
+
+        let states = self.transitions.iter().map(|t| t.state.shallow_clone());
+        let actions = self
+            .transitions
+            .iter()
+            .map(|t| t.action)
+            .collect::<Vec<_>>();
+        let rewards = self
+            .transitions
+            .iter()
+            .map(|t| t.reward)
+            .collect::<Vec<_>>();
+        let states_ = self.transitions.iter().map(|t| t.state_.shallow_clone());
+        Tensor::save_multi(
+            &[
+                ("states", Tensor::stack(&states.collect::<Vec<_>>(), 0)),
+                ("actions", Tensor::from_slice(&actions)),
+                ("rewards", Tensor::from_slice(&rewards)),
+                ("states_", Tensor::stack(&states_.collect::<Vec<_>>(), 0)),
+            ],
+            path,
         )
     }
+
+    /// Loads the transitions stored with `save` into a new memory.
+    pub fn load<P: AsRef<Path>>(
+        path: P,
+        capacity: usize,
+        min_size: usize,
+    ) -> Result<Self, TchError> {
+        let mut memory = Self::new(capacity, min_size);
+        let tensors = Tensor::load_multi(path)?
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        if tensors.is_empty() {
+            return Ok(memory);
+        }
+
+        let states = &tensors["states"];
+        let actions = &tensors["actions"];
+        let rewards = &tensors["rewards"];
+        let states_ = &tensors["states_"];
+        for i in 0..actions.size()[0] {
+            memory.add(Transition::new(
+                &states.get(i),
+                actions.int64_value(&[i]),
+                rewards.double_value(&[i]) as f32,
+                &states_.get(i),
+            ));
+        }
+        Ok(memory)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Policy;
+    use super::{DuelingPolicy, Policy, ReplayMemory, Transition};
+    use crate::ml::{ComputeModel, DEVICE};
+    use tch::{Kind, Tensor};
 
     #[test]
     fn new_policy() {
@@ -171,4 +384,162 @@ mod tests {
         assert!(policy.layers[2].weights(storage).size() == [8, 13]);
         assert!(policy.layers[2].bias(storage).size() == [1, 13]);
     }
+
+    #[test]
+    fn mixed_activations() {
+        let storage = &mut crate::ml::tensor::TensorStorage::default();
+        let policy = Policy::new_with_activations(
+            storage,
+            4,
+            13,
+            vec![16, 8],
+            vec![
+                crate::agent::dqn::ActivationFunction::ReLU,
+                crate::agent::dqn::ActivationFunction::Tanh,
+            ],
+        );
+
+        let output = policy.forward(storage, &Tensor::ones(&[5, 4], (Kind::Float, DEVICE)));
+        assert!(output.size() == [5, 13]);
+
+        // The last hidden layer uses tanh, so its output is bounded.
+        let hidden = policy.activations[1].apply(&policy.layers[1].forward(
+            storage,
+            &policy.activations[0].apply(
+                &policy.layers[0].forward(storage, &Tensor::ones(&[1, 4], (Kind::Float, DEVICE))),
+            ),
+        ));
+        assert!(hidden.abs().max().double_value(&[]) <= 1.0);
+    }
+
+    #[test]
+    fn new_dueling_policy() {
+        let storage = &mut crate::ml::tensor::TensorStorage::default();
+        let policy = DuelingPolicy::new(
+            storage,
+            4,
+            13,
+            vec![16, 8],
+            crate::agent::dqn::ActivationFunction::ReLU,
+        );
+
+        assert!(policy.layers.len() == 2);
+        assert!(policy.layers[1].weights(storage).size() == [16, 8]);
+        assert!(policy.value.weights(storage).size() == [8, 1]);
+        assert!(policy.advantage.weights(storage).size() == [8, 13]);
+
+        let output = policy.forward(storage, &Tensor::ones(&[5, 4], (Kind::Float, DEVICE)));
+        assert!(output.size() == [5, 13]);
+    }
+
+    #[test]
+    fn dueling_recombination() {
+        let storage = &mut crate::ml::tensor::TensorStorage::default();
+        let policy = DuelingPolicy::new(
+            storage,
+            4,
+            13,
+            vec![8],
+            crate::agent::dqn::ActivationFunction::Tanh,
+        );
+        let input = Tensor::ones(&[1, 4], (Kind::Float, DEVICE));
+        let q_values = policy.forward(storage, &input);
+        assert!(q_values.size() == [1, 13]);
+
+        // The advantages are centered, so the mean of the Q-values is V(s).
+        let hidden = policy.layers[0].forward(storage, &input).tanh();
+        let value = policy.value.forward(storage, &hidden).double_value(&[0, 0]);
+        let mean_q_value = q_values.mean(Kind::Float).double_value(&[]);
+        assert!((mean_q_value - value).abs() < 1e-4);
+    }
+
+    #[test]
+    fn replay_memory_round_trip() {
+        let mut memory = ReplayMemory::new(10, 5);
+        for i in 0..8 {
+            let state = Tensor::from_slice(&[i as f32, 1.0, 2.0]);
+            let state_ = Tensor::from_slice(&[i as f32 + 1.0, 1.0, 2.0]);
+            memory.add(Transition::new(&state, i % 3, i as f32 / 2.0, &state_));
+        }
+
+        // Unique per run, so that concurrent or earlier runs cannot interfere.
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let path = std::env::temp_dir().join(format!(
+            "replay_memory_round_trip_{}_{}.ot",
+            std::process::id(),
+            nanos
+        ));
+        memory.save(&path).unwrap();
+        let mut loaded = ReplayMemory::load(&path, 10, 5).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.transitions.len(), memory.transitions.len());
+        for (t, u) in memory.transitions.iter().zip(loaded.transitions.iter()) {
+            assert!(t.state.equal(&u.state));
+            assert_eq!(t.action, u.action);
+            assert_eq!(t.reward, u.reward);
+            assert!(t.state_.equal(&u.state_));
+        }
+
+        // Reseeded alike, both memories sample the same batches.
+        memory.reseed(7);
+        loaded.reseed(7);
+        for _ in 0..3 {
+            let (states1, actions1, rewards1, states1_) = memory.sample_batch(4).unwrap();
+            let (states2, actions2, rewards2, states2_) = loaded.sample_batch(4).unwrap();
+            assert!(states1.equal(&states2));
+            assert!(actions1.equal(&actions2));
+            assert!(rewards1.equal(&rewards2));
+            assert!(states1_.equal(&states2_));
+        }
+
+        let empty = ReplayMemory::new(10, 5);
+        empty.save(&path).unwrap();
+        assert!(ReplayMemory::load(&path, 10, 5)
+            .unwrap()
+            .transitions
+            .is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn seeded_batches() {
+        let memory = || {
+            let mut memory = ReplayMemory::new(20, 5);
+            for i in 0..20 {
+                let state = Tensor::from_slice(&[i as f32]);
+                memory.add(Transition::new(&state, i, i as f32, &state));
+            }
+            memory.reseed(42);
+            memory
+        };
+
+        let (mut first, mut second) = (memory(), memory());
+        for _ in 0..3 {
+            let (states1, actions1, rewards1, states1_) = first.sample_batch(8).unwrap();
+            let (states2, actions2, rewards2, states2_) = second.sample_batch(8).unwrap();
+            assert!(states1.equal(&states2));
+            assert!(actions1.equal(&actions2));
+            assert!(rewards1.equal(&rewards2));
+            assert!(states1_.equal(&states2_));
+        }
+    }
+
+    #[test]
+    fn empty_memory() {
+        let mut memory = ReplayMemory::new(10, 0);
+        assert!(memory.sample_batch(4).is_none());
+
+        // Even with no minimum size, the memory is only filled once it holds a transition.
+        let state = Tensor::from_slice(&[0.0f32, 1.0]);
+        assert!(memory.add_initial(Transition::new(&state, 0, 1.0, &state)));
+        assert_eq!(memory.transitions.len(), 1);
+        assert!(memory.sample_batch(0).is_none());
+        let (states, actions, _, _) = memory.sample_batch(4).unwrap();
+        assert_eq!(states.size(), vec![4, 2]);
+        assert_eq!(actions.size(), vec![4, 1]);
+    }
 }
@@ -0,0 +1,69 @@
+use tch::Tensor;
+
+use super::SimulatorAgent;
+use crate::simulator::Simulator;
+
+/// Builds the state given to the networks of the agent from what it observed
+/// and the current state of the simulator.
+pub trait StateEncoder {
+    /// The state, as a tensor of `num_features` values.
+    fn encode(&self, agent: &SimulatorAgent, simulator: &Simulator) -> Tensor;
+
+    fn num_features(&self) -> usize;
+}
+
+/// Two features per task, from top to bottom: its L-mode budget and the execution time
+/// of its last job (-1 if unknown), both normalized to the range from its BCET to its `wcet_h`.
+/// Optionally followed by the load of the simulator: the ready queue length and the number
+/// of pending events, relative to the number of tasks.
+#[derive(Debug, Clone, Copy)]
+pub struct DefaultEncoder {
+    number_of_tasks: usize,
+    load_features: bool,
+}
+
+impl DefaultEncoder {
+    pub fn new(number_of_tasks: usize) -> Self {
+        Self {
+            number_of_tasks,
+            load_features: false,
+        }
+    }
+
+    /// Also encodes the backlog of the simulator, which builds up before an overload.
+    pub fn with_load_features(mut self) -> Self {
+        self.load_features = true;
+        self
+    }
+}
+
+impl StateEncoder for DefaultEncoder {
+    fn encode(&self, agent: &SimulatorAgent, simulator: &Simulator) -> Tensor {
+        let mut input = Vec::with_capacity(self.num_features());
+
+        for task in simulator.tasks.iter().take(self.number_of_tasks) {
+            let wcet_l = task.borrow().task.props().wcet_l as f32;
+            let wcet_h = task.borrow().task.props().wcet_h as f32;
+            let bcet = task.borrow().bcet as f32;
+            let last_job_execution_time = agent
+                .last_execution_time(task.borrow().task.props().id)
+                .map_or(-1.0, |exec_time| exec_time as f32);
+
+            // Push normalized values.
+            input.push((wcet_l - bcet) / (wcet_h - bcet));
+            input.push((last_job_execution_time - bcet) / (wcet_h - bcet));
+        }
+
+        if self.load_features {
+            let number_of_tasks = self.number_of_tasks as f32;
+            input.push(simulator.ready_queue_len() as f32 / number_of_tasks);
+            input.push(simulator.pending_event_count() as f32 / number_of_tasks);
+        }
+
+        Tensor::from_slice(input.as_slice())
+    }
+
+    fn num_features(&self) -> usize {
+        self.number_of_tasks * 2 + if self.load_features { 2 } else { 0 }
+    }
+}
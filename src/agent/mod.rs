@@ -1,18 +1,28 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
 use std::rc::Rc;
 
-use self::dqn::{Policy, ReplayMemory};
+use self::ddpg::{apply_scaling, Ddpg};
+use self::dqn::{DuelingPolicy, Policy, PolicyArchitecture, ReplayMemory};
+use self::encoder::{DefaultEncoder, StateEncoder};
 use crate::agent::dqn::Transition;
-use crate::ml::tensor::{mean_squared_error, TensorStorage};
+use crate::ml::tensor::{
+    accuracy, cross_entropy, GradientClip, LearningRateSchedule, LossConfig, TensorStorage,
+};
 use crate::ml::ComputeModel;
 use crate::simulator::task::{SimulatorTask, TaskProps, TimeUnit};
+use crate::simulator::validation::{
+    feasible_schedule_online, feasible_schedule_online_exact, response_time,
+};
 use crate::simulator::SimulatorMode;
 use crate::simulator::{task::TaskId, Simulator, SimulatorEvent};
 use rand::Rng;
-use tch::Tensor;
+use tch::{TchError, Tensor};
 
+pub mod ddpg;
 pub mod dqn;
+pub mod encoder;
 
 pub const DEFAULT_MEM_SIZE: usize = 200;
 pub const DEFAULT_MIN_MEM_SIZE: usize = 20;
@@ -20,18 +30,16 @@ pub const DEFAULT_GAMMA: f32 = 0.99;
 pub const DEFAULT_UPDATE_FREQ: usize = 5;
 pub const DEFAULT_LEARNING_RATE: f32 = 0.00005;
 pub const DEFAULT_SAMPLE_BATCH_SIZE: usize = 6;
-pub const MAX_EVENTS_STORED: usize = 10000;
+pub const DEFAULT_EVENT_HISTORY_CAPACITY: usize = 10000;
+pub const MEM_SIZE_PER_OUTPUT: usize = 10; // transitions per action or feature, for `suggested_mem_size`
 
-pub type SimulatorAction = (
-    SimulatorActionPart,
-    SimulatorActionPart,
-    SimulatorActionPart,
-);
+/// The parts of an action are applied in order.
+pub type SimulatorAction = Vec<SimulatorActionPart>;
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum SimulatorActionPart {
-    WcetIncrease(TaskId), // 10% increase
-    WcetDecrease(TaskId), // 5% decrease
+    WcetIncrease(TaskId), // increase by a fraction of wcet_h
+    WcetDecrease(TaskId), // decrease by a fraction of wcet_h
     None,
 }
 
@@ -43,32 +51,63 @@ impl SimulatorActionPart {
         }
     }
 
-    pub fn apply(&self, tasks: &mut [Rc<RefCell<SimulatorTask>>]) {
+    fn task_to_change<'a>(
+        &self,
+        tasks: &'a mut [Rc<RefCell<SimulatorTask>>],
+    ) -> &'a mut Rc<RefCell<SimulatorTask>> {
+        tasks
+            .iter_mut()
+            .find(|t| t.borrow().task.props().id == self.task_id())
+            .unwrap()
+    }
+
+    /// Changes the budget of the task, returning by how much it actually changed,
+    /// which may be less than the magnitude if the budget was clamped.
+    pub fn apply(
+        &self,
+        tasks: &mut [Rc<RefCell<SimulatorTask>>],
+        magnitudes: ActionMagnitudes,
+    ) -> TimeUnit {
         if matches!(self, SimulatorActionPart::None) {
-            return;
+            return 0;
         }
 
-        let task_to_change = tasks
-            .iter_mut()
-            .find(|t| t.borrow().task.props().id == self.task_id())
-            .unwrap();
+        let task_to_change = self.task_to_change(tasks);
 
         let amount = (task_to_change.borrow().task.props().wcet_h as f32
             * match self {
-                SimulatorActionPart::WcetIncrease(_) => 0.1,
-                SimulatorActionPart::WcetDecrease(_) => 0.05,
+                SimulatorActionPart::WcetIncrease(_) => magnitudes.increase_factor,
+                SimulatorActionPart::WcetDecrease(_) => magnitudes.decrease_factor,
                 _ => unreachable!(),
             }) as TimeUnit;
 
         let wcet_l = task_to_change.borrow_mut().task.props().wcet_l;
-        match self {
-            SimulatorActionPart::WcetIncrease(_) => {
-                task_to_change.borrow_mut().task.props_mut().wcet_l = wcet_l.saturating_add(amount);
-            }
+        let new_wcet_l = match self {
+            SimulatorActionPart::WcetIncrease(_) => wcet_l.saturating_add(amount),
             SimulatorActionPart::WcetDecrease(_) => {
-                task_to_change.borrow_mut().task.props_mut().wcet_l = wcet_l.saturating_sub(amount);
+                // A null budget would make the schedule unfeasible, so we clamp to the floor.
+                let floor = task_to_change.borrow().wcet_l_floor.min(wcet_l);
+                wcet_l.saturating_sub(amount).max(floor)
             }
             SimulatorActionPart::None => unreachable!(),
+        };
+        task_to_change.borrow_mut().task.props_mut().wcet_l = new_wcet_l;
+        wcet_l.abs_diff(new_wcet_l)
+    }
+
+    /// Undoes a previous `apply` that changed the budget by `applied`.
+    fn undo(&self, tasks: &mut [Rc<RefCell<SimulatorTask>>], applied: TimeUnit) {
+        if matches!(self, SimulatorActionPart::None) {
+            return;
+        }
+
+        let task_to_change = self.task_to_change(tasks);
+        let mut task = task_to_change.borrow_mut();
+        let props = task.task.props_mut();
+        match self {
+            SimulatorActionPart::WcetIncrease(_) => props.wcet_l -= applied,
+            SimulatorActionPart::WcetDecrease(_) => props.wcet_l += applied,
+            SimulatorActionPart::None => unreachable!(),
         }
     }
 
@@ -81,8 +120,125 @@ impl SimulatorActionPart {
     }
 }
 
-#[derive(Debug, PartialEq)]
-enum SimulatorAgentStage {
+/// Applies each part of the action to the task set, in order.
+/// Returns by how much each part changed its task's budget, to revert the action.
+pub fn apply_action(
+    action: &[SimulatorActionPart],
+    tasks: &mut [Rc<RefCell<SimulatorTask>>],
+    magnitudes: ActionMagnitudes,
+) -> Vec<TimeUnit> {
+    action
+        .iter()
+        .map(|part| part.apply(tasks, magnitudes))
+        .collect()
+}
+
+/// The action that undoes the given one: each part reversed, in the opposite order.
+/// Note that applying it does not restore clamped budgets; see `revert_action`.
+pub fn reverse_action(action: &[SimulatorActionPart]) -> SimulatorAction {
+    action.iter().rev().map(|part| part.reverse()).collect()
+}
+
+/// Undoes an action, given the changes returned by `apply_action`, restoring
+/// the exact budgets it found.
+pub fn revert_action(
+    action: &[SimulatorActionPart],
+    applied: &[TimeUnit],
+    tasks: &mut [Rc<RefCell<SimulatorTask>>],
+) {
+    assert_eq!(action.len(), applied.len());
+    for (part, applied) in action.iter().zip(applied).rev() {
+        part.undo(tasks, *applied);
+    }
+}
+
+/// Fractions of `wcet_h` added to or removed from `wcet_l` by each action part.
+/// By default, increases of 10% and decreases of 5%.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct ActionMagnitudes {
+    pub increase_factor: f32,
+    pub decrease_factor: f32,
+}
+
+impl Default for ActionMagnitudes {
+    fn default() -> Self {
+        Self {
+            increase_factor: 0.1,
+            decrease_factor: 0.05,
+        }
+    }
+}
+
+/// Number of WCET increases and decreases combined in each action,
+/// always on distinct tasks. By default, one increase and two decreases.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct ActionShape {
+    pub increases: usize,
+    pub decreases: usize,
+}
+
+impl Default for ActionShape {
+    fn default() -> Self {
+        Self {
+            increases: 1,
+            decreases: 2,
+        }
+    }
+}
+
+/// Whether an action chosen by the agent was kept by the simulator, or reverted
+/// because it made the schedule unfeasible.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ActionOutcome {
+    Applied,
+    Reverted,
+}
+
+pub type ActionHook = Box<dyn FnMut(&SimulatorAction, ActionOutcome)>;
+
+/// Progress of the training, as given to the metrics sink after each optimizer step.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct TrainingMetrics {
+    pub step: usize,
+    pub loss: f64, // summed over the accumulated batches, each weighing as a share
+    pub epsilon: f32,
+    pub cumulative_reward: f64,
+    pub replay_size: usize,
+}
+
+pub type MetricsSink = Box<dyn FnMut(&TrainingMetrics)>;
+
+/// What an action would do to the task set, as told by `SimulatorAgent::evaluate_action`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ActionEvaluation {
+    pub feasible: bool,
+    // Period minus L-mode response time of each task, in the order of the simulator.
+    // Negative if the task would miss its deadline, None if the analysis did not converge.
+    pub slacks: Vec<(TaskId, Option<i64>)>,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ExplorationStrategy {
+    // With probability epsilon, take a random action; otherwise, the best one.
+    EpsilonGreedy,
+
+    // Sample an action from the softmax of the Q-values, scaled by the temperature.
+    // Lower temperatures favour the best actions.
+    Softmax { temperature: f32 },
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum DataCollectionStrategy {
+    // Actions are picked as in training, i.e. at random while epsilon is high.
+    Uniform,
+
+    // The task whose WCET is increased is the one picked the fewest times so far
+    // (doing nothing counts as a task), so that every task is covered by the memory.
+    Stratified,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum SimulatorAgentStage {
     // In the data collection stage, we fill the replay memory
     // with transitions collected from experience, to be sampled
     // later for training.
@@ -98,38 +254,99 @@ enum SimulatorAgentStage {
     // In placebo mode, the agent does nothing and just collects rewards.
     // Used for testing.
     Placebo,
+
+    // In monitor mode, the agent never acts, but still learns the value of the states
+    // it observes. Isolates how well the state predicts the reward from whether acting helps.
+    Monitor,
+}
+
+/// Reward given to the agent for each kind of event. `energy` is instead
+/// multiplied by the fraction of time the processor was busy since the last activation,
+/// and `deadline_miss_ratio` by the fraction of the jobs released since then that missed
+/// their deadline, which lets soft L-tasks miss a bounded share of their deadlines.
+/// With a `hmode_recovery_horizon`, a change to H-mode reverted within the same window
+/// is penalized in proportion to how long H-mode lasted, up to that horizon.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct RewardWeights {
+    pub kill: f64,
+    pub hmode_change: f64,
+    pub lmode_change: f64,
+    pub task_start: f64,
+    pub deadline_miss: f64,
+    pub deadline_miss_ratio: f64,
+    pub energy: f64,
+    pub hmode_recovery_horizon: TimeUnit, // 0 to always give the full penalty
+}
+
+impl Default for RewardWeights {
+    fn default() -> Self {
+        Self {
+            kill: -1.0,
+            hmode_change: -2.0,
+            lmode_change: 0.0,
+            task_start: 0.1,
+            deadline_miss: 0.0,
+            deadline_miss_ratio: 0.0,
+            energy: 0.0,
+            hmode_recovery_horizon: 0,
+        }
+    }
 }
 
 pub struct SimulatorAgent {
     // The agent is informed periodically about the state of the simulator.
-    events_history: Vec<SimulatorEvent>,
+    events_history: VecDeque<SimulatorEvent>,
+    event_history_capacity: usize, // the oldest events are dropped beyond it
     cumulative_reward: f64,
     mode_changes_to_hmode: usize,
     mode_changes_to_lmode: usize,
     task_kills: usize,
     task_starts: usize,
     last_processed_event_index: usize,
+    events_pushed: usize, // total number of events ever received, including end events
+    observation_window: usize,
     track: bool,
     number_of_features: usize,
     _number_of_actions: usize,
     number_of_tasks: usize,
+    task_ids: Vec<TaskId>,         // ids of the task set, in the order given
+    actionable_tasks: Vec<TaskId>, // ids of the tasks whose WCETs the agent may change
+    action_shape: ActionShape,
+    action_magnitudes: ActionMagnitudes,
+    architecture: PolicyArchitecture,
 
     // DQN parameters.
     sample_batch_size: usize,
+    accumulate_steps: usize, // batches whose gradients add up before each optimizer step
     gamma: f32,
     update_freq: usize,
     learning_rate: f32,
+    learning_rate_schedule: LearningRateSchedule,
+    optimizer_steps: usize,
     stage: SimulatorAgentStage,
+    exploration: ExplorationStrategy,
+    data_collection: DataCollectionStrategy,
+    exact_online_analysis: bool,
+    increase_counts: HashMap<Option<TaskId>, usize>, // times each task was increased, for stratification
+    gradient_clip: GradientClip,
+    loss: LossConfig,
+    reward_weights: RewardWeights,
+    last_activation_time: TimeUnit,
+    last_busy_time: TimeUnit,
+    last_releases: usize,
+    last_deadline_misses: usize,
+    hidden_sizes: Vec<usize>,
+    activation: dqn::ActivationFunction,
 
     // DQN model
     /// The policy network is the one that is being trained.
     /// It receives the state as input and outputs the Q-values for each action.
-    policy_network: Policy,
+    policy_network: Box<dyn ComputeModel>,
 
     /// The target network is a snapshot of the policy network that is
     /// used to compute the loss and update the policy network via backpropagation.
     /// DQN uses this to stabilize the learning process.
-    target_network: Policy,
+    target_network: Box<dyn ComputeModel>,
 
     /// The replay memory is a collection of tuples (state, action, reward, state')
     /// stored from experience.
@@ -139,10 +356,20 @@ pub struct SimulatorAgent {
     memory_target: TensorStorage,
     epsilon: f32,
     reward_history: Vec<f32>,
+    action_history: Vec<usize>, // index of the action chosen at each activation
 
     buffered_action: Option<SimulatorAction>,
     buffered_state: Option<Tensor>,
-    exec_times: HashMap<TaskId, TimeUnit>,
+    on_action: Option<ActionHook>,
+    metrics_sink: Option<MetricsSink>,
+    exec_times: HashMap<TaskId, (TimeUnit, usize)>, // (execution time, events pushed until then)
+    state_encoder: Box<dyn StateEncoder>,
+    ensemble: Vec<(TensorStorage, Box<dyn ComputeModel>)>, // members voting along the policy network
+    ddpg: Option<Ddpg>, // scales the budgets instead of taking discrete actions
+    max_scaling: f32,   // largest budget change per activation, as a fraction of wcet_h
+    buffered_factors: Vec<f32>,
+    #[cfg(test)]
+    fixed_action: Option<SimulatorAction>, // taken instead of consulting the policy
 }
 
 impl SimulatorAgent {
@@ -163,7 +390,8 @@ impl SimulatorAgent {
 
         let replay_memory = ReplayMemory::new(mem_size, min_mem_size);
         let mut memory_policy = TensorStorage::default();
-        let policy_network = Policy::new(
+        let policy_network = Self::build_network(
+            PolicyArchitecture::Standard,
             &mut memory_policy,
             number_of_features,
             number_of_actions,
@@ -171,24 +399,42 @@ impl SimulatorAgent {
             activation,
         );
         let mut memory_target = TensorStorage::default();
-        let target_network = Policy::new(
+        let target_network = Self::build_network(
+            PolicyArchitecture::Standard,
             &mut memory_target,
             number_of_features,
             number_of_actions,
-            hidden_sizes,
+            hidden_sizes.clone(),
             activation,
         );
         memory_target.copy(&memory_policy);
 
         Self {
-            events_history: Vec::new(),
+            events_history: VecDeque::new(),
+            event_history_capacity: DEFAULT_EVENT_HISTORY_CAPACITY,
             track: true,
             cumulative_reward: 0.0,
             gamma,
             update_freq,
             learning_rate,
+            learning_rate_schedule: LearningRateSchedule::Constant,
+            optimizer_steps: 0,
             sample_batch_size,
+            accumulate_steps: 1,
             stage: SimulatorAgentStage::DataCollection,
+            exploration: ExplorationStrategy::EpsilonGreedy,
+            data_collection: DataCollectionStrategy::Uniform,
+            exact_online_analysis: false,
+            increase_counts: HashMap::new(),
+            gradient_clip: GradientClip::Value(1.0),
+            loss: LossConfig::L1,
+            reward_weights: RewardWeights::default(),
+            last_activation_time: 0,
+            last_busy_time: 0,
+            last_releases: 0,
+            last_deadline_misses: 0,
+            hidden_sizes,
+            activation,
             policy_network,
             target_network,
             replay_memory,
@@ -196,17 +442,34 @@ impl SimulatorAgent {
             memory_target,
             epsilon: 1.0,
             reward_history: Vec::new(),
+            action_history: Vec::new(),
             buffered_action: None,
             buffered_state: None,
+            on_action: None,
+            metrics_sink: None,
             mode_changes_to_hmode: 0,
             mode_changes_to_lmode: 0,
             task_kills: 0,
             task_starts: 0,
             last_processed_event_index: 0,
+            events_pushed: 0,
+            observation_window: usize::MAX,
             number_of_features,
             _number_of_actions: number_of_actions,
             number_of_tasks: task_set.len(),
+            task_ids: task_set.iter().map(|t| t.task.props().id).collect(),
+            actionable_tasks: task_set.iter().map(|t| t.task.props().id).collect(),
+            action_shape: ActionShape::default(),
+            action_magnitudes: ActionMagnitudes::default(),
+            architecture: PolicyArchitecture::Standard,
             exec_times: HashMap::new(),
+            state_encoder: Box::new(DefaultEncoder::new(task_set.len())),
+            ensemble: vec![],
+            ddpg: None,
+            max_scaling: 0.0,
+            buffered_factors: vec![],
+            #[cfg(test)]
+            fixed_action: None,
         }
     }
 
@@ -214,6 +477,12 @@ impl SimulatorAgent {
         self.cumulative_reward
     }
 
+    /// Index of the action chosen at each activation since training (or the placebo
+    /// mode) started. Doing nothing is the last index.
+    pub fn action_history(&self) -> &[usize] {
+        &self.action_history
+    }
+
     pub fn task_kills(&self) -> usize {
         self.task_kills
     }
@@ -230,21 +499,366 @@ impl SimulatorAgent {
         self.mode_changes_to_lmode
     }
 
+    /// Probability of taking a random action under epsilon-greedy exploration.
+    pub fn epsilon(&self) -> f32 {
+        self.epsilon
+    }
+
+    pub fn stage(&self) -> SimulatorAgentStage {
+        self.stage
+    }
+
+    pub fn replay_memory_size(&self) -> usize {
+        self.replay_memory.transitions.len()
+    }
+
+    pub fn events_history_len(&self) -> usize {
+        self.events_history.len()
+    }
+
+    /// Transitions collected so far, which can be used to seed the replay
+    /// memory of other agents with `seed_memory_from`.
+    pub fn transitions(&self) -> Vec<Transition> {
+        self.replay_memory.transitions()
+    }
+
+    /// Pre-loads the replay memory with transitions collected elsewhere (e.g. from
+    /// a random policy run), so that training can begin without waiting for the
+    /// data collection stage to fill it.
+    pub fn seed_memory_from(&mut self, transitions: Vec<Transition>) {
+        for transition in transitions {
+            match self.stage {
+                SimulatorAgentStage::DataCollection => {
+                    if self.replay_memory.add_initial(transition) {
+                        self.stage = SimulatorAgentStage::Training;
+                    }
+                }
+                _ => self.replay_memory.add(transition),
+            }
+        }
+    }
+
+    /// Makes the batches sampled from the replay memory reproducible.
+    pub fn set_memory_seed(&mut self, seed: u64) {
+        self.replay_memory.reseed(seed);
+        if let Some(ddpg) = &mut self.ddpg {
+            ddpg.reseed(seed);
+        }
+    }
+
+    /// Stores the replay memory in a file, to resume training later with `load_memory`.
+    pub fn save_memory<P: AsRef<Path>>(&self, path: P) -> Result<(), TchError> {
+        self.replay_memory.save(path)
+    }
+
+    /// Resumes from a replay memory stored with `save_memory`. If it holds enough
+    /// transitions, the agent moves on to the training stage right away.
+    pub fn load_memory<P: AsRef<Path>>(&mut self, path: P) -> Result<(), TchError> {
+        let memory = ReplayMemory::load(
+            path,
+            self.replay_memory.capacity,
+            self.replay_memory.min_size,
+        )?;
+        self.seed_memory_from(memory.transitions());
+        Ok(())
+    }
+
+    /// Whether the reward has plateaued, i.e. the moving average over the last
+    /// `window` updates improved less than `epsilon` over the previous `window`.
+    pub fn has_converged(&self, window: usize, epsilon: f32) -> bool {
+        if window == 0 || self.reward_history.len() < 2 * window {
+            return false;
+        }
+
+        let average = |rewards: &[f32]| rewards.iter().sum::<f32>() / rewards.len() as f32;
+        let last = &self.reward_history[self.reward_history.len() - window..];
+        let previous = &self.reward_history
+            [self.reward_history.len() - 2 * window..self.reward_history.len() - window];
+        (average(last) - average(previous)).abs() < epsilon
+    }
+
+    /// Exponentially weighted moving average of the rewards since training (or the
+    /// placebo mode) started, seeded with the first reward. A higher `alpha`
+    /// discounts older rewards faster. Zero if no reward was given yet.
+    pub fn recent_reward(&self, alpha: f64) -> f64 {
+        assert!((0.0..=1.0).contains(&alpha), "Alpha must be in [0, 1].");
+        let mut rewards = self.reward_history.iter().map(|r| *r as f64);
+        let first = rewards.next().unwrap_or(0.0);
+        rewards.fold(first, |average, reward| {
+            alpha * reward + (1.0 - alpha) * average
+        })
+    }
+
     pub fn push_exec_time(&mut self, task_id: TaskId, exec_time: TimeUnit) {
-        self.exec_times.insert(task_id, exec_time);
+        self.exec_times
+            .insert(task_id, (exec_time, self.events_pushed));
     }
 
     pub fn push_event(&mut self, event: SimulatorEvent) {
+        self.events_pushed += 1;
         if matches!(event, SimulatorEvent::End(_, _, _)) {
             // We don't need to track end events.
             return;
         }
 
-        if self.events_history.len() > MAX_EVENTS_STORED - 1 {
-            self.events_history.remove(0);
+        if self.events_history.len() >= self.event_history_capacity {
+            self.events_history.pop_front();
+            self.last_processed_event_index = self.last_processed_event_index.saturating_sub(1);
+        }
+        self.events_history.push_back(event);
+    }
+
+    /// Changes how many events the agent remembers, independently of the replay memory.
+    /// Events older than the last activation may be dropped and not rewarded.
+    pub fn set_event_history_capacity(&mut self, capacity: usize) {
+        assert!(capacity > 0, "The event history capacity must be positive.");
+        self.event_history_capacity = capacity;
+        while self.events_history.len() > capacity {
+            self.events_history.pop_front();
             self.last_processed_event_index = self.last_processed_event_index.saturating_sub(1);
         }
-        self.events_history.push(event);
+    }
+
+    /// Only the last `observation_window` events influence the state given to the
+    /// network; older execution times are seen as unknown. This is independent of
+    /// how many events are kept to compute rewards.
+    pub fn set_observation_window(&mut self, observation_window: usize) {
+        self.observation_window = observation_window;
+    }
+
+    /// Replaces how the state given to the networks is built. The networks are rebuilt
+    /// to take the features of the encoder, discarding what they learned.
+    pub fn set_state_encoder(&mut self, state_encoder: Box<dyn StateEncoder>) {
+        self.number_of_features = state_encoder.num_features();
+        self.state_encoder = state_encoder;
+        self.rebuild_networks();
+    }
+
+    /// Installs a hook called whenever the simulator applies or reverts an action of the agent.
+    pub fn set_on_action(
+        &mut self,
+        on_action: impl FnMut(&SimulatorAction, ActionOutcome) + 'static,
+    ) {
+        self.on_action = Some(Box::new(on_action));
+    }
+
+    /// Installs a sink called after each training step, e.g. to feed a live dashboard.
+    pub fn set_metrics_sink(&mut self, metrics_sink: MetricsSink) {
+        self.metrics_sink = Some(metrics_sink);
+    }
+
+    pub fn notify_action(&mut self, action: &SimulatorAction, outcome: ActionOutcome) {
+        if let Some(on_action) = &mut self.on_action {
+            on_action(action, outcome);
+        }
+    }
+
+    pub fn set_exploration(&mut self, exploration: ExplorationStrategy) {
+        self.exploration = exploration;
+    }
+
+    /// Whether actions are validated with the full response time analysis, rather than
+    /// the faster approximation that reuses the response times cached by the simulator.
+    pub fn set_exact_online_analysis(&mut self, exact_online_analysis: bool) {
+        self.exact_online_analysis = exact_online_analysis;
+    }
+
+    pub fn feasible_online(
+        &self,
+        tasks: &[Rc<RefCell<SimulatorTask>>],
+        simulator: &Simulator,
+    ) -> bool {
+        if self.exact_online_analysis {
+            feasible_schedule_online_exact(tasks)
+        } else {
+            feasible_schedule_online(tasks, &simulator.cached_response_times)
+        }
+    }
+
+    pub fn set_data_collection_strategy(&mut self, data_collection: DataCollectionStrategy) {
+        self.data_collection = data_collection;
+    }
+
+    /// Accumulates the gradients of this many sampled batches before each optimizer step,
+    /// which amounts to a batch that many times larger, without holding it all at once.
+    pub fn set_accumulate_steps(&mut self, accumulate_steps: usize) {
+        assert!(
+            accumulate_steps > 0,
+            "At least one batch is needed per step."
+        );
+        self.accumulate_steps = accumulate_steps;
+    }
+
+    pub fn set_gradient_clip(&mut self, gradient_clip: GradientClip) {
+        self.gradient_clip = gradient_clip;
+    }
+
+    pub fn set_reward_weights(&mut self, reward_weights: RewardWeights) {
+        self.reward_weights = reward_weights;
+    }
+
+    pub fn set_learning_rate_schedule(&mut self, schedule: LearningRateSchedule) {
+        self.learning_rate_schedule = schedule;
+    }
+
+    /// The learning rate to be used in the next optimizer step.
+    pub fn current_learning_rate(&self) -> f32 {
+        self.learning_rate_schedule
+            .rate(self.learning_rate, self.optimizer_steps)
+    }
+
+    pub fn set_loss(&mut self, loss: LossConfig) {
+        self.loss = loss;
+    }
+
+    /// Rebuilds the policy and target networks with the given architecture.
+    /// Must be called before training, since the learned weights are discarded.
+    pub fn set_architecture(&mut self, architecture: PolicyArchitecture) {
+        self.architecture = architecture;
+        self.rebuild_networks();
+    }
+
+    /// Acts with `size` independently initialized networks, the policy network among them:
+    /// each proposes an action, and the most proposed one is taken. The members learn from
+    /// the same batches and target network, differing only by their initialization.
+    /// Must be called before training, since the learned weights are discarded.
+    pub fn set_ensemble_size(&mut self, size: usize) {
+        assert!(size > 0, "The ensemble must have at least one member.");
+        self.ensemble = (1..size)
+            .map(|_| {
+                let mut storage = TensorStorage::default();
+                let network = Self::build_network(
+                    self.architecture,
+                    &mut storage,
+                    self.number_of_features,
+                    self._number_of_actions,
+                    self.hidden_sizes.clone(),
+                    self.activation,
+                );
+                (storage, network)
+            })
+            .collect();
+    }
+
+    /// Restricts the actions to WCET changes of the given tasks, shrinking the action space.
+    /// Rebuilds the networks, so it must be called before training.
+    pub fn set_actionable_tasks(&mut self, actionable_tasks: Vec<TaskId>) {
+        assert!(
+            actionable_tasks.iter().all(|id| self.task_ids.contains(id)),
+            "Actionable tasks must belong to the task set."
+        );
+        self.actionable_tasks = actionable_tasks;
+        self.update_number_of_actions();
+    }
+
+    /// Changes how many WCET increases and decreases make up each action.
+    /// Rebuilds the networks, so it must be called before training.
+    pub fn set_action_shape(&mut self, action_shape: ActionShape) {
+        assert!(
+            action_shape.increases + action_shape.decreases > 0,
+            "Actions must have at least one part."
+        );
+        self.action_shape = action_shape;
+        self.update_number_of_actions();
+    }
+
+    /// Changes the fractions of `wcet_h` by which actions change the budgets.
+    pub fn set_action_magnitudes(&mut self, action_magnitudes: ActionMagnitudes) {
+        assert!(
+            action_magnitudes.increase_factor > 0.0 && action_magnitudes.decrease_factor > 0.0,
+            "Action magnitudes must be positive."
+        );
+        self.action_magnitudes = action_magnitudes;
+    }
+
+    /// Scales the L-mode budgets of all tasks with a DDPG policy instead of taking discrete
+    /// actions, each by at most `max_change` of its H-mode budget per activation. Epsilon
+    /// is the amplitude of the exploration noise. Must be called before training.
+    pub fn set_continuous_actions(&mut self, max_change: f32) {
+        assert!(max_change > 0.0, "The maximum change must be positive.");
+        self.ddpg = Some(Ddpg::new(
+            self.number_of_features,
+            self.number_of_tasks,
+            self.hidden_sizes.clone(),
+            self.activation,
+            self.replay_memory.capacity,
+            self.gamma,
+            self.learning_rate,
+            self.update_freq,
+        ));
+        self.max_scaling = max_change;
+    }
+
+    pub fn action_magnitudes(&self) -> ActionMagnitudes {
+        self.action_magnitudes
+    }
+
+    fn update_number_of_actions(&mut self) {
+        self._number_of_actions = Self::generate_actions(
+            self.actionable_tasks
+                .iter()
+                .map(|id| TaskProps::new_empty(*id))
+                .collect::<Vec<_>>()
+                .as_slice(),
+            self.action_shape,
+        )
+        .len()
+            + 1;
+        self.rebuild_networks();
+    }
+
+    fn rebuild_networks(&mut self) {
+        let mut memory_policy = TensorStorage::default();
+        self.policy_network = Self::build_network(
+            self.architecture,
+            &mut memory_policy,
+            self.number_of_features,
+            self._number_of_actions,
+            self.hidden_sizes.clone(),
+            self.activation,
+        );
+        let mut memory_target = TensorStorage::default();
+        self.target_network = Self::build_network(
+            self.architecture,
+            &mut memory_target,
+            self.number_of_features,
+            self._number_of_actions,
+            self.hidden_sizes.clone(),
+            self.activation,
+        );
+        memory_target.copy(&memory_policy);
+        self.memory_policy = memory_policy;
+        self.memory_target = memory_target;
+        self.set_ensemble_size(self.ensemble.len() + 1);
+        if self.ddpg.is_some() {
+            self.set_continuous_actions(self.max_scaling);
+        }
+    }
+
+    fn build_network(
+        architecture: PolicyArchitecture,
+        storage: &mut TensorStorage,
+        number_of_features: usize,
+        number_of_actions: usize,
+        hidden_sizes: Vec<usize>,
+        activation: dqn::ActivationFunction,
+    ) -> Box<dyn ComputeModel> {
+        match architecture {
+            PolicyArchitecture::Standard => Box::new(Policy::new(
+                storage,
+                number_of_features,
+                number_of_actions,
+                hidden_sizes,
+                activation,
+            )),
+            PolicyArchitecture::Dueling => Box::new(DuelingPolicy::new(
+                storage,
+                number_of_features,
+                number_of_actions,
+                hidden_sizes,
+                activation,
+            )),
+        }
     }
 
     pub fn skip_tracking(&mut self) {
@@ -259,19 +873,28 @@ impl SimulatorAgent {
 
         // Get a new action from the policy.
         // This will be applied by the simulator once the agent's task is finished.
+        let stratify = self.stage == SimulatorAgentStage::DataCollection
+            && self.data_collection == DataCollectionStrategy::Stratified;
         let raw_action = match self.stage {
-            SimulatorAgentStage::Placebo => None,
-            _ => self.epsilon_greedy(
+            SimulatorAgentStage::Placebo | SimulatorAgentStage::Monitor => None,
+            #[cfg(test)]
+            _ if self.fixed_action.is_some() => self.fixed_action.clone(),
+            _ if self.ddpg.is_some() => {
+                self.scale_budgets(&state, simulator);
+                None
+            }
+            _ if stratify => self.stratified_simulator_action(simulator),
+            _ if !self.ensemble.is_empty() => self.ensemble_action(&state, simulator),
+            _ => self.explore(
                 &self.memory_policy,
-                &self.policy_network,
-                self.epsilon,
+                &*self.policy_network,
                 &state,
                 simulator,
             ),
         };
-        let action_parts =
-            raw_action.map_or(vec![SimulatorActionPart::None], |(a, b, c)| vec![a, b, c]);
-        simulator.set_pending_agent_action(raw_action);
+        self.action_history
+            .push(self.action_to_index(raw_action.as_ref(), simulator));
+        simulator.set_pending_agent_action(raw_action.clone());
         //println!("Got action: {:?}", raw_action);
 
         // Track events.
@@ -301,23 +924,45 @@ impl SimulatorAgent {
                 .filter(|e| matches!(e, SimulatorEvent::Start(_, _)))
                 .count();
         }
-        let reward = self
-            .events_history
-            .iter()
-            .skip(self.last_processed_event_index)
-            .map(|e| Self::event_to_reward(e, simulator))
-            .sum::<f64>();
+        let reward = self.events_reward(
+            self.events_history.range(self.last_processed_event_index..),
+            self.busy_fraction_since_last_activation(simulator),
+            self.deadline_miss_ratio_since_last_activation(simulator),
+        );
+        self.last_activation_time = simulator.now();
+        self.last_busy_time = simulator.busy_time();
+        self.last_releases = simulator.releases();
+        self.last_deadline_misses = simulator.deadline_misses();
         self.cumulative_reward += reward;
         //println!("Reward: {}", reward);
         println!("Cumulative reward: {}", self.cumulative_reward);
         self.reward_history.push(reward as f32);
         self.last_processed_event_index = self.events_history.len();
 
-        if let Some(buffered_action) = &self.buffered_action {
+        let scaling = !matches!(
+            self.stage,
+            SimulatorAgentStage::Placebo | SimulatorAgentStage::Monitor
+        );
+        if let Some(ddpg) = self.ddpg.as_mut().filter(|_| scaling) {
+            if let Some(buffered_state) = &self.buffered_state {
+                ddpg.push(
+                    buffered_state,
+                    &self.buffered_factors,
+                    reward as f32,
+                    &state,
+                );
+            }
+            self.buffered_state = Some(state);
+            ddpg.train_step(self.sample_batch_size);
+            return;
+        }
+
+        if let Some(buffered_state) = &self.buffered_state {
             // We had taken an action previously, and are now receiving the reward.
+            // Doing nothing is an action too, so that its value is also learned.
             let transition = Transition::new(
-                self.buffered_state.as_ref().unwrap(),
-                self.action_to_index(Some(buffered_action), simulator) as i64,
+                buffered_state,
+                self.action_to_index(self.buffered_action.as_ref(), simulator) as i64,
                 reward as f32,
                 &state,
             );
@@ -330,7 +975,7 @@ impl SimulatorAgent {
                         self.stage = SimulatorAgentStage::Training;
                     }
                 }
-                SimulatorAgentStage::Training => {
+                SimulatorAgentStage::Training | SimulatorAgentStage::Monitor => {
                     self.replay_memory.add(transition);
                 }
                 _ => {}
@@ -338,35 +983,21 @@ impl SimulatorAgent {
         }
 
         // Store this action and state to generate a transition later.
-        self.buffered_action = if action_parts == vec![SimulatorActionPart::None] {
-            None
-        } else {
-            Some((action_parts[0], action_parts[1], action_parts[2]))
-        };
+        self.buffered_action = raw_action;
         self.buffered_state = Some(state);
 
         // If we are not training, do nothing else.
-        if self.stage != SimulatorAgentStage::Training {
+        let monitoring = self.stage == SimulatorAgentStage::Monitor
+            && self.replay_memory.transitions.len() >= self.replay_memory.min_size;
+        if self.stage != SimulatorAgentStage::Training && !monitoring {
             //println!("Not training. Skipping NN activity.");
             return;
         }
 
         // println!("Training.");
-
-        let (b_state, b_action, b_reward, b_state_) =
-            self.replay_memory.sample_batch(self.sample_batch_size);
-        let qvalues = self
-            .policy_network
-            .forward(&self.memory_policy, &b_state)
-            .gather(1, &b_action, false);
-        let target_values: Tensor =
-            tch::no_grad(|| self.target_network.forward(&self.memory_target, &b_state_));
-        let max_target_values = target_values.max_dim(1, true).0;
-        let expected_values = b_reward + self.gamma * (&max_target_values);
-
-        let loss = mean_squared_error(&qvalues, &expected_values);
-        loss.backward();
-        self.memory_policy.apply_grads_adam(self.learning_rate);
+        let Some(loss) = self.optimize(monitoring) else {
+            return;
+        };
 
         // We update the target network every `update_freq` steps.
         // This allows for a more stable learning process.
@@ -377,12 +1008,120 @@ impl SimulatorAgent {
             self.epsilon = (self.epsilon * 0.95).max(0.3);
             //  println!("Updated epsilon: {}", self.epsilon);
         }
+
+        if let Some(metrics_sink) = &mut self.metrics_sink {
+            metrics_sink(&TrainingMetrics {
+                step: self.optimizer_steps,
+                loss,
+                epsilon: self.epsilon,
+                cumulative_reward: self.cumulative_reward,
+                replay_size: self.replay_memory.transitions.len(),
+            });
+        }
+    }
+
+    /// Scales the budgets by the factors of the DDPG policy, restoring them if the
+    /// schedule becomes unfeasible. The factors are kept to learn from later.
+    fn scale_budgets(&mut self, state: &Tensor, simulator: &mut Simulator) {
+        let ddpg = self.ddpg.as_mut().unwrap();
+        let factors = ddpg.act(state, self.epsilon);
+        let task_ids = simulator
+            .tasks
+            .iter()
+            .map(|t| t.borrow().task.props().id)
+            .collect::<Vec<_>>();
+        let budgets = simulator
+            .tasks
+            .iter()
+            .map(|t| t.borrow().task.props().wcet_l)
+            .collect::<Vec<_>>();
+        apply_scaling(&mut simulator.tasks, &task_ids, &factors, self.max_scaling);
+        if !self.feasible_online(&simulator.tasks, simulator) {
+            for (task, wcet_l) in simulator.tasks.iter().zip(budgets) {
+                task.borrow_mut().task.props_mut().wcet_l = wcet_l;
+            }
+        }
+        self.buffered_factors = factors;
+    }
+
+    /// Takes an optimizer step with the gradients of `accumulate_steps` batches sampled
+    /// from the replay memory. Returns the loss, or `None` if the memory had nothing to sample.
+    fn optimize(&mut self, monitoring: bool) -> Option<f64> {
+        let mut total_loss = 0.0;
+        for _ in 0..self.accumulate_steps {
+            let (b_state, b_action, b_reward, b_state_) =
+                self.replay_memory.sample_batch(self.sample_batch_size)?;
+            let qvalues = self
+                .policy_network
+                .forward(&self.memory_policy, &b_state)
+                .gather(1, &b_action, false);
+            let target_values: Tensor =
+                tch::no_grad(|| self.target_network.forward(&self.memory_target, &b_state_));
+            let max_target_values = if monitoring {
+                // The observed policy always does nothing, the last action.
+                target_values.narrow(1, self._number_of_actions as i64 - 1, 1)
+            } else {
+                target_values.max_dim(1, true).0
+            };
+            let expected_values = b_reward + self.gamma * (&max_target_values);
+
+            // Gradients add up until the step, so each batch weighs as a share of it.
+            let loss = self.loss.compute(&qvalues, &expected_values) / self.accumulate_steps as f64;
+            loss.backward();
+            total_loss += loss.double_value(&[]);
+            for (storage, network) in &self.ensemble {
+                let qvalues = network
+                    .forward(storage, &b_state)
+                    .gather(1, &b_action, false);
+                (self.loss.compute(&qvalues, &expected_values) / self.accumulate_steps as f64)
+                    .backward();
+            }
+        }
+        let learning_rate = self.current_learning_rate();
+        self.memory_policy
+            .apply_grads_adam(learning_rate, self.gradient_clip);
+        for (storage, _) in &mut self.ensemble {
+            storage.apply_grads_adam(learning_rate, self.gradient_clip);
+        }
+        self.optimizer_steps += 1;
+        Some(total_loss)
+    }
+
+    /// Supervised warm start of the policy before reinforcement learning: fits the policy
+    /// network to the actions picked by an expert (e.g. a heuristic) in the given states,
+    /// with a cross-entropy loss. Actions are given by their index, doing nothing being
+    /// the last one. Returns the accuracy on the given pairs after training.
+    pub fn pretrain(
+        &mut self,
+        states: &[Tensor],
+        expert_actions: &[usize],
+        epochs: usize,
+        learning_rate: f32,
+    ) -> f64 {
+        assert_eq!(states.len(), expert_actions.len());
+        assert!(expert_actions.iter().all(|a| *a < self._number_of_actions));
+        let states = Tensor::stack(states, 0);
+        let targets =
+            Tensor::from_slice(&expert_actions.iter().map(|a| *a as i64).collect::<Vec<_>>());
+
+        for _ in 0..epochs {
+            let logits = self.policy_network.forward(&self.memory_policy, &states);
+            let loss = cross_entropy(&targets, &logits);
+            loss.backward();
+            self.memory_policy
+                .apply_grads_adam(learning_rate, self.gradient_clip);
+        }
+        self.memory_target.copy(&self.memory_policy);
+
+        let logits = tch::no_grad(|| self.policy_network.forward(&self.memory_policy, &states));
+        accuracy(&targets, &logits)
     }
 
     pub fn quit_training(&mut self) {
         self.stage = SimulatorAgentStage::Reactive;
         self.cumulative_reward = 0.0;
         self.reward_history.clear();
+        self.action_history.clear();
         self.task_kills = 0;
         self.task_starts = 0;
         self.mode_changes_to_hmode = 0;
@@ -390,12 +1129,20 @@ impl SimulatorAgent {
         self.events_history.clear();
         self.last_processed_event_index = 0;
         self.buffered_action = None;
+        self.buffered_state = None;
+    }
+
+    /// Observes and learns without ever acting; see `SimulatorAgentStage::Monitor`.
+    /// Unlike `placebo_mode`, meant to be set before the agent starts.
+    pub fn monitor_mode(&mut self) {
+        self.stage = SimulatorAgentStage::Monitor;
     }
 
     pub fn placebo_mode(&mut self) {
         self.stage = SimulatorAgentStage::Placebo;
         self.cumulative_reward = 0.0;
         self.reward_history.clear();
+        self.action_history.clear();
         self.task_kills = 0;
         self.task_starts = 0;
         self.mode_changes_to_hmode = 0;
@@ -403,55 +1150,141 @@ impl SimulatorAgent {
         self.events_history.clear();
         self.last_processed_event_index = 0;
         self.buffered_action = None;
+        self.buffered_state = None;
     }
 
-    pub fn event_to_reward(event: &SimulatorEvent, _simulator: &Simulator) -> f64 {
+    pub fn event_to_reward(&self, event: &SimulatorEvent) -> f64 {
         match event {
-            SimulatorEvent::Start(_, _) => 0.1,
-            SimulatorEvent::TaskKill(_, _) => -1.0,
-            SimulatorEvent::ModeChange(SimulatorMode::HMode, _) => -2.0,
+            SimulatorEvent::Start(_, _) => self.reward_weights.task_start,
+            SimulatorEvent::TaskKill(_, _) => self.reward_weights.kill,
+            SimulatorEvent::ModeChange(SimulatorMode::HMode, _) => self.reward_weights.hmode_change,
+            SimulatorEvent::ModeChange(SimulatorMode::LMode, _) => self.reward_weights.lmode_change,
+            SimulatorEvent::DeadlineMiss(_, _) => self.reward_weights.deadline_miss,
             _ => 0.0,
         }
     }
 
+    fn events_reward<'a>(
+        &self,
+        events: impl IntoIterator<Item = &'a SimulatorEvent>,
+        busy_fraction: f64,
+        deadline_miss_ratio: f64,
+    ) -> f64 {
+        let events = events.into_iter().collect::<Vec<_>>();
+        (0..events.len())
+            .map(|i| self.event_to_reward(events[i]) * self.recovery_discount(&events[i..]))
+            .sum::<f64>()
+            + self.reward_weights.energy * busy_fraction
+            + self.reward_weights.deadline_miss_ratio * deadline_miss_ratio
+    }
+
+    /// Share of the penalty of the first event given the ones that follow it:
+    /// a change to H-mode is discounted if the return to L-mode comes quickly.
+    fn recovery_discount(&self, events: &[&SimulatorEvent]) -> f64 {
+        let horizon = self.reward_weights.hmode_recovery_horizon;
+        let SimulatorEvent::ModeChange(SimulatorMode::HMode, start) = events[0] else {
+            return 1.0;
+        };
+        if horizon == 0 {
+            return 1.0;
+        }
+        events[1..]
+            .iter()
+            .find_map(|e| match e {
+                SimulatorEvent::ModeChange(SimulatorMode::LMode, end) => {
+                    Some(((end - start) as f64 / horizon as f64).min(1.0))
+                }
+                _ => None,
+            })
+            .unwrap_or(1.0)
+    }
+
+    fn deadline_miss_ratio_since_last_activation(&self, simulator: &Simulator) -> f64 {
+        // The simulator may have been reset since the last activation.
+        if simulator.releases() <= self.last_releases
+            || simulator.deadline_misses() < self.last_deadline_misses
+        {
+            return 0.0;
+        }
+        (simulator.deadline_misses() - self.last_deadline_misses) as f64
+            / (simulator.releases() - self.last_releases) as f64
+    }
+
+    fn busy_fraction_since_last_activation(&self, simulator: &Simulator) -> f64 {
+        // The simulator may have been reset since the last activation.
+        if simulator.now() <= self.last_activation_time
+            || simulator.busy_time() < self.last_busy_time
+        {
+            return 0.0;
+        }
+        (simulator.busy_time() - self.last_busy_time) as f64
+            / (simulator.now() - self.last_activation_time) as f64
+    }
+
     pub fn history_to_input(&self, simulator: &Simulator) -> Tensor {
-        let mut input = Vec::with_capacity(self.number_of_features);
-
-        for task in simulator.tasks.iter().take(self.number_of_tasks) {
-            let wcet_l = task.borrow().task.props().wcet_l as f32;
-            let wcet_h = task.borrow().task.props().wcet_h as f32;
-            let bcet = task.borrow().bcet as f32;
-            let last_job_execution_time =
-                if let Some(diff_time) = self.exec_times.get(&task.borrow().task.props().id) {
-                    *diff_time as f32
-                } else {
-                    -1.0
-                };
+        self.state_encoder.encode(self, simulator)
+    }
 
-            // Push normalized values.
-            input.push((wcet_l - bcet) / (wcet_h - bcet));
-            input.push((last_job_execution_time - bcet) / (wcet_h - bcet));
+    /// Execution time of the last job of the task, if it ended within the observation window.
+    pub fn last_execution_time(&self, task_id: TaskId) -> Option<TimeUnit> {
+        match self.exec_times.get(&task_id) {
+            Some((exec_time, pushed_at))
+                if self.events_pushed - pushed_at < self.observation_window =>
+            {
+                Some(*exec_time)
+            }
+            _ => None,
         }
+    }
 
-        Tensor::from_slice(input.as_slice())
+    /// The states of the given simulators, stacked in a `[N, features]` tensor.
+    pub fn histories_to_input(&self, simulators: &[&Simulator]) -> Tensor {
+        let states = simulators
+            .iter()
+            .map(|simulator| self.history_to_input(simulator))
+            .collect::<Vec<_>>();
+        Tensor::stack(&states, 0)
     }
 
     pub fn sample_simulator_action(&self, simulator: &Simulator) -> Option<SimulatorAction> {
-        let actions = Self::generate_actions(
-            simulator
-                .tasks
-                .iter()
-                .take(self.number_of_tasks)
-                .map(|t| t.borrow().task.props())
-                .collect::<Vec<_>>()
-                .as_slice(),
-        );
+        let actions = Self::generate_actions(&self.actionable_props(simulator), self.action_shape);
         let mut rng = rand::thread_rng();
         let action_index = rng.gen_range(0..actions.len() + 1);
-        if action_index == actions.len() {
-            return None;
+        actions.into_iter().nth(action_index)
+    }
+
+    pub fn stratified_simulator_action(
+        &mut self,
+        simulator: &Simulator,
+    ) -> Option<SimulatorAction> {
+        let mut strata: HashMap<Option<TaskId>, Vec<usize>> = HashMap::new();
+        for index in self.valid_actions(simulator) {
+            // Doing nothing, or an action without increases, falls in the `None` stratum.
+            let increased_task = self.index_to_action(index, simulator).and_then(|action| {
+                action.iter().find_map(|part| match part {
+                    SimulatorActionPart::WcetIncrease(id) => Some(*id),
+                    _ => None,
+                })
+            });
+            strata.entry(increased_task).or_default().push(index);
         }
-        Some(actions[action_index])
+
+        let mut rng = rand::thread_rng();
+        let fewest_picks = strata
+            .keys()
+            .map(|task| self.increase_counts.get(task).copied().unwrap_or(0))
+            .min()
+            .unwrap();
+        let candidates = strata
+            .iter()
+            .filter(|(task, _)| {
+                self.increase_counts.get(task).copied().unwrap_or(0) == fewest_picks
+            })
+            .collect::<Vec<_>>();
+        let (task, indices) = candidates[rng.gen_range(0..candidates.len())];
+
+        *self.increase_counts.entry(*task).or_insert(0) += 1;
+        self.index_to_action(indices[rng.gen_range(0..indices.len())], simulator)
     }
 
     pub fn epsilon_greedy(
@@ -462,103 +1295,315 @@ impl SimulatorAgent {
         environment: &Tensor,
         simulator: &Simulator,
     ) -> Option<SimulatorAction> {
+        let valid_actions = self.valid_actions(simulator);
         let mut rng = rand::thread_rng();
         let random_number: f32 = rng.gen::<f32>();
         if random_number > epsilon || self.stage == SimulatorAgentStage::Reactive {
             // println!("Using policy.");
             let value = tch::no_grad(|| policy.forward(storage, environment));
-            let action_index = value.argmax(1, false).int64_value(&[]) as usize;
-            self.index_to_action(action_index, simulator)
+            let mut q_values = Self::q_values(&value);
+            Self::mask_invalid_actions(&mut q_values, &valid_actions);
+            self.index_to_action(Self::argmax(&q_values), simulator)
         } else {
             // println!("Using random action.");
-            self.sample_simulator_action(simulator)
+            let action_index = valid_actions[rng.gen_range(0..valid_actions.len())];
+            self.index_to_action(action_index, simulator)
         }
     }
 
-    pub fn number_of_actions(tasks: &[SimulatorTask]) -> usize {
-        if tasks.len() < 3 {
-            return 1; // Only the None action is available.
-        }
+    /// Action of the network under the configured exploration strategy.
+    fn explore(
+        &self,
+        storage: &TensorStorage,
+        policy: &dyn ComputeModel,
+        state: &Tensor,
+        simulator: &Simulator,
+    ) -> Option<SimulatorAction> {
+        match self.exploration {
+            ExplorationStrategy::EpsilonGreedy => {
+                self.epsilon_greedy(storage, policy, self.epsilon, state, simulator)
+            }
+            ExplorationStrategy::Softmax { temperature } => {
+                self.softmax_exploration(storage, policy, temperature, state, simulator)
+            }
+        }
+    }
+
+    /// Each member of the ensemble proposes an action with the configured exploration
+    /// strategy, which only picks valid actions, so only those get votes. The most voted
+    /// one is taken, ties broken by the highest Q-value averaged over the members.
+    fn ensemble_action(&self, state: &Tensor, simulator: &Simulator) -> Option<SimulatorAction> {
+        let members = std::iter::once((&self.memory_policy, &*self.policy_network))
+            .chain(
+                self.ensemble
+                    .iter()
+                    .map(|(storage, network)| (storage, &**network)),
+            )
+            .collect::<Vec<_>>();
+        let mut votes = vec![0; self._number_of_actions];
+        let mut average_q_values = vec![0.0; self._number_of_actions];
+        for (storage, network) in &members {
+            let action = self.explore(storage, *network, state, simulator);
+            votes[self.action_to_index(action.as_ref(), simulator)] += 1;
+            let value = tch::no_grad(|| network.forward(storage, state));
+            for (average, q_value) in average_q_values.iter_mut().zip(Self::q_values(&value)) {
+                *average += q_value / members.len() as f32;
+            }
+        }
+
+        let best = (0..votes.len())
+            .max_by(|&a, &b| {
+                votes[a]
+                    .cmp(&votes[b])
+                    .then(average_q_values[a].total_cmp(&average_q_values[b]))
+            })
+            .unwrap();
+        self.index_to_action(best, simulator)
+    }
+
+    pub fn softmax_exploration(
+        &self,
+        storage: &TensorStorage,
+        policy: &dyn ComputeModel,
+        temperature: f32,
+        environment: &Tensor,
+        simulator: &Simulator,
+    ) -> Option<SimulatorAction> {
+        let value = tch::no_grad(|| policy.forward(storage, environment));
+        let mut q_values = Self::q_values(&value);
+        Self::mask_invalid_actions(&mut q_values, &self.valid_actions(simulator));
+
+        let action_index = if self.stage == SimulatorAgentStage::Reactive {
+            Self::argmax(&q_values)
+        } else {
+            Self::softmax_sample(&q_values, temperature, &mut rand::thread_rng())
+        };
+        self.index_to_action(action_index, simulator)
+    }
+
+    /// Greedy action of the current policy for each of the given states,
+    /// to inspect what the agent learned across the state space.
+    pub fn policy_table(
+        &self,
+        states: &[Tensor],
+        simulator: &Simulator,
+    ) -> Vec<Option<SimulatorAction>> {
+        self.batch_q_values(states)
+            .iter()
+            .map(|q_values| self.index_to_action(Self::argmax(q_values), simulator))
+            .collect()
+    }
+
+    /// Q-values of the policy for each of the given states, evaluated in a single pass.
+    pub fn batch_q_values(&self, states: &[Tensor]) -> Vec<Vec<f32>> {
+        if states.is_empty() {
+            return vec![];
+        }
+        let values = tch::no_grad(|| {
+            self.policy_network
+                .forward_batch(&self.memory_policy, states)
+        });
+        (0..states.len() as i64)
+            .map(|i| Self::q_values(&values.get(i)))
+            .collect()
+    }
+
+    /// Copy of the task set of the simulator, with the action applied.
+    fn tasks_after(
+        &self,
+        action: &SimulatorAction,
+        simulator: &Simulator,
+    ) -> Vec<Rc<RefCell<SimulatorTask>>> {
+        let mut tasks = simulator
+            .tasks
+            .iter()
+            .map(|t| Rc::new(RefCell::new(t.borrow().clone())))
+            .collect::<Vec<_>>();
+        apply_action(action, &mut tasks, self.action_magnitudes);
+        tasks
+    }
+
+    /// Evaluates the action on a copy of the task set, leaving the simulator untouched.
+    pub fn evaluate_action(
+        &self,
+        action: &SimulatorAction,
+        simulator: &Simulator,
+    ) -> ActionEvaluation {
+        let tasks = self.tasks_after(action, simulator);
+        let feasible = self.feasible_online(&tasks, simulator);
+
+        let tasks = tasks.iter().map(|t| t.borrow().clone()).collect::<Vec<_>>();
+        let slacks = tasks
+            .iter()
+            .map(|task| {
+                let slack = response_time(task, &tasks, SimulatorMode::LMode)
+                    .ok()
+                    .map(|r| task.task.props().period as i64 - r as i64);
+                (task.task.props().id, slack)
+            })
+            .collect();
+        ActionEvaluation { feasible, slacks }
+    }
+
+    /// Returns the indexes of the actions that keep the task set feasible.
+    /// Doing nothing is always a valid action.
+    pub fn valid_actions(&self, simulator: &Simulator) -> Vec<usize> {
+        let actions = Self::generate_actions(&self.actionable_props(simulator), self.action_shape);
+
+        let mut valid_actions = actions
+            .iter()
+            .enumerate()
+            .filter(|(_, action)| {
+                // Try the action on a copy of the task set.
+                self.feasible_online(&self.tasks_after(action, simulator), simulator)
+            })
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        valid_actions.push(actions.len());
+        valid_actions
+    }
+
+    fn q_values(value: &Tensor) -> Vec<f32> {
+        Vec::<f32>::try_from(&value.flatten(0, -1)).unwrap()
+    }
+
+    fn mask_invalid_actions(q_values: &mut [f32], valid_actions: &[usize]) {
+        for (index, q_value) in q_values.iter_mut().enumerate() {
+            if !valid_actions.contains(&index) {
+                *q_value = f32::NEG_INFINITY;
+            }
+        }
+    }
+
+    fn argmax(q_values: &[f32]) -> usize {
+        q_values
+            .iter()
+            .enumerate()
+            .fold(0, |best, (index, q_value)| {
+                if *q_value > q_values[best] {
+                    index
+                } else {
+                    best
+                }
+            })
+    }
+
+    fn softmax_sample(q_values: &[f32], temperature: f32, rng: &mut impl Rng) -> usize {
+        // Subtract the maximum for numerical stability.
+        let max_q = q_values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        let weights = q_values
+            .iter()
+            .map(|q| ((q - max_q) / temperature).exp())
+            .collect::<Vec<_>>();
+
+        let mut threshold = rng.gen::<f32>() * weights.iter().sum::<f32>();
+        for (index, weight) in weights.iter().enumerate() {
+            if threshold < *weight {
+                return index;
+            }
+            threshold -= weight;
+        }
+        weights.len() - 1
+    }
+
+    /// Number of actions with the default shape, including doing nothing.
+    pub fn number_of_actions(tasks: &[SimulatorTask]) -> usize {
+        if tasks.len() < 3 {
+            return 1; // Only the None action is available.
+        }
         Self::generate_actions(
             tasks
                 .iter()
                 .map(|t| t.task.props())
                 .collect::<Vec<_>>()
                 .as_slice(),
+            ActionShape::default(),
         )
         .len()
             + 1
     }
 
+    /// A replay memory size that grows with the state and action spaces of the task set,
+    /// so that they are covered by enough transitions. Never smaller than `DEFAULT_MEM_SIZE`.
+    pub fn suggested_mem_size(number_of_actions: usize, number_of_features: usize) -> usize {
+        DEFAULT_MEM_SIZE.max(MEM_SIZE_PER_OUTPUT * (number_of_actions + number_of_features))
+    }
+
     pub fn number_of_features(tasks: &[SimulatorTask]) -> usize {
         // We'll place the tasks from task to bottom.
         // Each task has 2 features: WCET_L and last job execution time.
         tasks.len() * 2
     }
 
-    fn generate_actions(tasks: &[TaskProps]) -> Vec<SimulatorAction> {
-        // Actions are tiples (increase(i), decrease(j), decrease(k))
-        // where i, j, k are the ids of the tasks.
+    fn generate_actions(tasks: &[TaskProps], shape: ActionShape) -> Vec<SimulatorAction> {
+        // Actions are the increases of a set of tasks followed by the decreases of
+        // another set of tasks, disjoint from the first. Since the order inside each
+        // set does not matter, each one is enumerated once, sorted by id.
+        // The ids are sorted so that the enumeration does not depend on the task order.
+        let mut ids = tasks.iter().map(|prop| prop.id).collect::<Vec<_>>();
+        ids.sort_unstable();
         let mut actions = Vec::new();
 
-        for prop in tasks {
-            let increase_first = SimulatorActionPart::WcetIncrease(prop.id);
-            let mut decrease_pairs = vec![];
-
-            for second_prop in tasks {
-                if second_prop.id == prop.id {
-                    continue;
-                }
-                let decrease_second = SimulatorActionPart::WcetDecrease(second_prop.id);
-                for third_prop in tasks {
-                    if third_prop.id == prop.id || third_prop.id == second_prop.id {
-                        continue;
-                    }
-                    let decrease_third = SimulatorActionPart::WcetDecrease(third_prop.id);
-
-                    // Avoid duplicate actions.
-                    if decrease_pairs
+        for increased in Self::combinations(&ids, shape.increases) {
+            let remaining = ids
+                .iter()
+                .filter(|id| !increased.contains(id))
+                .copied()
+                .collect::<Vec<_>>();
+            for decreased in Self::combinations(&remaining, shape.decreases) {
+                actions.push(
+                    increased
                         .iter()
-                        .any(|(s, t)| *s == decrease_third && *t == decrease_second)
-                    {
-                        continue;
-                    }
-
-                    decrease_pairs.push((decrease_second, decrease_third));
-                    actions.push((increase_first, decrease_second, decrease_third));
-                }
+                        .map(|id| SimulatorActionPart::WcetIncrease(*id))
+                        .chain(
+                            decreased
+                                .iter()
+                                .map(|id| SimulatorActionPart::WcetDecrease(*id)),
+                        )
+                        .collect(),
+                );
             }
         }
 
         actions
     }
 
-    fn index_to_action(&self, index: usize, simulator: &Simulator) -> Option<SimulatorAction> {
-        let actions = Self::generate_actions(
-            simulator
-                .tasks
-                .iter()
-                .take(self.number_of_tasks)
-                .map(|t| t.borrow().task.props())
-                .collect::<Vec<_>>()
-                .as_slice(),
-        );
-        if index >= actions.len() {
-            return None;
+    /// The subsets of `size` ids, in lexicographic order.
+    fn combinations(ids: &[TaskId], size: usize) -> Vec<Vec<TaskId>> {
+        if size == 0 {
+            return vec![vec![]];
         }
-        Some(actions[index])
+        (0..ids.len())
+            .flat_map(|i| {
+                Self::combinations(&ids[i + 1..], size - 1)
+                    .into_iter()
+                    .map(move |mut rest| {
+                        rest.insert(0, ids[i]);
+                        rest
+                    })
+            })
+            .collect()
+    }
+
+    /// Properties of the actionable tasks, as seen by the simulator.
+    fn actionable_props(&self, simulator: &Simulator) -> Vec<TaskProps> {
+        simulator
+            .tasks
+            .iter()
+            .take(self.number_of_tasks)
+            .zip(&self.task_ids)
+            .filter(|(_, id)| self.actionable_tasks.contains(id))
+            .map(|(t, _)| t.borrow().task.props())
+            .collect()
+    }
+
+    fn index_to_action(&self, index: usize, simulator: &Simulator) -> Option<SimulatorAction> {
+        let actions = Self::generate_actions(&self.actionable_props(simulator), self.action_shape);
+        actions.into_iter().nth(index)
     }
 
     fn action_to_index(&self, action: Option<&SimulatorAction>, simulator: &Simulator) -> usize {
-        let actions = Self::generate_actions(
-            simulator
-                .tasks
-                .iter()
-                .take(self.number_of_tasks)
-                .map(|t| t.borrow().task.props())
-                .collect::<Vec<_>>()
-                .as_slice(),
-        );
+        let actions = Self::generate_actions(&self.actionable_props(simulator), self.action_shape);
 
         if action.is_none() {
             return actions.len(); // None is the last action.
@@ -572,7 +1617,143 @@ impl SimulatorAgent {
 
 #[cfg(test)]
 mod tests {
-    use crate::simulator::task::TaskProps;
+    use crate::simulator::{
+        task::{SimulatorTask, Task, TaskProps, TimeUnit},
+        Simulator, SimulatorEvent, SimulatorMode,
+    };
+    use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+    fn test_agent(tasks: &[SimulatorTask]) -> super::SimulatorAgent {
+        super::SimulatorAgent::new(
+            super::DEFAULT_MEM_SIZE,
+            super::DEFAULT_MIN_MEM_SIZE,
+            super::DEFAULT_GAMMA,
+            super::DEFAULT_UPDATE_FREQ,
+            super::DEFAULT_LEARNING_RATE,
+            vec![4],
+            super::DEFAULT_SAMPLE_BATCH_SIZE,
+            super::dqn::ActivationFunction::ReLU,
+            tasks,
+        )
+    }
+
+    fn test_tasks() -> Vec<SimulatorTask> {
+        (1..=3)
+            .map(|id| {
+                SimulatorTask::new_with_custom_priority(
+                    Task::LTask(TaskProps {
+                        id,
+                        wcet_l: 2,
+                        wcet_h: 3,
+                        offset: 0,
+                        period: 10,
+                    }),
+                    id,
+                    1,
+                )
+            })
+            .collect()
+    }
+
+    /// Any WCET increase makes the lowest priority task miss its deadline.
+    fn saturated_tasks() -> Vec<SimulatorTask> {
+        (1..=3)
+            .map(|id| {
+                SimulatorTask::new_with_custom_priority(
+                    Task::LTask(TaskProps {
+                        id,
+                        wcet_l: if id == 3 { 4 } else { 3 },
+                        wcet_h: 10,
+                        offset: 0,
+                        period: 10,
+                    }),
+                    id,
+                    1,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn action_history() {
+        let tasks = test_tasks();
+        let agent = Rc::new(RefCell::new(test_agent(&tasks)));
+        let mut simulator = Simulator::new(tasks, false, Some(agent.clone()));
+        simulator.agent_period = 5;
+        simulator.fire::<false>(50);
+
+        let activations = simulator.memory_usage().len();
+        assert!(activations >= 10);
+        assert_eq!(agent.borrow().action_history().len(), activations);
+        assert!(agent
+            .borrow()
+            .action_history()
+            .iter()
+            .all(|index| *index < agent.borrow()._number_of_actions));
+
+        agent.borrow_mut().quit_training();
+        assert!(agent.borrow().action_history().is_empty());
+    }
+
+    #[test]
+    fn continuous_actions() {
+        let tasks = test_tasks();
+        let mut agent = test_agent(&tasks);
+        agent.set_continuous_actions(0.5);
+        agent.set_memory_seed(0);
+        let agent = Rc::new(RefCell::new(agent));
+        let mut simulator = Simulator::new(tasks, false, Some(agent.clone()));
+        simulator.agent_period = 5;
+        simulator.fire::<false>(100);
+
+        // The budgets are scaled directly, never through a discrete action.
+        let activations = simulator.memory_usage().len();
+        let nothing = agent.borrow()._number_of_actions - 1;
+        assert!(agent
+            .borrow()
+            .action_history()
+            .iter()
+            .all(|index| *index == nothing));
+        assert_eq!(agent.borrow().replay_memory_size(), 0);
+        assert_eq!(
+            agent.borrow().ddpg.as_ref().unwrap().replay_memory_size(),
+            activations - 1
+        );
+        for task in &simulator.tasks {
+            let task = task.borrow();
+            let props = task.task.props();
+            assert!((task.wcet_l_floor.min(2)..=props.wcet_h).contains(&props.wcet_l));
+        }
+    }
+
+    #[test]
+    fn monitor_mode() {
+        let tasks = test_tasks();
+        let mut agent = test_agent(&tasks);
+        agent.replay_memory.min_size = 4;
+        agent.monitor_mode();
+        let agent = Rc::new(RefCell::new(agent));
+        let mut simulator = Simulator::new(tasks, false, Some(agent.clone()));
+        simulator.agent_period = 5;
+        simulator.fire::<false>(100);
+
+        // The agent learns from what it observes, but never changes the budgets.
+        assert_eq!(agent.borrow().stage(), super::SimulatorAgentStage::Monitor);
+        assert!(agent.borrow().replay_memory_size() >= 4);
+        assert!(agent.borrow().optimizer_steps > 0);
+        let nothing = agent.borrow()._number_of_actions - 1;
+        assert!(agent
+            .borrow()
+            .action_history()
+            .iter()
+            .all(|index| *index == nothing));
+        for (task, initial) in simulator.tasks.iter().zip(simulator.original_tasks()) {
+            assert_eq!(
+                task.borrow().task.props().wcet_l,
+                initial.task.props().wcet_l
+            );
+        }
+    }
 
     #[test]
     fn generate_actions() {
@@ -584,7 +1765,8 @@ mod tests {
             TaskProps::new_empty(4),
             TaskProps::new_empty(5),
         ];
-        let actions = super::SimulatorAgent::generate_actions(&props);
+        let actions =
+            super::SimulatorAgent::generate_actions(&props, super::ActionShape::default());
         for action in &actions {
             println!("{:?}", action);
         }
@@ -592,4 +1774,1007 @@ mod tests {
         let expected_number = 6 * (5 * 4) / 2;
         assert_eq!(actions.len(), expected_number);
     }
+
+    #[test]
+    fn canonical_actions() {
+        let props = (0..5).map(TaskProps::new_empty).collect::<Vec<_>>();
+        let shuffled = [3, 0, 4, 2, 1]
+            .iter()
+            .map(|&id| TaskProps::new_empty(id))
+            .collect::<Vec<_>>();
+
+        let shape = super::ActionShape::default();
+        let actions = super::SimulatorAgent::generate_actions(&props, shape);
+        assert_eq!(
+            super::SimulatorAgent::generate_actions(&shuffled, shape),
+            actions
+        );
+        assert_eq!(
+            actions[0],
+            vec![
+                super::SimulatorActionPart::WcetIncrease(0),
+                super::SimulatorActionPart::WcetDecrease(1),
+                super::SimulatorActionPart::WcetDecrease(2)
+            ]
+        );
+    }
+
+    #[test]
+    fn softmax_sample() {
+        let q_values = [1.0, 5.0, 2.0, -3.0];
+        let rng = &mut rand::thread_rng();
+
+        let mut counts = [0; 4];
+        for _ in 0..1000 {
+            counts[super::SimulatorAgent::softmax_sample(&q_values, 0.1, rng)] += 1;
+        }
+
+        assert!(counts[1] > 990);
+    }
+
+    #[test]
+    fn masked_actions_never_selected() {
+        let mut q_values = [10.0, 1.0, 2.0, 8.0];
+        super::SimulatorAgent::mask_invalid_actions(&mut q_values, &[1, 2]);
+        assert_eq!(super::SimulatorAgent::argmax(&q_values), 2);
+
+        let rng = &mut rand::thread_rng();
+        for _ in 0..1000 {
+            let index = super::SimulatorAgent::softmax_sample(&q_values, 1.0, rng);
+            assert!(index == 1 || index == 2);
+        }
+    }
+
+    #[test]
+    fn valid_actions() {
+        let tasks = saturated_tasks();
+        let agent = test_agent(&tasks);
+        let simulator = Simulator::new(tasks, false, None);
+
+        assert_eq!(agent.valid_actions(&simulator), vec![3]);
+    }
+
+    #[test]
+    fn evaluate_action() {
+        let tasks = saturated_tasks();
+        let agent = test_agent(&tasks);
+        let simulator = Simulator::new(tasks, false, None);
+        let snapshot = |simulator: &Simulator| {
+            simulator
+                .tasks
+                .iter()
+                .map(|t| t.borrow().clone())
+                .collect::<Vec<_>>()
+        };
+        let before = snapshot(&simulator);
+
+        // Doing nothing leaves the lowest priority task with no slack.
+        let evaluation = agent.evaluate_action(&vec![super::SimulatorActionPart::None], &simulator);
+        assert!(evaluation.feasible);
+        assert_eq!(
+            evaluation
+                .slacks
+                .iter()
+                .map(|(_, slack)| *slack)
+                .collect::<Vec<_>>(),
+            vec![Some(7), Some(4), Some(0)]
+        );
+
+        // Any increase makes it miss its deadline.
+        let actions = super::SimulatorAgent::generate_actions(
+            &agent.actionable_props(&simulator),
+            agent.action_shape,
+        );
+        let evaluation = agent.evaluate_action(&actions[0], &simulator);
+        assert!(!evaluation.feasible);
+        assert!(evaluation.slacks[2].1.unwrap() < 0);
+        assert_eq!(snapshot(&simulator), before);
+    }
+
+    #[test]
+    fn seed_memory() {
+        let mut agent = test_agent(&test_tasks());
+        let state = tch::Tensor::from_slice(&vec![0.0f32; agent.number_of_features]);
+        let transitions = (0..super::DEFAULT_MIN_MEM_SIZE)
+            .map(|i| super::Transition::new(&state, i as i64 % 2, 1.0, &state))
+            .collect::<Vec<_>>();
+
+        assert_eq!(agent.stage, super::SimulatorAgentStage::DataCollection);
+        agent.seed_memory_from(transitions);
+        assert_eq!(agent.stage, super::SimulatorAgentStage::Training);
+        assert_eq!(agent.replay_memory_size(), super::DEFAULT_MIN_MEM_SIZE);
+        assert_eq!(agent.transitions().len(), super::DEFAULT_MIN_MEM_SIZE);
+    }
+
+    #[test]
+    fn gradient_accumulation() {
+        let tasks = test_tasks();
+        let new_agent = |accumulate_steps, sample_batch_size| {
+            let mut agent = test_agent(&tasks);
+            agent.sample_batch_size = sample_batch_size;
+            agent.set_accumulate_steps(accumulate_steps);
+            // All the transitions are the same, so that every batch is too.
+            let state = tch::Tensor::from_slice(&vec![0.5f32; agent.number_of_features]);
+            agent.seed_memory_from(
+                (0..super::DEFAULT_MIN_MEM_SIZE)
+                    .map(|_| super::Transition::new(&state, 1, 1.0, &state))
+                    .collect(),
+            );
+            agent
+        };
+        let mut accumulated = new_agent(2, 3);
+        let mut single = new_agent(1, 6);
+        for i in 0..single.memory_policy.size() {
+            let weights = accumulated.memory_policy.get(i).detach().copy();
+            single.memory_policy.set(i, weights.set_requires_grad(true));
+        }
+        single.memory_target.copy(&accumulated.memory_target);
+
+        // Two batches of 3 make the same step as one batch of 6.
+        assert!(accumulated.optimize(false).is_some());
+        assert!(single.optimize(false).is_some());
+        assert_eq!(accumulated.optimizer_steps, 1);
+        for i in 0..single.memory_policy.size() {
+            assert!(accumulated.memory_policy.get(i).allclose(
+                single.memory_policy.get(i),
+                1e-5,
+                1e-6,
+                false
+            ));
+        }
+    }
+
+    #[test]
+    fn resume_memory() {
+        let mut agent = test_agent(&test_tasks());
+        let state = tch::Tensor::from_slice(&vec![0.0f32; agent.number_of_features]);
+        let transitions = (0..super::DEFAULT_MIN_MEM_SIZE)
+            .map(|i| super::Transition::new(&state, i as i64 % 2, 1.0, &state))
+            .collect::<Vec<_>>();
+        agent.seed_memory_from(transitions);
+
+        let path = std::env::temp_dir().join("resume_memory.ot");
+        agent.save_memory(&path).unwrap();
+        let mut resumed = test_agent(&test_tasks());
+        resumed.load_memory(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(resumed.stage, super::SimulatorAgentStage::Training);
+        assert_eq!(resumed.replay_memory_size(), super::DEFAULT_MIN_MEM_SIZE);
+    }
+
+    #[test]
+    fn epsilon_decay() {
+        let tasks = test_tasks();
+        let mut agent = test_agent(&tasks);
+        assert_eq!(agent.epsilon(), 1.0);
+        assert_eq!(agent.stage(), super::SimulatorAgentStage::DataCollection);
+
+        let state = tch::Tensor::from_slice(&vec![0.0f32; agent.number_of_features]);
+        let transitions = (0..super::DEFAULT_MIN_MEM_SIZE)
+            .map(|_| super::Transition::new(&state, 0, 1.0, &state))
+            .collect::<Vec<_>>();
+        agent.seed_memory_from(transitions);
+        assert_eq!(agent.stage(), super::SimulatorAgentStage::Training);
+
+        let mut simulator = Simulator::new(tasks, false, None);
+        for _ in 0..super::DEFAULT_UPDATE_FREQ {
+            agent.activate(&mut simulator);
+        }
+        assert!(agent.epsilon() < 1.0);
+    }
+
+    #[test]
+    fn pretraining() {
+        let tasks = test_tasks();
+        let mut agent = super::SimulatorAgent::new(
+            super::DEFAULT_MEM_SIZE,
+            super::DEFAULT_MIN_MEM_SIZE,
+            super::DEFAULT_GAMMA,
+            super::DEFAULT_UPDATE_FREQ,
+            super::DEFAULT_LEARNING_RATE,
+            vec![16],
+            super::DEFAULT_SAMPLE_BATCH_SIZE,
+            super::dqn::ActivationFunction::Tanh,
+            &tasks,
+        );
+
+        // Each action is the expert choice in the state where a single feature is set.
+        let states = (0..agent._number_of_actions)
+            .map(|action| {
+                let mut state = vec![0.0f32; agent.number_of_features];
+                state[action] = 1.0;
+                tch::Tensor::from_slice(&state)
+            })
+            .collect::<Vec<_>>();
+        let actions = (0..agent._number_of_actions).collect::<Vec<_>>();
+
+        assert!(agent.pretrain(&states, &actions, 500, 0.001) > 0.9);
+    }
+
+    #[test]
+    fn event_history_capacity() {
+        let mut agent = test_agent(&test_tasks());
+        agent.set_event_history_capacity(3);
+        let task = Rc::new(RefCell::new(test_tasks()[0].clone()));
+
+        for time in 0..10 {
+            agent.push_event(SimulatorEvent::Start(task.clone(), time));
+            assert!(agent.events_history_len() <= 3);
+        }
+        let times = agent
+            .events_history
+            .iter()
+            .map(|e| e.time())
+            .collect::<Vec<_>>();
+        assert_eq!(times, vec![7, 8, 9]);
+
+        agent.set_event_history_capacity(2);
+        agent.push_event(SimulatorEvent::Start(task, 10));
+        let times = agent
+            .events_history
+            .iter()
+            .map(|e| e.time())
+            .collect::<Vec<_>>();
+        assert_eq!(times, vec![9, 10]);
+    }
+
+    #[test]
+    fn convergence() {
+        let mut agent = test_agent(&test_tasks());
+        assert!(!agent.has_converged(10, 0.1));
+
+        agent.reward_history = vec![5.0; 20];
+        assert!(agent.has_converged(10, 0.1));
+
+        agent.reward_history = (0..20).map(|i| i as f32).collect();
+        assert!(!agent.has_converged(10, 0.1));
+    }
+
+    #[test]
+    fn recent_reward() {
+        let mut agent = test_agent(&test_tasks());
+        assert_eq!(agent.recent_reward(0.5), 0.0);
+
+        // s_n = (1 - a)^n * r_0 + sum_{k = 1..n} a * (1 - a)^(n - k) * r_k
+        let rewards = [4.0f32, -2.0, 1.0, 3.0];
+        agent.reward_history = rewards.to_vec();
+        let alpha = 0.25f64;
+        let n = rewards.len() - 1;
+        let expected = (1.0 - alpha).powi(n as i32) * rewards[0] as f64
+            + (1..=n)
+                .map(|k| alpha * (1.0 - alpha).powi((n - k) as i32) * rewards[k] as f64)
+                .sum::<f64>();
+        assert!((agent.recent_reward(alpha) - expected).abs() < 1e-9);
+        assert_eq!(agent.recent_reward(1.0), 3.0);
+        assert_eq!(agent.recent_reward(0.0), 4.0);
+
+        agent.quit_training();
+        assert_eq!(agent.recent_reward(alpha), 0.0);
+    }
+
+    #[test]
+    fn reward_weights() {
+        let mut agent = test_agent(&test_tasks());
+        agent.set_reward_weights(super::RewardWeights {
+            kill: -1.0,
+            hmode_change: 0.0,
+            lmode_change: 0.0,
+            task_start: 0.0,
+            deadline_miss: 0.0,
+            deadline_miss_ratio: 0.0,
+            energy: 0.0,
+            hmode_recovery_horizon: 0,
+        });
+
+        let task = Rc::new(RefCell::new(test_tasks()[0].clone()));
+        let events = vec![
+            SimulatorEvent::Start(task.clone(), 0),
+            SimulatorEvent::TaskKill(task.clone(), 2),
+            SimulatorEvent::ModeChange(SimulatorMode::HMode, 3),
+            SimulatorEvent::DeadlineMiss(task.clone(), 4),
+            SimulatorEvent::ModeChange(SimulatorMode::LMode, 5),
+            SimulatorEvent::Start(task.clone(), 10),
+            SimulatorEvent::TaskKill(task, 12),
+        ];
+        assert_eq!(agent.events_reward(&events, 1.0, 0.5), -2.0);
+
+        agent.set_reward_weights(super::RewardWeights::default());
+        assert_eq!(agent.events_reward(&events, 1.0, 0.5), 0.2 - 2.0 - 2.0);
+
+        agent.set_reward_weights(super::RewardWeights {
+            deadline_miss_ratio: -4.0,
+            ..Default::default()
+        });
+        assert_eq!(
+            agent.events_reward(&events, 1.0, 0.5),
+            0.2 - 2.0 - 2.0 - 2.0
+        );
+    }
+
+    #[test]
+    fn hmode_recovery_discount() {
+        let mut agent = test_agent(&test_tasks());
+        agent.set_reward_weights(super::RewardWeights {
+            hmode_recovery_horizon: 10,
+            ..Default::default()
+        });
+        let run = |lmode_return: Option<TimeUnit>| {
+            let mut events = vec![SimulatorEvent::ModeChange(SimulatorMode::HMode, 3)];
+            events
+                .extend(lmode_return.map(|t| SimulatorEvent::ModeChange(SimulatorMode::LMode, t)));
+            agent.events_reward(&events, 0.0, 0.0)
+        };
+
+        // H-mode for 2 of the 10 units of the horizon is a fifth of the penalty.
+        assert!((run(Some(5)) - -0.4).abs() < 1e-9);
+        assert_eq!(run(Some(20)), -2.0);
+        assert_eq!(run(None), -2.0);
+        assert!(run(Some(5)) > run(Some(10)));
+    }
+
+    #[test]
+    fn policy_table() {
+        let tasks = test_tasks();
+        let agent = test_agent(&tasks);
+        let simulator = Simulator::new(tasks, false, None);
+        let states = [0.0f32, 1.0]
+            .iter()
+            .map(|v| tch::Tensor::from_slice(&vec![*v; agent.number_of_features]))
+            .collect::<Vec<_>>();
+
+        let table = agent.policy_table(&states, &simulator);
+        assert_eq!(table.len(), 2);
+        assert_eq!(table, agent.policy_table(&states, &simulator));
+
+        for (state, action) in states.iter().zip(&table) {
+            let value = agent.policy_network.forward(&agent.memory_policy, state);
+            let index = super::SimulatorAgent::argmax(&super::SimulatorAgent::q_values(&value));
+            assert_eq!(*action, agent.index_to_action(index, &simulator));
+        }
+    }
+
+    #[test]
+    fn batched_evaluation() {
+        let tasks = test_tasks();
+        for architecture in [
+            super::dqn::PolicyArchitecture::Standard,
+            super::dqn::PolicyArchitecture::Dueling,
+        ] {
+            let mut agent = test_agent(&tasks);
+            agent.set_architecture(architecture);
+            let states = (0..8)
+                .map(|i| {
+                    tch::Tensor::from_slice(&vec![i as f32 / 8.0 - 0.5; agent.number_of_features])
+                })
+                .collect::<Vec<_>>();
+
+            let batch = agent
+                .policy_network
+                .forward_batch(&agent.memory_policy, &states);
+            assert_eq!(batch.size(), vec![8, agent._number_of_actions as i64]);
+            for (i, state) in states.iter().enumerate() {
+                let single = agent.policy_network.forward(&agent.memory_policy, state);
+                assert!(batch
+                    .get(i as i64)
+                    .allclose(&single.flatten(0, -1), 1e-5, 1e-6, false));
+            }
+        }
+
+        let agent = test_agent(&tasks);
+        let simulators = [
+            Simulator::new(tasks.clone(), false, None),
+            Simulator::new(tasks, false, None),
+        ];
+        let simulators = simulators.iter().collect::<Vec<_>>();
+        let input = agent.histories_to_input(&simulators);
+        assert_eq!(input.size(), vec![2, agent.number_of_features as i64]);
+        assert!(input.get(1).equal(&agent.history_to_input(simulators[1])));
+    }
+
+    #[test]
+    fn observation_window() {
+        let tasks = test_tasks();
+        let mut agent = test_agent(&tasks);
+        agent.set_observation_window(2);
+        let simulator = Simulator::new(tasks, false, None);
+        let task_id = simulator.tasks[0].borrow().task.props().id;
+
+        // Unknown execution times are normalized to (-1 - bcet) / (wcet_h - bcet).
+        let last_exec_time_feature =
+            |agent: &super::SimulatorAgent| agent.history_to_input(&simulator).double_value(&[1]);
+        assert_eq!(last_exec_time_feature(&agent), -1.0);
+
+        agent.push_exec_time(task_id, 2);
+        assert_eq!(last_exec_time_feature(&agent), 0.5);
+        agent.push_event(SimulatorEvent::AgentTick(1));
+        assert_eq!(last_exec_time_feature(&agent), 0.5);
+
+        // The execution time is now older than the window.
+        agent.push_event(SimulatorEvent::AgentTick(2));
+        assert_eq!(last_exec_time_feature(&agent), -1.0);
+    }
+
+    #[test]
+    fn state_encoder() {
+        /// The utilization of each task, and the time of the simulator.
+        struct UtilizationEncoder(usize);
+
+        impl super::encoder::StateEncoder for UtilizationEncoder {
+            fn encode(&self, _: &super::SimulatorAgent, simulator: &Simulator) -> tch::Tensor {
+                let mut input = simulator
+                    .tasks
+                    .iter()
+                    .map(|t| t.borrow().task.props().utilization() as f32)
+                    .collect::<Vec<_>>();
+                input.push(simulator.now() as f32);
+                tch::Tensor::from_slice(&input)
+            }
+
+            fn num_features(&self) -> usize {
+                self.0 + 1
+            }
+        }
+
+        let tasks = test_tasks();
+        let mut agent = test_agent(&tasks);
+        agent.set_state_encoder(Box::new(UtilizationEncoder(tasks.len())));
+        assert_eq!(agent.number_of_features, 4);
+
+        let simulator = Simulator::new(tasks.clone(), false, None);
+        let state = agent.history_to_input(&simulator);
+        assert_eq!(state.size(), vec![4]);
+        let value = agent.policy_network.forward(&agent.memory_policy, &state);
+        assert_eq!(
+            super::SimulatorAgent::q_values(&value).len(),
+            agent._number_of_actions
+        );
+
+        // The agent trains on the custom states.
+        agent.replay_memory.min_size = 4;
+        let agent = Rc::new(RefCell::new(agent));
+        let mut simulator = Simulator::new(tasks, false, Some(agent.clone()));
+        simulator.agent_period = 5;
+        simulator.fire::<false>(100);
+        assert!(agent.borrow().optimizer_steps > 0);
+
+        // The default encoder may also see the backlog of the simulator.
+        let mut agent = agent.borrow_mut();
+        agent.set_state_encoder(Box::new(
+            super::encoder::DefaultEncoder::new(3).with_load_features(),
+        ));
+        let state = agent.history_to_input(&simulator);
+        assert_eq!(state.size(), vec![8]);
+        assert_eq!(
+            state.double_value(&[7]),
+            (simulator.pending_event_count() as f32 / 3.0) as f64
+        );
+    }
+
+    #[test]
+    fn actionable_tasks() {
+        let tasks = (1..=6)
+            .map(|id| {
+                SimulatorTask::new_with_custom_priority(
+                    Task::LTask(TaskProps {
+                        id,
+                        wcet_l: 1,
+                        wcet_h: 2,
+                        offset: 0,
+                        period: 20,
+                    }),
+                    id,
+                    1,
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut agent = test_agent(&tasks);
+        assert_eq!(agent._number_of_actions, 6 * (5 * 4) / 2 + 1);
+
+        agent.set_actionable_tasks(vec![2, 4, 6]);
+        // Each task may be increased while the other two are decreased, plus the None action.
+        assert_eq!(agent._number_of_actions, 3 + 1);
+
+        let simulator = Simulator::new(tasks, false, None);
+        let state = agent.history_to_input(&simulator);
+        let value = agent.policy_network.forward(&agent.memory_policy, &state);
+        assert_eq!(super::SimulatorAgent::q_values(&value).len(), 4);
+
+        let actionable_ids = [1, 3, 5]
+            .iter()
+            .map(|&i| simulator.tasks[i].borrow().task.props().id)
+            .collect::<Vec<_>>();
+        for index in 0..3 {
+            let action = agent.index_to_action(index, &simulator).unwrap();
+            assert!(action.iter().all(|p| actionable_ids.contains(&p.task_id())));
+            assert_eq!(agent.action_to_index(Some(&action), &simulator), index);
+        }
+        assert_eq!(agent.index_to_action(3, &simulator), None);
+    }
+
+    #[test]
+    fn on_action() {
+        let tasks = saturated_tasks();
+        let agent = Rc::new(RefCell::new(test_agent(&tasks)));
+        let outcomes = Rc::new(RefCell::new(vec![]));
+        let outcomes_cpy = outcomes.clone();
+        agent.borrow_mut().set_on_action(move |action, outcome| {
+            outcomes_cpy.borrow_mut().push((action.clone(), outcome))
+        });
+        let mut simulator = Simulator::new(tasks, false, Some(agent));
+
+        let ids = simulator
+            .tasks
+            .iter()
+            .map(|t| t.borrow().task.props().id)
+            .collect::<Vec<_>>();
+        let action = vec![
+            super::SimulatorActionPart::WcetIncrease(ids[0]),
+            super::SimulatorActionPart::WcetDecrease(ids[1]),
+            super::SimulatorActionPart::WcetDecrease(ids[2]),
+        ];
+        let outcome =
+            crate::simulator::handlers::apply_agent_action(action.clone(), &mut simulator);
+
+        assert_eq!(outcome, super::ActionOutcome::Reverted);
+        assert_eq!(
+            *outcomes.borrow(),
+            vec![(action, super::ActionOutcome::Reverted)]
+        );
+    }
+
+    #[test]
+    fn activation_pipeline() {
+        let run = |tasks: Vec<SimulatorTask>| {
+            let mut agent = test_agent(&tasks);
+            agent.set_reward_weights(super::RewardWeights {
+                task_start: 1.0,
+                ..Default::default()
+            });
+            agent.set_action_magnitudes(super::ActionMagnitudes {
+                increase_factor: 0.5,
+                decrease_factor: 0.5,
+            });
+            let agent = Rc::new(RefCell::new(agent));
+            let mut simulator = Simulator::new(tasks, false, Some(agent.clone()));
+            simulator.agent_period = 5;
+
+            // Take budget from the two lowest priority tasks to give to the highest.
+            let ids = simulator
+                .tasks
+                .iter()
+                .map(|t| t.borrow().task.props().id)
+                .collect::<Vec<_>>();
+            let action = vec![
+                super::SimulatorActionPart::WcetIncrease(ids[0]),
+                super::SimulatorActionPart::WcetDecrease(ids[1]),
+                super::SimulatorActionPart::WcetDecrease(ids[2]),
+            ];
+            agent.borrow_mut().fixed_action = Some(action.clone());
+            let outcomes = Rc::new(RefCell::new(vec![]));
+            let outcomes_cpy = outcomes.clone();
+            agent
+                .borrow_mut()
+                .set_on_action(move |_, outcome| outcomes_cpy.borrow_mut().push(outcome));
+            let initial_state = agent.borrow().history_to_input(&simulator);
+            let index = agent.borrow().action_to_index(Some(&action), &simulator) as i64;
+
+            // Activations at 0 and 5: the first transition is complete.
+            simulator.fire::<false>(5);
+            let outcomes = outcomes.borrow().clone();
+            (agent, simulator, initial_state, index, outcomes)
+        };
+
+        // The action is applied, and its reward is the 3 jobs started since.
+        let (agent, simulator, initial_state, index, outcomes) = run(test_tasks());
+        assert_eq!(outcomes, vec![super::ActionOutcome::Applied; 2]);
+        // Applied at both activations, the decreases stopping at the floor.
+        assert_eq!(simulator.tasks[0].borrow().task.props().wcet_l, 4);
+        assert_eq!(simulator.tasks[1].borrow().task.props().wcet_l, 1);
+        let agent = agent.borrow();
+        assert_eq!(agent.reward_history, vec![0.0, 3.0]);
+        assert_eq!(agent.replay_memory.transitions.len(), 1);
+        let transition = &agent.replay_memory.transitions[0];
+        assert_eq!(transition.action, index);
+        assert_eq!(transition.reward, 3.0);
+        assert!(transition.state.equal(&initial_state));
+        assert!(!transition.state_.equal(&initial_state));
+
+        // No budget can shrink, and any increase makes the lowest priority task miss its
+        // deadline: the action is reverted, but still learned as taken.
+        let mut tasks = saturated_tasks();
+        for task in &mut tasks {
+            task.wcet_l_floor = task.task.props().wcet_l;
+        }
+        let (agent, simulator, _, index, outcomes) = run(tasks.clone());
+        assert_eq!(outcomes, vec![super::ActionOutcome::Reverted; 2]);
+        for (task, initial) in simulator.tasks.iter().zip(tasks) {
+            assert_eq!(
+                task.borrow().task.props().wcet_l,
+                initial.task.props().wcet_l
+            );
+        }
+        assert_eq!(agent.borrow().replay_memory.transitions[0].action, index);
+    }
+
+    #[test]
+    fn ensemble() {
+        let tasks = test_tasks();
+        let mut agent = test_agent(&tasks);
+        agent.set_ensemble_size(3);
+        assert_eq!(agent.ensemble.len(), 2);
+        for (storage, _) in &mut agent.ensemble {
+            storage.copy(&agent.memory_policy);
+        }
+        agent.quit_training();
+
+        // Identical members agree, and the ensemble takes their action.
+        let simulator = Simulator::new(tasks.clone(), false, None);
+        let state = agent.history_to_input(&simulator);
+        let action = agent.epsilon_greedy(
+            &agent.memory_policy,
+            &*agent.policy_network,
+            0.0,
+            &state,
+            &simulator,
+        );
+        for (storage, network) in &agent.ensemble {
+            assert_eq!(
+                agent.epsilon_greedy(storage, &**network, 0.0, &state, &simulator),
+                action
+            );
+        }
+        assert_eq!(agent.ensemble_action(&state, &simulator), action);
+
+        // The members explore as configured: a hot softmax spreads the votes.
+        let mut agent = test_agent(&tasks);
+        agent.set_ensemble_size(3);
+        agent.set_exploration(super::ExplorationStrategy::Softmax { temperature: 1e6 });
+        agent.epsilon = 0.0;
+        let actions = (0..50)
+            .map(|_| agent.ensemble_action(&state, &simulator))
+            .collect::<Vec<_>>();
+        assert!(actions.iter().any(|a| *a != actions[0]));
+
+        // All members train along the policy network.
+        let mut agent = test_agent(&tasks);
+        agent.set_ensemble_size(2);
+        agent.replay_memory.min_size = 4;
+        let agent = Rc::new(RefCell::new(agent));
+        let mut simulator = Simulator::new(tasks, false, Some(agent.clone()));
+        simulator.agent_period = 5;
+        simulator.fire::<false>(100);
+        assert!(agent.borrow().optimizer_steps > 0);
+    }
+
+    #[test]
+    fn metrics_sink() {
+        let tasks = test_tasks();
+        let mut agent = test_agent(&tasks);
+        agent.replay_memory.min_size = 4;
+        let metrics = Rc::new(RefCell::new(vec![]));
+        let metrics_cpy = metrics.clone();
+        agent.set_metrics_sink(Box::new(move |m: &super::TrainingMetrics| {
+            metrics_cpy.borrow_mut().push(*m)
+        }));
+        let agent = Rc::new(RefCell::new(agent));
+        let mut simulator = Simulator::new(tasks, false, Some(agent.clone()));
+        simulator.agent_period = 5;
+        simulator.fire::<false>(100);
+
+        // Once per training step, in order.
+        let metrics = metrics.borrow();
+        assert_eq!(metrics.len(), agent.borrow().optimizer_steps);
+        assert!(!metrics.is_empty());
+        for (i, m) in metrics.iter().enumerate() {
+            assert_eq!(m.step, i + 1);
+            assert!(m.loss.is_finite());
+            assert!(m.replay_size >= 4);
+        }
+        let last = metrics.last().unwrap();
+        assert_eq!(last.epsilon, agent.borrow().epsilon());
+        assert_eq!(last.replay_size, agent.borrow().replay_memory_size());
+    }
+
+    #[test]
+    fn wcet_l_floor() {
+        let mut task = SimulatorTask::new_with_custom_priority(
+            Task::LTask(TaskProps {
+                id: 1,
+                wcet_l: 20,
+                wcet_h: 40,
+                offset: 0,
+                period: 100,
+            }),
+            1,
+            1,
+        );
+        task.wcet_l_floor = 5;
+        let tasks = &mut [Rc::new(RefCell::new(task))];
+
+        // Each decrease removes 5% of wcet_h, i.e. 2 units.
+        for _ in 0..20 {
+            super::SimulatorActionPart::WcetDecrease(1).apply(tasks, Default::default());
+            assert!(tasks[0].borrow().task.props().wcet_l >= 5);
+        }
+        assert_eq!(tasks[0].borrow().task.props().wcet_l, 5);
+    }
+
+    #[test]
+    fn suggested_mem_size() {
+        let sizes = (3..10)
+            .map(|number_of_tasks| {
+                let tasks = (0..number_of_tasks)
+                    .map(|id| SimulatorTask::new(Task::LTask(TaskProps::new_empty(id)), 1, 1))
+                    .collect::<Vec<_>>();
+                super::SimulatorAgent::suggested_mem_size(
+                    super::SimulatorAgent::number_of_actions(&tasks),
+                    super::SimulatorAgent::number_of_features(&tasks),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(sizes[0], super::DEFAULT_MEM_SIZE);
+        assert!(sizes.windows(2).all(|w| w[0] <= w[1]));
+        assert!(sizes[sizes.len() - 1] > super::DEFAULT_MEM_SIZE);
+    }
+
+    #[test]
+    fn no_op_action() {
+        // With less than 3 tasks, doing nothing is the only action.
+        let tasks = test_tasks()[..2].to_vec();
+        let mut agent = test_agent(&tasks);
+        assert_eq!(agent._number_of_actions, 1);
+        let mut simulator = Simulator::new(tasks, false, None);
+
+        let state = agent.history_to_input(&simulator);
+        let value = agent.policy_network.forward(&agent.memory_policy, &state);
+        assert_eq!(super::SimulatorAgent::q_values(&value).len(), 1);
+        assert_eq!(agent.valid_actions(&simulator), vec![0]);
+        assert_eq!(agent.index_to_action(0, &simulator), None);
+        assert_eq!(agent.action_to_index(None, &simulator), 0);
+
+        // The no-op transitions are learned from.
+        agent.activate(&mut simulator);
+        agent.activate(&mut simulator);
+        assert_eq!(agent.replay_memory_size(), 1);
+    }
+
+    #[test]
+    fn stratified_sampling() {
+        let tasks = (1..=4)
+            .map(|id| {
+                SimulatorTask::new_with_custom_priority(
+                    Task::LTask(TaskProps {
+                        id,
+                        wcet_l: 10,
+                        wcet_h: 20,
+                        offset: 0,
+                        period: 100,
+                    }),
+                    id,
+                    1,
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut agent = test_agent(&tasks);
+        let simulator = Simulator::new(tasks, false, None);
+
+        let mut counts = HashMap::new();
+        for _ in 0..500 {
+            let increased_task = agent
+                .stratified_simulator_action(&simulator)
+                .map(|action| action[0].task_id());
+            *counts.entry(increased_task).or_insert(0) += 1;
+        }
+
+        // Each of the 4 tasks, and doing nothing, are picked equally.
+        assert_eq!(counts.len(), 5);
+        assert!(counts.values().all(|&count| count == 100));
+    }
+
+    #[test]
+    fn exact_online_analysis() {
+        let tasks = vec![SimulatorTask::new_with_custom_priority(
+            Task::HTask(TaskProps {
+                id: 1,
+                wcet_l: 2,
+                wcet_h: 5,
+                offset: 0,
+                period: 10,
+            }),
+            1,
+            1,
+        )];
+        let mut agent = test_agent(&tasks);
+        let simulator = Simulator::new(tasks, false, None);
+
+        // The budget increase invalidates the cached L-mode response time (2),
+        // which the approximate analysis still relies on.
+        simulator.tasks[0].borrow_mut().task.props_mut().wcet_l = 3;
+        assert!(!agent.feasible_online(&simulator.tasks, &simulator));
+
+        agent.set_exact_online_analysis(true);
+        assert!(agent.feasible_online(&simulator.tasks, &simulator));
+    }
+
+    #[test]
+    fn action_shapes() {
+        let props = (0..4).map(TaskProps::new_empty).collect::<Vec<_>>();
+        let shape = |increases, decreases| super::ActionShape {
+            increases,
+            decreases,
+        };
+        let actions = |increases, decreases| {
+            super::SimulatorAgent::generate_actions(&props, shape(increases, decreases))
+        };
+
+        assert_eq!(actions(1, 0).len(), 4);
+        assert_eq!(actions(0, 1).len(), 4);
+        assert_eq!(actions(1, 1).len(), 4 * 3);
+        assert_eq!(actions(2, 2).len(), 6);
+        assert_eq!(
+            actions(1, 1)[0],
+            vec![
+                super::SimulatorActionPart::WcetIncrease(0),
+                super::SimulatorActionPart::WcetDecrease(1)
+            ]
+        );
+
+        let mut agent = test_agent(&test_tasks());
+        agent.set_action_shape(shape(1, 1));
+        let number_of_tasks = test_tasks().len();
+        assert_eq!(
+            agent._number_of_actions,
+            number_of_tasks * (number_of_tasks - 1) + 1
+        );
+    }
+
+    #[test]
+    fn single_part_action() {
+        let mut tasks = vec![Rc::new(RefCell::new(SimulatorTask::new(
+            Task::LTask(TaskProps {
+                id: 1,
+                wcet_l: 50,
+                wcet_h: 100,
+                offset: 0,
+                period: 200,
+            }),
+            50,
+            50,
+        )))];
+        let wcet_l = |tasks: &[Rc<RefCell<SimulatorTask>>]| tasks[0].borrow().task.props().wcet_l;
+
+        let action = vec![super::SimulatorActionPart::WcetIncrease(1)];
+        let applied = super::apply_action(&action, &mut tasks, Default::default());
+        assert_eq!(wcet_l(&tasks), 60);
+        assert_eq!(applied, vec![10]);
+
+        let reverse = super::reverse_action(&action);
+        assert_eq!(reverse, vec![super::SimulatorActionPart::WcetDecrease(1)]);
+        super::revert_action(&action, &applied, &mut tasks);
+        assert_eq!(wcet_l(&tasks), 50);
+    }
+
+    #[test]
+    fn action_magnitudes() {
+        let mut tasks = vec![Rc::new(RefCell::new(SimulatorTask::new(
+            Task::LTask(TaskProps {
+                id: 1,
+                wcet_l: 500,
+                wcet_h: 1003,
+                offset: 0,
+                period: 2000,
+            }),
+            50,
+            50,
+        )))];
+        let wcet_l = |tasks: &[Rc<RefCell<SimulatorTask>>]| tasks[0].borrow().task.props().wcet_l;
+        let magnitudes = super::ActionMagnitudes {
+            increase_factor: 0.17,
+            decrease_factor: 0.03,
+        };
+
+        for action in [
+            vec![super::SimulatorActionPart::WcetIncrease(1)],
+            vec![super::SimulatorActionPart::WcetDecrease(1)],
+        ] {
+            let applied = super::apply_action(&action, &mut tasks, magnitudes);
+            assert_ne!(wcet_l(&tasks), 500);
+            super::revert_action(&action, &applied, &mut tasks);
+            assert_eq!(wcet_l(&tasks), 500);
+        }
+
+        let mut agent = test_agent(&test_tasks());
+        assert_eq!(
+            agent.action_magnitudes(),
+            super::ActionMagnitudes::default()
+        );
+        agent.set_action_magnitudes(magnitudes);
+        assert_eq!(agent.action_magnitudes(), magnitudes);
+    }
+
+    #[test]
+    fn two_part_action() {
+        let mut tasks = (1..=2)
+            .map(|id| {
+                Rc::new(RefCell::new(SimulatorTask::new(
+                    Task::LTask(TaskProps {
+                        id,
+                        wcet_l: 50,
+                        wcet_h: 100,
+                        offset: 0,
+                        period: 200,
+                    }),
+                    50,
+                    50,
+                )))
+            })
+            .collect::<Vec<_>>();
+        let wcet_ls = |tasks: &[Rc<RefCell<SimulatorTask>>]| {
+            tasks
+                .iter()
+                .map(|t| t.borrow().task.props().wcet_l)
+                .collect::<Vec<_>>()
+        };
+
+        let action = vec![
+            super::SimulatorActionPart::WcetIncrease(1),
+            super::SimulatorActionPart::WcetDecrease(2),
+        ];
+        let applied = super::apply_action(&action, &mut tasks, Default::default());
+        assert_eq!(wcet_ls(&tasks), vec![60, 45]);
+
+        // The parts are undone in the opposite order.
+        let reverse = super::reverse_action(&action);
+        assert_eq!(
+            reverse,
+            vec![
+                super::SimulatorActionPart::WcetIncrease(2),
+                super::SimulatorActionPart::WcetDecrease(1)
+            ]
+        );
+        super::revert_action(&action, &applied, &mut tasks);
+        assert_eq!(wcet_ls(&tasks), vec![50, 50]);
+    }
+
+    #[test]
+    fn clamped_action_reverts() {
+        let mut task = SimulatorTask::new(
+            Task::LTask(TaskProps {
+                id: 1,
+                wcet_l: 12,
+                wcet_h: 100,
+                offset: 0,
+                period: 200,
+            }),
+            1,
+            1,
+        );
+        task.wcet_l_floor = 10;
+        let mut tasks = vec![Rc::new(RefCell::new(task))];
+        let wcet_l = |tasks: &[Rc<RefCell<SimulatorTask>>]| tasks[0].borrow().task.props().wcet_l;
+
+        // The decreases are clamped to the floor, so they do not mirror the increases.
+        let actions = [
+            super::SimulatorActionPart::WcetDecrease(1),
+            super::SimulatorActionPart::WcetIncrease(1),
+            super::SimulatorActionPart::WcetDecrease(1),
+            super::SimulatorActionPart::None,
+        ];
+        for _ in 0..10 {
+            for part in actions {
+                let action = vec![part];
+                let applied = super::apply_action(&action, &mut tasks, Default::default());
+                super::revert_action(&action, &applied, &mut tasks);
+                assert_eq!(wcet_l(&tasks), 12);
+            }
+        }
+
+        let action = actions.to_vec();
+        let applied = super::apply_action(&action, &mut tasks, Default::default());
+        assert_eq!(applied, vec![2, 10, 5, 0]);
+        super::revert_action(&action, &applied, &mut tasks);
+        assert_eq!(wcet_l(&tasks), 12);
+    }
 }
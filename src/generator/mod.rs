@@ -1,12 +1,19 @@
 use crate::simulator::{
-    task::{SimulatorTask, Task, TaskProps, TimeUnit},
+    task::{SimulatorTask, Task, TaskId, TaskProps, TimeUnit, TIME_UNITS_PER_SECOND},
+    validation::{feasible_schedule_design_time, total_utilization, validate_task_set},
     SimulatorMode,
 };
 use ctor::ctor;
 use rand::prelude::{Distribution, SliceRandom};
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use statrs::distribution::Uniform;
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+    time::Duration,
+};
 use weibull::RunnableWeibull;
 
 mod uunifast;
@@ -113,7 +120,17 @@ impl PartialEq for Runnable {
 }
 
 impl Runnable {
-    fn new_batch(period: Duration, number: usize) -> Vec<Runnable> {
+    /// A runnable whose execution time follows a Weibull distribution bounded by `bcet` and `wcet`.
+    pub fn new(bcet: TimeUnit, acet: TimeUnit, wcet: TimeUnit) -> Runnable {
+        Runnable {
+            acet,
+            bcet,
+            wcet,
+            weibull: RunnableWeibull::new(bcet as f64, acet as f64, wcet as f64),
+        }
+    }
+
+    fn new_batch(period: Duration, number: usize, rng: &mut impl Rng) -> Vec<Runnable> {
         let period_index = RUNNABLE_PERIODS.iter().position(|&x| x == period).unwrap();
         let [min_acet, avg_acet, max_acet] = MIN_AVG_MAX_AVG_EXECUTION_TIMES[period_index];
 
@@ -123,9 +140,9 @@ impl Runnable {
             Self::duration_to_time_unit(min_acet) as f64,
             Self::duration_to_time_unit(max_acet) as f64,
             Self::duration_to_time_unit(period) as f64,
+            rng,
         );
         assert_eq!(acets.len(), number);
-        let rng = &mut rand::thread_rng();
 
         acets
             .iter()
@@ -146,13 +163,6 @@ impl Runnable {
     }
 
     fn wcet_l_estimate(&self, period: Duration, mode: SimulatorMode) -> f64 {
-        // Sample execution times 100 times and sort them.
-        let rng = &mut rand::thread_rng();
-        let mut samples = (0..100)
-            .map(|_| self.weibull.sample(rng))
-            .collect::<Vec<f64>>();
-        samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
-
         // Find the budget assurance for this period.
         let period_index = RUNNABLE_PERIODS.iter().position(|&x| x == period).unwrap();
         let wcet_l_probability = match mode {
@@ -161,17 +171,22 @@ impl Runnable {
         };
 
         // Return the execution time that satisfies the budget assurance.
-        samples[wcet_l_probability as usize]
+        self.weibull.quantile(wcet_l_probability as f64 / 100.0)
     }
 
     pub fn duration_to_time_unit(duration: Duration) -> TimeUnit {
-        // We'll represent a second as 100_000_000 units.
-        // This allows us to represent us with a precision of 10^-2.
-        (duration.as_secs_f64() * 100_000_000.0) as TimeUnit
+        Self::duration_to_time_unit_with_scale(duration, TIME_UNITS_PER_SECOND)
+    }
+
+    /// Converts the duration to time units, `units_per_second` of them making up a second.
+    pub fn duration_to_time_unit_with_scale(
+        duration: Duration,
+        units_per_second: TimeUnit,
+    ) -> TimeUnit {
+        (duration.as_secs_f64() * units_per_second as f64) as TimeUnit
     }
 
-    pub fn sample_exec_time(&self) -> f64 {
-        let rng = &mut rand::thread_rng();
+    pub fn sample_exec_time(&self, rng: &mut impl Rng) -> f64 {
         let s = self.weibull.sample(rng);
         assert!(s <= self.wcet as f64);
         assert!(s >= self.bcet as f64);
@@ -180,8 +195,80 @@ impl Runnable {
 }
 
 pub fn generate_tasks(number_runnables: usize) -> Vec<SimulatorTask> {
-    let rng = &mut rand::thread_rng();
-    let mut period_runnables = HashMap::<Duration, usize>::new();
+    generate_tasks_with_offsets(number_runnables, false)
+}
+
+/// Generates a task set as in `generate_tasks`, optionally releasing each
+/// task at a random offset within its period.
+pub fn generate_tasks_with_offsets(
+    number_runnables: usize,
+    random_offsets: bool,
+) -> Vec<SimulatorTask> {
+    generate_tasks_with_rng(number_runnables, random_offsets, &mut rand::thread_rng())
+}
+
+/// Generates task sets as in `generate_tasks` until one is feasible at design time,
+/// giving up after `max_attempts`. The same seed always yields the same set.
+pub fn generate_feasible_tasks(
+    number_runnables: usize,
+    max_attempts: usize,
+    seed: u64,
+) -> Option<Vec<SimulatorTask>> {
+    let rng = &mut StdRng::seed_from_u64(seed);
+    (0..max_attempts)
+        .map(|_| generate_tasks_with_rng(number_runnables, false, rng))
+        .find(|tasks| validate_task_set(tasks).is_ok() && feasible_schedule_design_time(tasks))
+}
+
+/// How far the L-mode utilization of a set of `benchmark_suite` may be from its level.
+pub const BENCHMARK_UTILIZATION_TOLERANCE: f64 = 0.05;
+
+/// Task sets generated per requested set, before `benchmark_suite` gives up on a level.
+const BENCHMARK_ATTEMPTS_PER_SET: usize = 200;
+
+/// A reproducible corpus of feasible task sets: `sets_per_level` sets whose L-mode utilization
+/// is within `BENCHMARK_UTILIZATION_TOLERANCE` of each of the given levels, paired with
+/// that utilization. The number of runnables of the sets is adapted to reach each level;
+/// levels that cannot be reached with feasible sets have fewer (or no) sets.
+pub fn benchmark_suite(
+    utilizations: &[f64],
+    sets_per_level: usize,
+    seed: u64,
+) -> Vec<(f64, Vec<SimulatorTask>)> {
+    let rng = &mut StdRng::seed_from_u64(seed);
+    let mut suite = vec![];
+
+    for &target in utilizations {
+        let mut number_runnables = 10;
+        let mut found = 0;
+        for _ in 0..sets_per_level * BENCHMARK_ATTEMPTS_PER_SET {
+            if found == sets_per_level {
+                break;
+            }
+            let tasks = generate_tasks_with_rng(number_runnables, false, rng);
+            let utilization = total_utilization(&tasks, SimulatorMode::LMode);
+            if (utilization - target).abs() <= BENCHMARK_UTILIZATION_TOLERANCE {
+                if validate_task_set(&tasks).is_ok() && feasible_schedule_design_time(&tasks) {
+                    suite.push((utilization, tasks));
+                    found += 1;
+                }
+            } else {
+                // The utilization grows about linearly with the number of runnables.
+                let estimate = number_runnables as f64 * target / utilization.max(f64::EPSILON);
+                number_runnables = (estimate.round() as usize).clamp(1, 1000);
+            }
+        }
+    }
+    suite
+}
+
+fn generate_tasks_with_rng(
+    number_runnables: usize,
+    random_offsets: bool,
+    rng: &mut impl Rng,
+) -> Vec<SimulatorTask> {
+    // Periods are visited in order, so that the draws only depend on the generator.
+    let mut period_runnables = BTreeMap::<Duration, usize>::new();
     let mut tasks = Vec::new();
 
     for _ in 0..number_runnables {
@@ -197,8 +284,8 @@ pub fn generate_tasks(number_runnables: usize) -> Vec<SimulatorTask> {
             .or_insert(1);
     }
 
-    for period in period_runnables.keys() {
-        let runnables = Runnable::new_batch(*period, period_runnables[period]);
+    for (period, number) in &period_runnables {
+        let runnables = Runnable::new_batch(*period, *number, rng);
         let l_runnables = runnables
             .iter()
             .filter(|_| rng.gen_bool(0.5))
@@ -209,50 +296,160 @@ pub fn generate_tasks(number_runnables: usize) -> Vec<SimulatorTask> {
             .filter(|r| !l_runnables.contains(r))
             .cloned()
             .collect::<Vec<Runnable>>();
+        push_tasks(
+            &mut tasks,
+            *period,
+            l_runnables,
+            h_runnables,
+            random_offsets,
+            rng,
+        );
+    }
+
+    assert!(
+        tasks
+            .iter()
+            .all(|t| t.task.props().wcet_l <= t.task.props().wcet_h),
+        "The L-mode budget of a task must not exceed its H-mode budget."
+    );
+    tasks
+}
 
-        // L-task
-        if !l_runnables.is_empty() {
-            let l_task_props = TaskProps {
-                id: Runnable::duration_to_time_unit(*period) + 1,
-                offset: 0,
-                period: Runnable::duration_to_time_unit(*period),
-                wcet_l: l_runnables
-                    .iter()
-                    .map(|r| r.wcet_l_estimate(*period, SimulatorMode::LMode))
-                    .sum::<f64>() as u64,
-                wcet_h: l_runnables.iter().map(|r| r.wcet).sum(),
-            };
-            tasks.push(SimulatorTask::new_with_runnables(
-                Task::LTask(l_task_props),
-                l_runnables,
-            ));
+/// Pushes the L-task and the H-task made of the given runnables of the same period,
+/// skipping those with no runnables.
+fn push_tasks(
+    tasks: &mut Vec<SimulatorTask>,
+    period: Duration,
+    l_runnables: Vec<Runnable>,
+    h_runnables: Vec<Runnable>,
+    random_offsets: bool,
+    rng: &mut impl Rng,
+) {
+    // L-task
+    if !l_runnables.is_empty() {
+        let l_task_props = TaskProps {
+            id: Runnable::duration_to_time_unit(period) as TaskId + 1,
+            offset: random_offset(period, random_offsets, rng),
+            period: Runnable::duration_to_time_unit(period),
+            wcet_l: wcet_l_estimate(&l_runnables, period, SimulatorMode::LMode),
+            wcet_h: l_runnables.iter().map(|r| r.wcet).sum(),
+        };
+        tasks.push(SimulatorTask::new_with_runnables(
+            Task::LTask(l_task_props),
+            l_runnables,
+        ));
+    }
+
+    // H-task
+    if !h_runnables.is_empty() {
+        let h_task_props = TaskProps {
+            id: Runnable::duration_to_time_unit(period) as TaskId,
+            offset: random_offset(period, random_offsets, rng),
+            period: Runnable::duration_to_time_unit(period),
+            wcet_l: wcet_l_estimate(&h_runnables, period, SimulatorMode::HMode),
+            wcet_h: h_runnables.iter().map(|r| r.wcet).sum(),
+        };
+        tasks.push(SimulatorTask::new_with_runnables(
+            Task::HTask(h_task_props),
+            h_runnables,
+        ));
+    }
+}
+
+/// Builds a task set from a table of measured runnables, grouped by period as in
+/// `generate_tasks`. Each line holds `period,bcet,acet,wcet[,criticality]`, with the
+/// period in milliseconds (one of the benchmark periods) and the execution times in
+/// microseconds. The criticality is `L` or `H`; runnables without one are assigned a
+/// criticality at random. Empty lines, and lines starting with `#` or a letter
+/// (e.g. a header), are skipped.
+pub fn from_runnable_csv<P: AsRef<Path>>(path: P) -> io::Result<Vec<SimulatorTask>> {
+    from_runnable_csv_reader(BufReader::new(File::open(path)?))
+}
+
+/// Same as `from_runnable_csv`, reading the table from the given reader.
+pub fn from_runnable_csv_reader(reader: impl BufRead) -> io::Result<Vec<SimulatorTask>> {
+    let invalid = |line: usize, message: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Line {}: {}", line + 1, message),
+        )
+    };
+    let rng = &mut rand::thread_rng();
+    let mut period_runnables = BTreeMap::<Duration, (Vec<Runnable>, Vec<Runnable>)>::new();
+
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(|c: char| c == '#' || c.is_alphabetic()) {
+            continue;
         }
 
-        // H-task
-        if !h_runnables.is_empty() {
-            let h_task_props = TaskProps {
-                id: Runnable::duration_to_time_unit(*period),
-                offset: 0,
-                period: Runnable::duration_to_time_unit(*period),
-                wcet_l: h_runnables
-                    .iter()
-                    .map(|r| r.wcet_l_estimate(*period, SimulatorMode::HMode))
-                    .sum::<f64>() as u64,
-                wcet_h: h_runnables.iter().map(|r| r.wcet).sum(),
-            };
-            tasks.push(SimulatorTask::new_with_runnables(
-                Task::HTask(h_task_props),
-                h_runnables,
-            ));
+        let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+        if fields.len() != 4 && fields.len() != 5 {
+            return Err(invalid(number, "expected 4 or 5 fields"));
+        }
+        let value = |field: &str| {
+            field
+                .parse::<f64>()
+                .ok()
+                .filter(|v| *v >= 0.0)
+                .ok_or_else(|| invalid(number, "invalid number"))
+        };
+        let period = Duration::from_secs_f64(value(fields[0])? / 1000.0);
+        if !RUNNABLE_PERIODS.contains(&period) {
+            return Err(invalid(number, "unsupported period"));
+        }
+        let [bcet, acet, wcet] = [fields[1], fields[2], fields[3]].map(|field| {
+            value(field).map(|v| Runnable::duration_to_time_unit(Duration::from_secs_f64(v / 1e6)))
+        });
+        let (bcet, acet, wcet) = (bcet?, acet?, wcet?);
+        if !(bcet <= acet && acet <= wcet) {
+            return Err(invalid(number, "expected bcet <= acet <= wcet"));
+        }
+
+        let is_ltask = match fields.get(4) {
+            Some(&"L") => true,
+            Some(&"H") => false,
+            Some(_) => return Err(invalid(number, "expected criticality L or H")),
+            None => rng.gen_bool(0.5),
+        };
+        let (l_runnables, h_runnables) = period_runnables.entry(period).or_default();
+        let runnable = Runnable::new(bcet, acet, wcet);
+        if is_ltask {
+            l_runnables.push(runnable);
+        } else {
+            h_runnables.push(runnable);
         }
     }
 
-    tasks
+    let mut tasks = Vec::new();
+    for (period, (l_runnables, h_runnables)) in period_runnables {
+        push_tasks(&mut tasks, period, l_runnables, h_runnables, false, rng);
+    }
+    Ok(tasks)
+}
+
+/// Sum of the WCET estimates of the runnables, clamped to the sum of their WCETs:
+/// the quantiles are estimated, and must not exceed the H-mode budget of the task.
+fn wcet_l_estimate(runnables: &[Runnable], period: Duration, mode: SimulatorMode) -> TimeUnit {
+    let estimate = runnables
+        .iter()
+        .map(|r| r.wcet_l_estimate(period, mode))
+        .sum::<f64>() as TimeUnit;
+    estimate.min(runnables.iter().map(|r| r.wcet).sum())
+}
+
+fn random_offset(period: Duration, random_offsets: bool, rng: &mut impl Rng) -> TimeUnit {
+    if random_offsets {
+        rng.gen_range(0..Runnable::duration_to_time_unit(period))
+    } else {
+        0
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::simulator::validation::feasible_schedule_design_time;
+    use crate::simulator::validation::{feasible_schedule_design_time, total_utilization};
 
     #[test]
     fn gen_tasks() {
@@ -273,12 +470,128 @@ mod tests {
                 println!("BCET: {}, WCET: {}", runnable.bcet, runnable.wcet);
             }
             for sample_nr in 0..10 {
-                println!("Sample {}: {}", sample_nr, task.sample_execution_time());
+                println!(
+                    "Sample {}: {}",
+                    sample_nr,
+                    task.sample_execution_time(&mut rand::thread_rng())
+                );
             }
             println!();
         }
     }
 
+    #[test]
+    fn gen_tasks_with_offsets() {
+        let tasks = super::generate_tasks_with_offsets(80, true);
+
+        for task in tasks {
+            assert!(task.task.props().offset < task.task.props().period);
+            assert_eq!(task.next_arrival, task.task.props().offset);
+        }
+    }
+
+    #[test]
+    fn time_scales() {
+        let duration = std::time::Duration::from_micros(1500);
+        assert_eq!(super::Runnable::duration_to_time_unit(duration), 150_000);
+        assert_eq!(
+            super::Runnable::duration_to_time_unit_with_scale(duration, 1_000_000),
+            1500
+        );
+        assert_eq!(
+            super::Runnable::duration_to_time_unit_with_scale(duration, 1_000),
+            1
+        );
+    }
+
+    #[test]
+    fn runnable_csv() {
+        let csv = "period,bcet,acet,wcet,criticality
+# 1 ms runnables
+1,2,5,10,L
+1,1,3,6,H
+1,1,2,4,H
+
+10,10,20,40,L
+";
+        let tasks = super::from_runnable_csv_reader(csv.as_bytes()).unwrap();
+        let summary = tasks
+            .iter()
+            .map(|t| {
+                (
+                    matches!(t.task, super::Task::HTask(_)),
+                    t.task.props().period,
+                    t.task.props().wcet_h,
+                    t.runnables.as_ref().unwrap().len(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (false, 100_000, 1000, 1),
+                (true, 100_000, 1000, 2),
+                (false, 1_000_000, 4000, 1),
+            ]
+        );
+        assert!(tasks
+            .iter()
+            .all(|t| t.task.props().wcet_l <= t.task.props().wcet_h));
+
+        assert!(super::from_runnable_csv_reader("3,1,2,3\n".as_bytes()).is_err());
+        assert!(super::from_runnable_csv_reader("1,3,2,1\n".as_bytes()).is_err());
+        assert!(super::from_runnable_csv_reader("1,1,2\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn wcet_l_within_wcet_h() {
+        for _ in 0..200 {
+            for task in super::generate_tasks(40) {
+                assert!(task.task.props().wcet_l <= task.task.props().wcet_h);
+            }
+        }
+    }
+
+    #[test]
+    fn feasible_tasks() {
+        let tasks = super::generate_feasible_tasks(10, 100, 42).unwrap();
+        assert!(feasible_schedule_design_time(&tasks));
+
+        // The same seed yields the same set.
+        let ids = |tasks: &[super::SimulatorTask]| {
+            tasks
+                .iter()
+                .map(|t| (t.task.props().id, t.task.props().wcet_l))
+                .collect::<Vec<_>>()
+        };
+        let again = super::generate_feasible_tasks(10, 100, 42).unwrap();
+        assert_eq!(ids(&tasks), ids(&again));
+
+        assert!(super::generate_feasible_tasks(10, 0, 42).is_none());
+    }
+
+    #[test]
+    fn benchmark_suite() {
+        let levels = [0.1, 0.2];
+        let suite = super::benchmark_suite(&levels, 2, 42);
+        assert_eq!(suite.len(), 4);
+        for (i, (utilization, tasks)) in suite.iter().enumerate() {
+            assert!((utilization - levels[i / 2]).abs() <= super::BENCHMARK_UTILIZATION_TOLERANCE);
+            assert_eq!(
+                *utilization,
+                total_utilization(tasks, crate::simulator::SimulatorMode::LMode)
+            );
+            assert!(feasible_schedule_design_time(tasks));
+        }
+
+        // The same seed yields the same suite.
+        let again = super::benchmark_suite(&levels, 2, 42);
+        assert_eq!(
+            suite.iter().map(|(u, _)| *u).collect::<Vec<_>>(),
+            again.iter().map(|(u, _)| *u).collect::<Vec<_>>()
+        );
+    }
+
     #[test]
     fn schedulable_sets() {
         let mut data = vec![];
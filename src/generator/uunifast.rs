@@ -1,9 +1,8 @@
 use rand::Rng;
 
-fn uunifast(utilization: f64, number_runnables: usize) -> Vec<f64> {
+fn uunifast(utilization: f64, number_runnables: usize, rng: &mut impl Rng) -> Vec<f64> {
     let mut u = vec![0.0; number_runnables];
     let mut t = utilization;
-    let mut rng = rand::thread_rng();
 
     for i in (1..number_runnables).rev() {
         let s = t * rng.gen::<f64>().powf(1.0 / i as f64);
@@ -34,16 +33,18 @@ pub fn runnables_acets_uunifast(
     min_acet: f64,
     max_acet: f64,
     period: f64,
+    rng: &mut impl Rng,
 ) -> Vec<f64> {
     for _ in 0..100 {
         let utilizations = uunifast(
             (avg_acet / period) * number_runnables as f64,
             number_runnables,
+            rng,
         );
         if valid_utilizations(utilizations.clone(), min_acet, max_acet, period) {
             let acets = utilizations
                 .iter()
-                .map(|u| (u * period))
+                .map(|u| u * period)
                 .collect::<Vec<f64>>();
             return acets;
         }
@@ -58,7 +59,7 @@ mod tests {
 
     #[test]
     fn test_uunifast() {
-        let u = uunifast(0.8, 5);
+        let u = uunifast(0.8, 5, &mut rand::thread_rng());
         println!("{:?}", u);
     }
 }
@@ -4,8 +4,9 @@ use std::ops::Div;
 
 #[derive(Debug, Clone)]
 pub struct RunnableWeibull {
-    weibull: Weibull,
+    weibull: Option<Weibull>, // None if the execution time is always the ACET
     bcet: f64,
+    acet: f64,
     wcet: f64,
 }
 
@@ -18,25 +19,45 @@ impl RunnableWeibull {
         let min_quantile = 0.00001;
         let max_quantile = 0.99999;
 
-        let k = Self::weibull_k(bcet, wcet, min_quantile, max_quantile);
-        let lambda = Self::weibull_lambda(bcet, acet, k);
-
-        let w = Weibull::new(k.abs(), lambda.abs()).unwrap();
+        // The fit needs ln(wcet - bcet) > 0 and a positive scale; otherwise, the range
+        // is within a time unit or the ACET is the BCET, so a point mass is as good.
+        let weibull = if wcet - bcet > 1.0 && acet > bcet {
+            let k = Self::weibull_k(bcet, wcet, min_quantile, max_quantile);
+            let lambda = Self::weibull_lambda(bcet, acet, k);
+            Some(Weibull::new(k.abs(), lambda.abs()).unwrap())
+        } else {
+            None
+        };
         RunnableWeibull {
-            weibull: w,
+            weibull,
             bcet: (bcet as u64) as f64,
+            acet,
             wcet: (wcet as u64) as f64,
         }
     }
 
     pub fn sample(&self, rng: &mut impl rand::Rng) -> f64 {
+        let Some(weibull) = &self.weibull else {
+            return self.acet.max(self.bcet).min(self.wcet);
+        };
         // statrs Weibull distribution does not directly support a location parameter.
         // We need to shift the distribution to the right by the BCET.
-        (self.weibull.sample(rng) + self.bcet)
+        (weibull.sample(rng) + self.bcet)
             .max(self.bcet)
             .min(self.wcet)
     }
 
+    /// The execution time below which a fraction `p` of the samples fall,
+    /// from the inverse of the Weibull CDF shifted by the BCET.
+    pub fn quantile(&self, p: f64) -> f64 {
+        assert!((0.0..1.0).contains(&p), "The quantile must be in [0, 1).");
+        let Some(weibull) = &self.weibull else {
+            return self.acet.max(self.bcet).min(self.wcet);
+        };
+        let x = weibull.scale() * (-(1.0 - p).ln()).powf(1.0 / weibull.shape());
+        (x + self.bcet).max(self.bcet).min(self.wcet)
+    }
+
     fn weibull_k(min: f64, max: f64, min_quantile: f64, max_quantile: f64) -> f64 {
         // ln (ln (q_min) / ln (q_max))
         // ---------------------------
@@ -79,4 +100,42 @@ mod tests {
         let avg = sum / 100000.0;
         assert_eq!((avg / 100.0).round(), (acet / 100.0).round());
     }
+
+    #[test]
+    fn quantile() {
+        let bcet: f64 = Runnable::duration_to_time_unit(Duration::from_micros(50)) as f64;
+        let acet = Runnable::duration_to_time_unit(Duration::from_micros(100)) as f64;
+        let wcet = Runnable::duration_to_time_unit(Duration::from_micros(200)) as f64;
+
+        let weibull = super::RunnableWeibull::new(bcet, acet, wcet);
+        let rng = &mut rand::thread_rng();
+        let mut samples = (0..100000)
+            .map(|_| weibull.sample(rng))
+            .collect::<Vec<f64>>();
+        samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        assert_eq!(weibull.quantile(0.0), bcet);
+        for p in [0.25, 0.5, 0.75, 0.9] {
+            let empirical = samples[(p * samples.len() as f64) as usize];
+            let analytic = weibull.quantile(p);
+            assert!((analytic - empirical).abs() / empirical < 0.01);
+        }
+    }
+
+    #[test]
+    fn degenerate() {
+        let rng = &mut rand::thread_rng();
+        for (bcet, acet, wcet) in [(4300.0, 4300.0, 4300.0), (4300.0, 4300.0, 4300.5)] {
+            let weibull = super::RunnableWeibull::new(bcet, acet, wcet);
+            assert!((0..1000).all(|_| weibull.sample(rng) == 4300.0));
+            assert_eq!(weibull.quantile(0.5), 4300.0);
+        }
+
+        // The ACET may also be the BCET of a wider range.
+        let weibull = super::RunnableWeibull::new(3700.0, 3700.0, 4600.0);
+        assert!((0..1000).all(|_| weibull.sample(rng) == 3700.0));
+
+        let runnable = Runnable::new(4300, 4300, 4300);
+        assert!((0..1000).all(|_| runnable.sample_exec_time(rng) == 4300.0));
+    }
 }
@@ -1,17 +1,65 @@
-use crate::simulator::validation::feasible_schedule_design_time;
+use crate::simulator::validation::round_to_hyperperiod;
 use agent::{
     dqn::ActivationFunction, SimulatorAgent, DEFAULT_GAMMA, DEFAULT_LEARNING_RATE,
     DEFAULT_MEM_SIZE, DEFAULT_MIN_MEM_SIZE, DEFAULT_SAMPLE_BATCH_SIZE, DEFAULT_UPDATE_FREQ,
 };
-use generator::{generate_tasks, Runnable};
-use simulator::{task::SimulatorTask, Simulator};
-use std::{cell::RefCell, io::Write, rc::Rc, sync::mpsc::channel, time::Duration};
+use generator::{generate_feasible_tasks, Runnable};
+use simulator::{
+    task::{SimulatorTask, TimeUnit, TIME_UNITS_PER_SECOND},
+    Simulator,
+};
+use std::{
+    cell::RefCell, fs::File, io::Write, path::PathBuf, rc::Rc, sync::mpsc::channel, time::Duration,
+};
 
 pub mod agent;
 pub mod generator;
 pub mod ml;
 pub mod simulator;
 
+// Task sets to generate before giving up on finding a feasible one.
+const MAX_GENERATION_ATTEMPTS: usize = 1000;
+
+/// Where the results of a tuning run are written. Sweeps running in parallel on the
+/// same machine must use different directories or run ids, not to clobber each other.
+#[derive(Debug, Clone)]
+struct ResultFiles {
+    output_dir: PathBuf,
+    run_id: Option<String>, // prefixes the name of every file
+}
+
+impl ResultFiles {
+    /// Reads `OUTPUT_DIR` (defaults to `out`) and `RUN_ID` (optional).
+    fn from_env() -> Self {
+        Self {
+            output_dir: std::env::var("OUTPUT_DIR")
+                .unwrap_or_else(|_| "out".to_string())
+                .into(),
+            run_id: std::env::var("RUN_ID").ok(),
+        }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        let file_name = match &self.run_id {
+            Some(run_id) => format!("{run_id}_{name}.txt"),
+            None => format!("{name}.txt"),
+        };
+        self.output_dir.join(file_name)
+    }
+
+    /// Creates the file with the given name, or empties it if it exists.
+    fn create(&self, name: &str) -> File {
+        std::fs::create_dir_all(&self.output_dir).unwrap();
+        let file = std::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(self.path(name))
+            .unwrap();
+        file.set_len(0).unwrap();
+        file
+    }
+}
+
 fn write_result(agent: &SimulatorAgent, file: &mut std::fs::File) {
     let contents = format!(
         "Cumulative reward: {}; mode changes to H: {}; mode changes to L: {}; task kills: {}, task starts: {}\n",
@@ -24,19 +72,26 @@ fn write_result(agent: &SimulatorAgent, file: &mut std::fs::File) {
     file.write_all(contents.as_bytes()).unwrap();
 }
 
-fn tune(tasks: Vec<SimulatorTask>) {
-    let train_instants: u64 = Runnable::duration_to_time_unit(Duration::from_secs(
+fn tune(tasks: Vec<SimulatorTask>, files: &ResultFiles) {
+    let train_instants: TimeUnit = Runnable::duration_to_time_unit(Duration::from_secs(
         std::env::var("TRAIN_INSTANTS")
             .expect("TRAIN_INSTANTS not set")
             .parse::<u64>()
             .unwrap(),
     ));
-    let test_instants: u64 = Runnable::duration_to_time_unit(Duration::from_secs(
+    let test_instants: TimeUnit = Runnable::duration_to_time_unit(Duration::from_secs(
         std::env::var("TEST_INSTANTS")
             .expect("TEST_INSTANTS not set")
             .parse::<u64>()
             .unwrap(),
     ));
+    // Cover whole hyperperiods, so that every release pattern is simulated.
+    let train_instants = round_to_hyperperiod(train_instants, &tasks);
+    let test_instants = round_to_hyperperiod(test_instants, &tasks);
+    // Training stops early once this many seconds of real time have elapsed.
+    let max_train_time = std::env::var("MAX_TRAIN_SECONDS")
+        .map(|seconds| Duration::from_secs(seconds.parse::<u64>().unwrap()))
+        .unwrap_or(Duration::MAX);
     let number_test_simulations = std::env::var("NUMBER_TEST_SIMULATIONS")
         .expect("NUMBER_TEST_SIMULATIONS not set")
         .parse::<u64>()
@@ -53,18 +108,13 @@ fn tune(tasks: Vec<SimulatorTask>) {
 
     {
         ////////// Placebo //////////
-        let mut file = std::fs::OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open("out/placebo.txt")
-            .unwrap();
-        file.set_len(0).unwrap();
+        let mut file = files.create("placebo");
         file.write_all(
             format!(
                 "parameters: NUMBER_TEST_SIMULATIONS: {}; TRAIN_INSTANTS: {}; TEST_INSTANTS: {}; NUMBER_RUNNABLES: {}\n",
                 number_test_simulations,
-                train_instants / 100000000,
-                test_instants / 100000000,
+                train_instants / TIME_UNITS_PER_SECOND,
+                test_instants / TIME_UNITS_PER_SECOND,
                 tasks.iter().map(|t| t.runnables.as_ref().unwrap().len()).sum::<usize>()
             )
             .as_bytes(),
@@ -105,6 +155,7 @@ fn tune(tasks: Vec<SimulatorTask>) {
             let tasks = tasks.clone();
             let hidden_sizes = hidden_sizes.clone();
             let tx = tx.clone();
+            let files = files.clone();
 
             pool.execute(move || {
                     let agent = Rc::new(RefCell::new(SimulatorAgent::new(
@@ -123,22 +174,18 @@ fn tune(tasks: Vec<SimulatorTask>) {
                     {
                         let mut simulator =
                             Simulator::new(tasks.clone(), true, Some(agent.clone()));
-                        simulator.fire::<false>(train_instants);
+                        simulator.fire_within(train_instants, max_train_time);
                     }
 
                     ////////// Testing //////////
                     {
-                        let mut file = std::fs::OpenOptions::new()
-                        .append(true)
-                        .create(true)
-                        .open(format!("out/test_{hyper_iteration}.txt"))
-                        .unwrap();
-                    file.set_len(0).unwrap();
+                        let mut file = files.create(&format!("test_{hyper_iteration}"));
                     file.write_all(format!("hidden sizes: {:?}; sample batch size: {}; activation function: {:?}\n", hidden_sizes, sample_batch_size, ActivationFunction::ReLU).as_bytes()).unwrap();
 
+                    let mut simulator = Simulator::new(tasks.clone(), true, Some(agent.clone()));
                     for _ in 0..number_test_simulations {
                         agent.borrow_mut().quit_training();
-                        let mut simulator = Simulator::new(tasks.clone(), true, Some(agent.clone()));
+                        simulator.reset();
                         simulator.fire::<false>(test_instants);
                         write_result(&agent.borrow(), &mut file);
                     }
@@ -154,15 +201,9 @@ fn tune(tasks: Vec<SimulatorTask>) {
 }
 
 pub fn hp_tuning(number_runnables: usize) {
-    std::fs::create_dir_all("out").unwrap();
-    loop {
-        let set = generate_tasks(number_runnables);
-        if feasible_schedule_design_time(&set) {
-            tune(set.clone());
-            return;
-        }
-        println!("Infeasible schedule, retrying...\n");
-    }
+    let set = generate_feasible_tasks(number_runnables, MAX_GENERATION_ATTEMPTS, rand::random())
+        .expect("No feasible task set found.");
+    tune(set, &ResultFiles::from_env());
 }
 
 fn main() {
@@ -173,3 +214,36 @@ fn main() {
             .unwrap(),
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::ResultFiles;
+
+    #[test]
+    fn result_files() {
+        // Unique per run, so that concurrent or earlier runs cannot interfere.
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let output_dir =
+            std::env::temp_dir().join(format!("result_files_{}_{}", std::process::id(), nanos));
+        let files = |run_id: Option<&str>| ResultFiles {
+            output_dir: output_dir.join("sweep"),
+            run_id: run_id.map(str::to_string),
+        };
+
+        files(None).create("placebo").write_all(b"old").unwrap();
+        files(Some("a")).create("test_1").write_all(b"a").unwrap();
+        files(Some("b")).create("test_1").write_all(b"b").unwrap();
+        files(None).create("placebo").write_all(b"new").unwrap();
+
+        let read = |name| std::fs::read_to_string(output_dir.join("sweep").join(name)).unwrap();
+        assert_eq!(read("placebo.txt"), "new");
+        assert_eq!(read("a_test_1.txt"), "a");
+        assert_eq!(read("b_test_1.txt"), "b");
+        std::fs::remove_dir_all(&output_dir).unwrap();
+    }
+}
@@ -0,0 +1,45 @@
+use super::{tensor::TensorStorage, ComputeModel};
+use std::collections::HashMap;
+use tch::Tensor;
+
+/// 1D convolution over inputs of shape [batch, channels, length],
+/// e.g. a sequence of event features.
+#[derive(Debug)]
+pub struct Conv1d {
+    pub params: HashMap<String, usize>,
+    stride: i64,
+}
+
+impl Conv1d {
+    pub fn new(
+        mem: &mut TensorStorage,
+        in_channels: i64,
+        out_channels: i64,
+        kernel_size: i64,
+        stride: i64,
+    ) -> Self {
+        let mut p = HashMap::new();
+        p.insert(
+            "W".to_string(),
+            mem.push(&[out_channels, in_channels, kernel_size], true),
+        );
+        p.insert("b".to_string(), mem.push(&[out_channels], true));
+        Self { params: p, stride }
+    }
+
+    pub fn weights<'a>(&self, mem: &'a TensorStorage) -> &'a Tensor {
+        mem.get(*self.params.get("W").unwrap())
+    }
+
+    pub fn bias<'a>(&self, mem: &'a TensorStorage) -> &'a Tensor {
+        mem.get(*self.params.get("b").unwrap())
+    }
+}
+
+impl ComputeModel for Conv1d {
+    fn forward(&self, mem: &TensorStorage, input: &Tensor) -> Tensor {
+        let w = mem.get(*self.params.get("W").unwrap());
+        let b = mem.get(*self.params.get("b").unwrap());
+        input.conv1d(w, Some(b), &[self.stride], &[0], &[1], 1)
+    }
+}
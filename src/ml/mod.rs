@@ -2,11 +2,18 @@ use tch::{Device, Tensor};
 
 use self::tensor::TensorStorage;
 
+pub mod conv1d;
 pub mod linear;
+pub mod sequential;
 pub mod tensor;
 
 pub const DEVICE: Device = Device::Cpu;
 
 pub trait ComputeModel {
     fn forward(&self, storage: &TensorStorage, input: &Tensor) -> Tensor;
+
+    /// Evaluates all the inputs in a single pass, returning one row of outputs per input.
+    fn forward_batch(&self, storage: &TensorStorage, inputs: &[Tensor]) -> Tensor {
+        self.forward(storage, &Tensor::stack(inputs, 0))
+    }
 }
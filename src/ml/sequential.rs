@@ -0,0 +1,68 @@
+use super::{tensor::TensorStorage, ComputeModel};
+use tch::Tensor;
+
+/// Chains layers, feeding the output of each one to the next.
+#[derive(Default)]
+pub struct Sequential {
+    layers: Vec<Box<dyn ComputeModel>>,
+}
+
+impl Sequential {
+    pub fn push(&mut self, layer: impl ComputeModel + 'static) {
+        self.layers.push(Box::new(layer));
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+}
+
+impl ComputeModel for Sequential {
+    fn forward(&self, storage: &TensorStorage, input: &Tensor) -> Tensor {
+        self.layers
+            .iter()
+            .fold(input.shallow_clone(), |o, layer| layer.forward(storage, &o))
+    }
+}
+
+/// Flattens every dimension but the batch one, e.g. to feed
+/// the output of a convolution to a linear layer.
+#[derive(Debug)]
+pub struct Flatten;
+
+impl ComputeModel for Flatten {
+    fn forward(&self, _storage: &TensorStorage, input: &Tensor) -> Tensor {
+        input.flatten(1, -1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Flatten, Sequential};
+    use crate::ml::{
+        conv1d::Conv1d, linear::LinearLayer, tensor::TensorStorage, ComputeModel, DEVICE,
+    };
+    use tch::{Kind, Tensor};
+
+    #[test]
+    fn conv1d_linear_stack() {
+        let storage = &mut TensorStorage::default();
+        let conv = Conv1d::new(storage, 2, 3, 3, 1);
+        assert!(conv.weights(storage).size() == [3, 2, 3]);
+        assert!(conv.bias(storage).size() == [3]);
+
+        // The convolution turns [4, 2, 8] into [4, 3, 6], which is flattened into [4, 18].
+        let mut model = Sequential::default();
+        model.push(conv);
+        model.push(Flatten);
+        model.push(LinearLayer::new(storage, 18, 5));
+        assert_eq!(model.len(), 3);
+
+        let input = Tensor::ones(&[4, 2, 8], (Kind::Float, DEVICE));
+        assert!(model.forward(storage, &input).size() == [4, 5]);
+    }
+}
@@ -2,6 +2,16 @@ use tch::{Kind, Tensor};
 
 use super::DEVICE;
 
+/// How gradients are clipped before the optimizer step.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum GradientClip {
+    None,
+    /// Clamp each gradient element to `[-value, value]`.
+    Value(f32),
+    /// Scale all gradients so that their global L2 norm is at most `max_norm`.
+    Norm(f32),
+}
+
 #[derive(Default, Debug)]
 pub struct TensorStorage {
     values: Vec<Tensor>,
@@ -42,19 +52,47 @@ impl TensorStorage {
         self.values[index] = value;
     }
 
-    pub fn apply_grads_sgd(&mut self, learning_rate: f32) {
-        let mut g = Tensor::new();
-        self.values.iter_mut().for_each(|t| {
-            if t.requires_grad() {
-                g = t.grad();
+    /// Discards the gradients accumulated by tensors that are not being optimized.
+    pub fn zero_grads(&mut self) {
+        self.values
+            .iter_mut()
+            .filter(|t| t.requires_grad())
+            .for_each(|t| t.zero_grad());
+    }
+
+    /// Returns the clipped gradient of each stored tensor that requires one.
+    fn clipped_grads(&self, clip: GradientClip) -> Vec<Option<Tensor>> {
+        let grads = self
+            .values
+            .iter()
+            .filter(|t| t.requires_grad())
+            .map(|t| t.grad())
+            .collect::<Vec<_>>();
+        let mut clipped = clip_gradients(&grads, clip).into_iter();
+        self.values
+            .iter()
+            .map(|t| {
+                if t.requires_grad() {
+                    clipped.next()
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn apply_grads_sgd(&mut self, learning_rate: f32, clip: GradientClip) {
+        let grads = self.clipped_grads(clip);
+        self.values.iter_mut().zip(grads).for_each(|(t, g)| {
+            if let Some(g) = g {
                 t.set_data(&(t.data() - learning_rate * &g));
                 t.zero_grad();
             }
         });
     }
 
-    pub fn apply_grads_adam(&mut self, learning_rate: f32) {
-        let mut g = Tensor::new();
+    pub fn apply_grads_adam(&mut self, learning_rate: f32, clip: GradientClip) {
+        let grads = self.clipped_grads(clip);
         const BETA: f32 = 0.9;
 
         let mut velocity = zeros(&[self.size() as i64]).split(1, 0);
@@ -64,12 +102,10 @@ impl TensorStorage {
         let mut counter = 0;
 
         self.values.iter_mut().for_each(|t| {
-            if t.requires_grad() {
-                g = t.grad();
-                g = g.clamp(-1, 1);
-                mom[counter] = BETA * &mom[counter] + (1.0 - BETA) * &g;
+            if let Some(g) = &grads[counter] {
+                mom[counter] = BETA * &mom[counter] + (1.0 - BETA) * g;
                 velocity[counter] =
-                    BETA * &velocity[counter] + (1.0 - BETA) * (&g.pow(&Tensor::from(2)));
+                    BETA * &velocity[counter] + (1.0 - BETA) * (g.pow(&Tensor::from(2)));
                 mom_corr[counter] =
                     &mom[counter] / (Tensor::from(1.0 - BETA).pow(&Tensor::from(2)));
                 vel_corr[counter] =
@@ -87,8 +123,96 @@ impl TensorStorage {
     }
 }
 
+pub fn clip_gradients(grads: &[Tensor], clip: GradientClip) -> Vec<Tensor> {
+    match clip {
+        GradientClip::None => grads.iter().map(|g| g.shallow_clone()).collect(),
+        GradientClip::Value(value) => grads.iter().map(|g| g.clamp(-value, value)).collect(),
+        GradientClip::Norm(max_norm) => {
+            let total_norm = grads
+                .iter()
+                .map(|g| g.pow_tensor_scalar(2).sum(Kind::Float).double_value(&[]))
+                .sum::<f64>()
+                .sqrt();
+            if total_norm > max_norm as f64 {
+                let scale = max_norm as f64 / total_norm;
+                grads.iter().map(|g| g * scale).collect()
+            } else {
+                grads.iter().map(|g| g.shallow_clone()).collect()
+            }
+        }
+    }
+}
+
+/// Loss minimized when training the Q-network.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum LossConfig {
+    MeanSquaredError,
+    /// Quadratic for errors up to `delta` and linear beyond, which makes it less
+    /// sensitive to outliers than the squared error.
+    Huber {
+        delta: f64,
+    },
+    L1,
+}
+
+impl LossConfig {
+    pub fn compute(&self, target: &Tensor, pred: &Tensor) -> Tensor {
+        match self {
+            LossConfig::MeanSquaredError => mean_squared_error(target, pred),
+            LossConfig::Huber { delta } => huber_loss(target, pred, *delta),
+            LossConfig::L1 => l1_loss(target, pred),
+        }
+    }
+}
+
+/// How the learning rate evolves as the optimizer takes steps.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum LearningRateSchedule {
+    Constant,
+    /// Multiplies the rate by `gamma` every `every` steps.
+    StepDecay {
+        gamma: f32,
+        every: usize,
+    },
+    /// Anneals the rate from the base to `min` along a cosine over `period` steps,
+    /// then restarts from the base (warm restart).
+    CosineAnnealing {
+        min: f32,
+        period: usize,
+    },
+}
+
+impl LearningRateSchedule {
+    pub fn rate(&self, base: f32, step: usize) -> f32 {
+        match *self {
+            LearningRateSchedule::Constant => base,
+            LearningRateSchedule::StepDecay { gamma, every } => {
+                base * gamma.powi((step / every.max(1)) as i32)
+            }
+            LearningRateSchedule::CosineAnnealing { min, period } => {
+                let period = period.max(1);
+                let cycle_step = if step == 0 {
+                    0
+                } else {
+                    (step - 1) % period + 1
+                };
+                let progress = cycle_step as f32 / period as f32;
+                min + (base - min) * (1.0 + (std::f32::consts::PI * progress).cos()) / 2.0
+            }
+        }
+    }
+}
+
 pub fn mean_squared_error(target: &Tensor, pred: &Tensor) -> Tensor {
-    pred.smooth_l1_loss(target, tch::Reduction::Mean, 0.0)
+    pred.mse_loss(target, tch::Reduction::Mean)
+}
+
+pub fn huber_loss(target: &Tensor, pred: &Tensor, delta: f64) -> Tensor {
+    pred.huber_loss(target, tch::Reduction::Mean, delta)
+}
+
+pub fn l1_loss(target: &Tensor, pred: &Tensor) -> Tensor {
+    pred.l1_loss(target, tch::Reduction::Mean)
 }
 
 pub fn cross_entropy(target: &Tensor, pred: &Tensor) -> Tensor {
@@ -105,3 +229,77 @@ pub fn accuracy(target: &Tensor, pred: &Tensor) -> f64 {
 pub fn zeros(size: &[i64]) -> Tensor {
     Tensor::zeros(size, (Kind::Float, DEVICE))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{clip_gradients, GradientClip, LearningRateSchedule, LossConfig};
+    use tch::Tensor;
+
+    #[test]
+    fn gradient_clipping() {
+        let grads = vec![
+            Tensor::from_slice(&[3.0f32, -0.5]),
+            Tensor::from_slice(&[-4.0f32]),
+        ];
+        let to_vec = |t: &Tensor| Vec::<f32>::try_from(t).unwrap();
+
+        let clipped = clip_gradients(&grads, GradientClip::None);
+        assert_eq!(to_vec(&clipped[0]), vec![3.0, -0.5]);
+        assert_eq!(to_vec(&clipped[1]), vec![-4.0]);
+
+        let clipped = clip_gradients(&grads, GradientClip::Value(1.0));
+        assert_eq!(to_vec(&clipped[0]), vec![1.0, -0.5]);
+        assert_eq!(to_vec(&clipped[1]), vec![-1.0]);
+
+        // The global norm is sqrt(9 + 0.25 + 16) = 5.025, so everything is scaled down.
+        let clipped = clip_gradients(&grads, GradientClip::Norm(1.0));
+        let scale = 1.0 / 25.25f32.sqrt();
+        let first = to_vec(&clipped[0]);
+        assert!((first[0] - 3.0 * scale).abs() < 1e-5);
+        assert!((first[1] + 0.5 * scale).abs() < 1e-5);
+        assert!((to_vec(&clipped[1])[0] + 4.0 * scale).abs() < 1e-5);
+
+        // Below the threshold, norm clipping is a no-op.
+        let clipped = clip_gradients(&grads, GradientClip::Norm(10.0));
+        assert_eq!(to_vec(&clipped[0]), vec![3.0, -0.5]);
+    }
+
+    #[test]
+    fn losses() {
+        // The errors are 1 and 3.
+        let pred = Tensor::from_slice(&[1.0f32, 4.0]);
+        let target = Tensor::from_slice(&[0.0f32, 1.0]);
+        let loss = |config: LossConfig| config.compute(&target, &pred).double_value(&[]);
+
+        assert_eq!(loss(LossConfig::MeanSquaredError), 5.0);
+        assert_eq!(loss(LossConfig::L1), 2.0);
+        // (0.5 * 1^2 + 1 * (3 - 0.5)) / 2
+        assert_eq!(loss(LossConfig::Huber { delta: 1.0 }), 1.5);
+        // (0.5 * 1^2 + 2 * (3 - 1)) / 2
+        assert_eq!(loss(LossConfig::Huber { delta: 2.0 }), 2.25);
+    }
+
+    #[test]
+    fn learning_rate_schedules() {
+        assert_eq!(LearningRateSchedule::Constant.rate(0.1, 1000), 0.1);
+
+        let step_decay = LearningRateSchedule::StepDecay {
+            gamma: 0.5,
+            every: 10,
+        };
+        assert_eq!(step_decay.rate(0.1, 0), 0.1);
+        assert_eq!(step_decay.rate(0.1, 9), 0.1);
+        assert_eq!(step_decay.rate(0.1, 10), 0.05);
+        assert_eq!(step_decay.rate(0.1, 25), 0.025);
+
+        let cosine = LearningRateSchedule::CosineAnnealing {
+            min: 0.01,
+            period: 10,
+        };
+        assert_eq!(cosine.rate(0.1, 0), 0.1);
+        assert!((cosine.rate(0.1, 5) - 0.055).abs() < 1e-6);
+        assert!((cosine.rate(0.1, 10) - 0.01).abs() < 1e-6);
+        assert!(cosine.rate(0.1, 11) > 0.09);
+        assert!((cosine.rate(0.1, 20) - 0.01).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,429 @@
+use std::{cell::RefCell, rc::Rc, sync::mpsc::channel, time::Duration};
+
+use super::{
+    task::SimulatorTask, DegradationPolicy, ExecutionTimeMode, Simulator, SimulatorEvent,
+    SimulatorMode,
+};
+use crate::{
+    agent::{
+        dqn::ActivationFunction, SimulatorAgent, DEFAULT_GAMMA, DEFAULT_LEARNING_RATE,
+        DEFAULT_MEM_SIZE, DEFAULT_MIN_MEM_SIZE, DEFAULT_SAMPLE_BATCH_SIZE, DEFAULT_UPDATE_FREQ,
+    },
+    generator::Runnable,
+    simulator::task::TimeUnit,
+};
+
+/// Parameters of one of the simulations run by `simulate_batch`.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct SimulationConfig {
+    pub duration: TimeUnit,
+    pub execution_time: ExecutionTimeMode,
+    pub degradation_policy: DegradationPolicy,
+    pub seed: Option<u64>, // seeds the sampling of execution times, for reproducible runs
+}
+
+impl SimulationConfig {
+    pub fn new(duration: TimeUnit) -> Self {
+        Self {
+            duration,
+            execution_time: ExecutionTimeMode::Random,
+            degradation_policy: DegradationPolicy::Kill,
+            seed: None,
+        }
+    }
+}
+
+/// Outcome of a simulation run by `simulate_batch`.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Metrics {
+    pub time_in_lmode: TimeUnit,
+    pub time_in_hmode: TimeUnit,
+    pub busy_time: TimeUnit,
+    pub mode_changes_to_hmode: usize,
+    pub mode_changes_to_lmode: usize,
+    pub task_kills: usize,
+    pub deadline_misses: usize,
+    pub preemptions: usize,
+}
+
+impl Metrics {
+    fn from_simulation(simulator: &Simulator, events: &[SimulatorEvent]) -> Self {
+        let count =
+            |predicate: fn(&SimulatorEvent) -> bool| events.iter().filter(|e| predicate(e)).count();
+        Self {
+            time_in_lmode: simulator.time_in_mode(SimulatorMode::LMode),
+            time_in_hmode: simulator.time_in_mode(SimulatorMode::HMode),
+            busy_time: simulator.busy_time(),
+            mode_changes_to_hmode: count(|e| {
+                matches!(e, SimulatorEvent::ModeChange(SimulatorMode::HMode, _))
+            }),
+            mode_changes_to_lmode: count(|e| {
+                matches!(e, SimulatorEvent::ModeChange(SimulatorMode::LMode, _))
+            }),
+            task_kills: count(|e| matches!(e, SimulatorEvent::TaskKill(_, _))),
+            deadline_misses: count(|e| matches!(e, SimulatorEvent::DeadlineMiss(_, _))),
+            preemptions: simulator.preemptions(),
+        }
+    }
+
+    /// Each field by name, as a float.
+    fn fields(&self) -> [(&'static str, f64); 8] {
+        [
+            ("time_in_lmode", self.time_in_lmode as f64),
+            ("time_in_hmode", self.time_in_hmode as f64),
+            ("busy_time", self.busy_time as f64),
+            ("mode_changes_to_hmode", self.mode_changes_to_hmode as f64),
+            ("mode_changes_to_lmode", self.mode_changes_to_lmode as f64),
+            ("task_kills", self.task_kills as f64),
+            ("deadline_misses", self.deadline_misses as f64),
+            ("preemptions", self.preemptions as f64),
+        ]
+    }
+}
+
+/// Statistics of a field of `Metrics` over many runs.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct FieldStats {
+    pub mean: f64,
+    pub stddev: f64, // sample standard deviation; 0 for a single run
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Accumulates the `Metrics` of many runs, to report them without external tooling.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct MetricsAggregate {
+    runs: Vec<Metrics>,
+}
+
+impl MetricsAggregate {
+    pub fn push(&mut self, metrics: &Metrics) {
+        self.runs.push(metrics.clone());
+    }
+
+    pub fn len(&self) -> usize {
+        self.runs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    /// Statistics of each field of `Metrics`, by name, in declaration order.
+    /// Must not be called before any run was pushed.
+    pub fn stats(&self) -> Vec<(&'static str, FieldStats)> {
+        assert!(!self.is_empty(), "No runs were aggregated.");
+        let n = self.runs.len() as f64;
+        let fields = self.runs.iter().map(Metrics::fields).collect::<Vec<_>>();
+        (0..fields[0].len())
+            .map(|i| {
+                let values = fields.iter().map(|f| f[i].1);
+                let mean = values.clone().sum::<f64>() / n;
+                let variance = if self.runs.len() > 1 {
+                    values.clone().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0)
+                } else {
+                    0.0
+                };
+                let stats = FieldStats {
+                    mean,
+                    stddev: variance.sqrt(),
+                    min: values.clone().fold(f64::INFINITY, f64::min),
+                    max: values.fold(f64::NEG_INFINITY, f64::max),
+                };
+                (fields[0][i].0, stats)
+            })
+            .collect()
+    }
+
+    /// One line per field, e.g. `task_kills: 3.50 ± 0.71 [3, 4]`.
+    pub fn summary(&self) -> String {
+        let mut summary = format!("Over {} runs:\n", self.len());
+        for (name, stats) in self.stats() {
+            summary.push_str(&format!(
+                "{}: {:.2} ± {:.2} [{}, {}]\n",
+                name, stats.mean, stats.stddev, stats.min, stats.max
+            ));
+        }
+        summary
+    }
+}
+
+impl FromIterator<Metrics> for MetricsAggregate {
+    fn from_iter<T: IntoIterator<Item = Metrics>>(iter: T) -> Self {
+        Self {
+            runs: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// Runs one agentless simulation of the task set per config, spread across `threads` workers.
+/// Since the simulator state is not `Send`, it is built from scratch inside each worker.
+/// The metrics are returned in the same order as the configs.
+pub fn simulate_batch(
+    task_set: &[SimulatorTask],
+    configs: &[SimulationConfig],
+    threads: usize,
+) -> Vec<Metrics> {
+    let pool = threadpool::ThreadPool::new(threads.max(1));
+    let (tx, rx) = channel::<(usize, Metrics)>();
+
+    for (index, config) in configs.iter().enumerate() {
+        let tasks = task_set.to_vec();
+        let config = *config;
+        let tx = tx.clone();
+
+        pool.execute(move || {
+            let mut simulator = Simulator::new(tasks, config.execution_time, None);
+            simulator.degradation_policy = config.degradation_policy;
+            if let Some(seed) = config.seed {
+                simulator.set_seed(seed);
+            }
+            let (_, events) = simulator.fire::<false>(config.duration);
+            tx.send((index, Metrics::from_simulation(&simulator, &events)))
+                .unwrap();
+        });
+    }
+    drop(tx);
+
+    let mut metrics = vec![Metrics::default(); configs.len()];
+    for (index, run_metrics) in rx.iter() {
+        metrics[index] = run_metrics;
+    }
+    metrics
+}
+
+/// Parameters of the runs of `compare_policies`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ComparisonConfig {
+    pub train_duration: TimeUnit,
+    pub test_duration: TimeUnit,
+    pub agent_period: TimeUnit,
+    pub hidden_sizes: Vec<usize>,
+}
+
+impl ComparisonConfig {
+    pub fn new(train_duration: TimeUnit, test_duration: TimeUnit) -> Self {
+        Self {
+            train_duration,
+            test_duration,
+            agent_period: Runnable::duration_to_time_unit(Duration::from_millis(10)),
+            hidden_sizes: vec![8],
+        }
+    }
+}
+
+/// Outcome of the test runs of a policy, one entry per seed.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct PolicyRuns {
+    pub metrics: Vec<Metrics>,
+    pub rewards: Vec<f64>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ComparisonReport {
+    pub agent: PolicyRuns,
+    pub placebo: PolicyRuns,      // AMC without any WCET changes
+    pub always_hmode: PolicyRuns, // L-tasks never run, H-tasks always get wcet_h
+}
+
+/// Tests a freshly trained agent against the placebo and always H-mode baselines, for each seed.
+/// All runs with the same seed sample the same execution times, so that the comparison is fair.
+/// The baselines are also observed by a placebo agent, so that their rewards are comparable.
+pub fn compare_policies(
+    task_set: &[SimulatorTask],
+    seeds: &[u64],
+    config: &ComparisonConfig,
+) -> ComparisonReport {
+    let new_agent = || {
+        Rc::new(RefCell::new(SimulatorAgent::new(
+            DEFAULT_MEM_SIZE,
+            DEFAULT_MIN_MEM_SIZE,
+            DEFAULT_GAMMA,
+            DEFAULT_UPDATE_FREQ,
+            DEFAULT_LEARNING_RATE,
+            config.hidden_sizes.clone(),
+            DEFAULT_SAMPLE_BATCH_SIZE,
+            ActivationFunction::ReLU,
+            task_set,
+        )))
+    };
+    let new_simulator = |agent: &Rc<RefCell<SimulatorAgent>>, seed| {
+        let mut simulator = Simulator::new(task_set.to_vec(), true, Some(agent.clone()));
+        simulator.agent_period = config.agent_period;
+        simulator.set_seed(seed);
+        simulator
+    };
+    let test =
+        |runs: &mut PolicyRuns, simulator: &mut Simulator, agent: &Rc<RefCell<SimulatorAgent>>| {
+            let (_, events) = simulator.fire::<false>(config.test_duration);
+            runs.metrics
+                .push(Metrics::from_simulation(simulator, &events));
+            runs.rewards.push(agent.borrow().cumulative_reward());
+        };
+
+    let mut report = ComparisonReport::default();
+    for &seed in seeds {
+        let agent = new_agent();
+        let mut simulator = new_simulator(&agent, seed);
+        simulator.fire::<false>(config.train_duration);
+        agent.borrow_mut().quit_training();
+        simulator.reset();
+        test(&mut report.agent, &mut simulator, &agent);
+
+        for (initial_mode, runs) in [
+            (SimulatorMode::LMode, &mut report.placebo),
+            (SimulatorMode::HMode, &mut report.always_hmode),
+        ] {
+            let agent = new_agent();
+            agent.borrow_mut().placebo_mode();
+            let mut simulator = new_simulator(&agent, seed);
+            simulator.initial_mode = initial_mode;
+            test(runs, &mut simulator, &agent);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        compare_policies, simulate_batch, ComparisonConfig, FieldStats, Metrics, MetricsAggregate,
+        SimulationConfig,
+    };
+    use crate::{
+        generator::Runnable,
+        simulator::task::{SimulatorTask, Task, TaskProps},
+    };
+
+    fn tasks() -> Vec<SimulatorTask> {
+        let task = |id, period, runnables| {
+            SimulatorTask::new_with_runnables(
+                Task::HTask(TaskProps {
+                    id,
+                    wcet_l: 30,
+                    wcet_h: 60,
+                    offset: 0,
+                    period,
+                }),
+                runnables,
+            )
+        };
+        vec![
+            task(1, 200, vec![Runnable::new(10, 20, 60)]),
+            task(
+                2,
+                300,
+                vec![Runnable::new(5, 10, 30), Runnable::new(5, 10, 30)],
+            ),
+        ]
+    }
+
+    #[test]
+    fn deterministic_batch() {
+        let configs = (0..8)
+            .map(|seed| SimulationConfig {
+                seed: Some(seed),
+                ..SimulationConfig::new(3000)
+            })
+            .collect::<Vec<_>>();
+
+        let metrics = simulate_batch(&tasks(), &configs, 4);
+        assert_eq!(metrics.len(), configs.len());
+        assert!(metrics
+            .iter()
+            .all(|m| m.time_in_lmode + m.time_in_hmode >= 3000));
+        assert!(metrics.iter().all(|m| m.busy_time > 0));
+
+        // Seeded runs are reproducible, regardless of how they are scheduled.
+        assert_eq!(simulate_batch(&tasks(), &configs, 2), metrics);
+        assert_eq!(simulate_batch(&tasks(), &configs[3..4], 1), metrics[3..4]);
+    }
+
+    #[test]
+    fn degenerate_comparison() {
+        // With two tasks, doing nothing is the only action of the agent, and
+        // the H-tasks never exceed their budgets, so no policy can make a difference.
+        let task = |id, period| {
+            SimulatorTask::new(
+                Task::HTask(TaskProps {
+                    id,
+                    wcet_l: 40,
+                    wcet_h: 40,
+                    offset: 0,
+                    period,
+                }),
+                20,
+                10,
+            )
+        };
+        let tasks = vec![task(1, 200), task(2, 300)];
+        let config = ComparisonConfig {
+            agent_period: 50,
+            ..ComparisonConfig::new(3000, 3000)
+        };
+
+        let report = compare_policies(&tasks, &[1, 2, 3], &config);
+        assert_eq!(report.agent, report.placebo);
+        assert_eq!(report.always_hmode.rewards, report.placebo.rewards);
+        for (always_hmode, placebo) in report
+            .always_hmode
+            .metrics
+            .iter()
+            .zip(&report.placebo.metrics)
+        {
+            assert_eq!(always_hmode.time_in_lmode, 0);
+            assert_eq!(always_hmode.time_in_hmode, placebo.time_in_lmode);
+            assert_eq!(
+                super::Metrics {
+                    time_in_lmode: 0,
+                    time_in_hmode: 0,
+                    ..always_hmode.clone()
+                },
+                super::Metrics {
+                    time_in_lmode: 0,
+                    time_in_hmode: 0,
+                    ..placebo.clone()
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn metrics_aggregate() {
+        let run = |task_kills, preemptions| Metrics {
+            busy_time: 100,
+            task_kills,
+            preemptions,
+            ..Metrics::default()
+        };
+        let aggregate = [run(2, 10), run(4, 20), run(4, 30), run(6, 40)]
+            .into_iter()
+            .collect::<MetricsAggregate>();
+        assert_eq!(aggregate.len(), 4);
+
+        let stats = aggregate.stats();
+        assert_eq!(stats.len(), 8);
+        let field = |name| stats.iter().find(|(n, _)| *n == name).unwrap().1;
+        assert_eq!(
+            field("busy_time"),
+            FieldStats {
+                mean: 100.0,
+                stddev: 0.0,
+                min: 100.0,
+                max: 100.0
+            }
+        );
+        let task_kills = field("task_kills");
+        assert_eq!(task_kills.mean, 4.0);
+        assert!((task_kills.stddev - (8.0f64 / 3.0).sqrt()).abs() < 1e-9);
+        assert_eq!((task_kills.min, task_kills.max), (2.0, 6.0));
+        assert_eq!(field("preemptions").mean, 25.0);
+        assert!(aggregate
+            .summary()
+            .contains("task_kills: 4.00 ± 1.63 [2, 6]"));
+
+        let mut single = MetricsAggregate::default();
+        single.push(&run(3, 0));
+        assert_eq!(single.stats()[5].1.stddev, 0.0);
+    }
+}
@@ -1,13 +1,13 @@
 use std::{cell::RefCell, rc::Rc};
 
 use crate::{
-    agent::SimulatorActionPart,
+    agent::{apply_action, revert_action, ActionOutcome, SimulatorAction, SimulatorActionPart},
     simulator::{validation::feasible_schedule_online, EndReason},
 };
 
 use super::{
     task::{SimulatorTask, Task, TimeUnit},
-    Simulator, SimulatorEvent, SimulatorJob, SimulatorMode,
+    DegradationPolicy, ExecutionTimeMode, Simulator, SimulatorEvent, SimulatorJob, SimulatorMode,
 };
 
 pub fn handle_start_event(
@@ -15,6 +15,16 @@ pub fn handle_start_event(
     _time: TimeUnit,
     simulator: &mut Simulator,
 ) {
+    simulator.push_event(Rc::new(RefCell::new(SimulatorEvent::Release(
+        task.clone(),
+        simulator.now,
+    ))));
+
+    let id = task.borrow().task.props().id;
+    let releases = simulator.releases.entry(id).or_insert(0);
+    *releases += 1;
+    let job_index = *releases - 1;
+
     // Update the time of the next arrival
     let period = task.borrow().task.props().period;
     task.borrow_mut().next_arrival += period;
@@ -26,35 +36,33 @@ pub fn handle_start_event(
     //     job.borrow().task.borrow().task.props().id,
     //     time
     // );
-    if job.borrow().is_agent {
-        // println!(
-        //     "Scheduling agent at instant: {}, id: {}",
-        //     time,
-        //     job.borrow().task.borrow().task.props().id
-        // );
-    }
-    let next_exec_time = if simulator.random_execution_time {
-        task.borrow().sample_execution_time()
-    } else {
-        task.borrow().acet
+    let original_id = simulator.original_ids[&id];
+    let next_exec_time = match (
+        simulator.replayed_trace.get(&(original_id, job_index)),
+        simulator.execution_time,
+    ) {
+        (Some(exec_time), _) => *exec_time,
+        (None, ExecutionTimeMode::Random) => {
+            task.borrow().sample_execution_time(&mut simulator.rng)
+        }
+        (None, ExecutionTimeMode::Average) => task.borrow().acet,
+        (None, ExecutionTimeMode::Worst(mode)) => task.borrow().task.props().wcet_in_mode(mode),
     };
+    simulator
+        .execution_trace
+        .push((original_id, job_index, next_exec_time));
     job.borrow_mut().exec_time = next_exec_time;
     job.borrow_mut().run_time = 0;
 
-    // Context switch or add to the queue
-    if simulator.running_job.is_none()
-        || job.borrow().task.borrow().task.props().id
-            < simulator
-                .running_job
-                .as_ref()
-                .unwrap()
-                .borrow()
-                .task
-                .borrow()
-                .task
-                .props()
-                .id
-    {
+    // Context switch or add to the queue.
+    // Simultaneous arrivals are popped from the event queue in priority order.
+    let dispatch = match &simulator.running_job {
+        None => true,
+        Some(running) => simulator
+            .scheduler
+            .should_preempt(&running.borrow().task.borrow(), &job.borrow().task.borrow()),
+    };
+    if dispatch {
         context_switch(job.clone(), simulator);
     } else {
         simulator.ready_jobs_queue.push(job.clone());
@@ -65,6 +73,68 @@ pub fn handle_start_event(
     }
 }
 
+pub fn handle_agent_tick_event(time: TimeUnit, simulator: &mut Simulator) {
+    let activation_start = std::time::Instant::now();
+
+    // Activate the agent
+    if let Some(agent) = simulator.agent.take() {
+        // println!("Agent is running. instant={}", simulator.now);
+        agent.borrow_mut().activate(simulator);
+        simulator.memory_usage.push((
+            agent.borrow().replay_memory_size(),
+            agent.borrow().events_history_len(),
+        ));
+        simulator.agent = Some(agent);
+    }
+
+    // Apply its actions
+    if let Some(action) = simulator.pending_agent_action.clone() {
+        //println!("Applying agent's actions");
+        apply_agent_action(action, simulator);
+    }
+    if simulator.agent.is_some() {
+        simulator.agent_times.push(activation_start.elapsed());
+    }
+
+    // Schedule the next activation
+    let next_tick_event = Rc::new(RefCell::new(SimulatorEvent::AgentTick(
+        time + simulator.agent_period,
+    )));
+    simulator.event_queue.push(next_tick_event);
+}
+
+/// Applies the action to the task set, reverting it if the schedule becomes unfeasible,
+/// and notifies the agent of the outcome.
+pub fn apply_agent_action(action: SimulatorAction, simulator: &mut Simulator) -> ActionOutcome {
+    let magnitudes = simulator
+        .agent
+        .as_ref()
+        .map(|agent| agent.borrow().action_magnitudes())
+        .unwrap_or_default();
+    let applied = apply_action(&action, &mut simulator.tasks, magnitudes);
+
+    let feasible = |simulator: &Simulator| match &simulator.agent {
+        Some(agent) => agent.borrow().feasible_online(&simulator.tasks, simulator),
+        None => feasible_schedule_online(&simulator.tasks, &simulator.cached_response_times),
+    };
+    let no_op = action
+        .iter()
+        .all(|part| matches!(part, SimulatorActionPart::None));
+    let outcome = if no_op || feasible(simulator) {
+        //println!("Applied action {:?}", action);
+        ActionOutcome::Applied
+    } else {
+        //println!("Invalid action {:?}, reverting.", action);
+        revert_action(&action, &applied, &mut simulator.tasks);
+        ActionOutcome::Reverted
+    };
+
+    if let Some(agent) = &simulator.agent {
+        agent.borrow_mut().notify_action(&action, outcome);
+    }
+    outcome
+}
+
 pub fn handle_end_event(
     task: Rc<RefCell<SimulatorTask>>,
     time: TimeUnit,
@@ -77,6 +147,33 @@ pub fn handle_end_event(
         reason,
     ))));
 
+    // Deadlines are implicit, so the deadline of this job is the next arrival of its task.
+    if matches!(reason, EndReason::JobCompletion) {
+        let arrival = task.borrow().next_arrival - task.borrow().task.props().period;
+        simulator
+            .response_times
+            .entry(task.borrow().task.props().id)
+            .or_default()
+            .push(time - arrival);
+    }
+    if matches!(reason, EndReason::JobCompletion) && time > task.borrow().next_arrival {
+        *simulator
+            .deadline_misses
+            .entry(task.borrow().task.props().id)
+            .or_insert(0) += 1;
+        simulator.push_event(Rc::new(RefCell::new(SimulatorEvent::DeadlineMiss(
+            task.clone(),
+            time,
+        ))));
+    }
+
+    // Degrade the L-task before deciding on its next arrival
+    let is_ltask = matches!(task.borrow().task, Task::LTask(_));
+    if matches!(reason, EndReason::BudgetExceedance) && is_ltask {
+        degrade_ltask(task.clone(), simulator);
+    }
+    let next_release = simulator.release_time(&task);
+
     let job = simulator.jobs.get(&task.borrow().task.props().id).unwrap();
     // println!(
     //     "Handling end event for task: {}; instant: {}",
@@ -84,28 +181,6 @@ pub fn handle_end_event(
     //     time
     // );
 
-    // If this is the agent, apply its actions
-    if job.borrow().is_agent && simulator.pending_agent_action.is_some() {
-        //println!("Applying agent's actions");
-        let action_parts = simulator
-            .pending_agent_action
-            .map_or(vec![SimulatorActionPart::None], |(a, b, c)| vec![a, b, c]);
-        action_parts
-            .iter()
-            .for_each(|a| a.apply(&mut simulator.tasks));
-        if !matches!(action_parts[0], SimulatorActionPart::None) {
-            if !feasible_schedule_online(&simulator.tasks, &simulator.cached_response_times) {
-                //println!("Invalid action {:?}, reverting.", action_parts);
-                let reverse_action = action_parts.iter().map(|a| a.reverse()).collect::<Vec<_>>();
-                reverse_action
-                    .iter()
-                    .for_each(|a| a.apply(&mut simulator.tasks));
-            } else {
-                //println!("Applied action {:?}", action_parts);
-            }
-        }
-    }
-
     // Push exec time to the agent
     if let Some(agent) = &simulator.agent {
         agent
@@ -116,7 +191,7 @@ pub fn handle_end_event(
     // Schedule the arrival of the next job of the same task
     let new_start_event = Rc::new(RefCell::new(SimulatorEvent::Start(
         job.borrow().task.clone(),
-        std::cmp::max(simulator.now, task.borrow().next_arrival),
+        next_release,
     )));
     simulator.event_queue.push(new_start_event.clone());
     // println!(
@@ -127,36 +202,61 @@ pub fn handle_end_event(
 
     // Update runtime
     job.borrow_mut().run_time += simulator.now - simulator.last_context_switch;
+    let run_time = job.borrow().run_time;
 
     // Set running job to None
     simulator.running_job = None;
 
     // Budget exceedance handling
-    if matches!(reason, EndReason::BudgetExceedance) {
-        let is_ltask = matches!(job.borrow().task.borrow().task, Task::LTask(_));
-
-        if is_ltask {
-            // println!(
-            //     "Killing task: {}",
-            //     job.borrow().task.borrow().task.props().id
-            // );
-            simulator.push_event(Rc::new(RefCell::new(SimulatorEvent::TaskKill(
-                task.clone(),
-                simulator.now,
-            ))));
-        } else {
-            change_mode(SimulatorMode::HMode, simulator);
+    // The job ran for its whole H-mode budget and did not finish.
+    let critical_overrun = matches!(reason, EndReason::BudgetExceedance)
+        && !is_ltask
+        && run_time >= task.borrow().task.props().wcet_h;
+    if critical_overrun {
+        simulator.push_event(Rc::new(RefCell::new(SimulatorEvent::CriticalOverrun(
+            task.clone(),
+            time,
+        ))));
+    } else if matches!(reason, EndReason::BudgetExceedance) && !is_ltask {
+        // The next arrival was already scheduled, so the job was released one period ago.
+        let release = task.borrow().next_arrival - task.borrow().task.props().period;
+        simulator
+            .mode_change_offsets
+            .push((task.borrow().task.props().id, time - release));
+        let level = task.borrow().criticality_level;
+        if level > simulator.criticality_level {
+            change_mode(level, simulator);
         }
     }
 
     if simulator.ready_jobs_queue.is_empty() {
         // Idle handling
         match simulator.mode {
-            SimulatorMode::LMode => (),
-            SimulatorMode::HMode => change_mode(SimulatorMode::LMode, simulator),
+            SimulatorMode::HMode if simulator.initial_mode == SimulatorMode::LMode => {
+                if simulator.lmode_return_delay == 0 {
+                    change_mode(0, simulator)
+                } else {
+                    // Return later, unless some H-job runs in the meantime.
+                    simulator.hmode_idle_since = time;
+                    simulator
+                        .event_queue
+                        .push(Rc::new(RefCell::new(SimulatorEvent::ModeChange(
+                            SimulatorMode::LMode,
+                            time + simulator.lmode_return_delay,
+                        ))));
+                }
+            }
+            _ => (),
         }
     } else {
-        let job = simulator.ready_jobs_queue.pop().unwrap();
+        let ready = simulator
+            .ready_jobs_queue
+            .iter()
+            .map(|job| job.borrow().task.clone())
+            .collect::<Vec<_>>();
+        let job = simulator
+            .ready_jobs_queue
+            .remove(simulator.scheduler.select_next(&ready));
         // println!(
         //     "Popped job from ready queue: {}",
         //     job.borrow().task.borrow().task.props().id
@@ -165,19 +265,41 @@ pub fn handle_end_event(
     }
 }
 
-fn run_job(job: Rc<RefCell<SimulatorJob>>, simulator: &mut Simulator) {
-    // TODO: Right now, we are applying agent's actions immediately.
-    // We should change this to apply the agent's actions at the end of the time slice.
-
-    // TODO: Memory usage and time usage should be updated here.
-
-    if job.borrow().is_agent && simulator.agent.is_some() {
-        let agent = simulator.agent.take().unwrap();
-        // println!("Agent is running. instant={}", simulator.now);
-        agent.borrow_mut().activate(simulator);
-        simulator.agent = Some(agent);
+/// Returns to L-mode if the processor stayed idle in H-mode since the return was scheduled.
+pub fn handle_lmode_return_event(time: TimeUnit, simulator: &mut Simulator) {
+    let idle = simulator.running_job.is_none() && simulator.ready_jobs_queue.is_empty();
+    if simulator.mode == SimulatorMode::HMode
+        && idle
+        && time == simulator.hmode_idle_since + simulator.lmode_return_delay
+    {
+        change_mode(0, simulator);
     }
+}
+
+fn degrade_ltask(task: Rc<RefCell<SimulatorTask>>, simulator: &mut Simulator) {
+    // println!("Degrading task: {}", task.borrow().task.props().id);
+    let event = match simulator.degradation_policy {
+        DegradationPolicy::Kill => SimulatorEvent::TaskKill(task.clone(), simulator.now),
+        DegradationPolicy::SkipJob => {
+            let period = task.borrow().task.props().period;
+            task.borrow_mut().next_arrival += period;
+            SimulatorEvent::JobSkip(task.clone(), simulator.now)
+        }
+        DegradationPolicy::StretchPeriod { factor } => {
+            let period = task.borrow().task.props().period;
+            let stretched_period = (period as f64 * factor) as TimeUnit;
+            task.borrow_mut().task.props_mut().period = stretched_period;
+
+            // The next arrival was computed with the previous period.
+            let next_arrival = task.borrow().next_arrival;
+            task.borrow_mut().next_arrival = next_arrival - period + stretched_period;
+            SimulatorEvent::PeriodStretch(task.clone(), simulator.now)
+        }
+    };
+    simulator.push_event(Rc::new(RefCell::new(event)));
+}
 
+fn run_job(job: Rc<RefCell<SimulatorJob>>, simulator: &mut Simulator) {
     // println!(
     //     "Running job: {}",
     //     job.borrow().task.borrow().task.props().id
@@ -194,12 +316,17 @@ fn context_switch(job: Rc<RefCell<SimulatorJob>>, simulator: &mut Simulator) {
         ))));
     }
 
-    if let Some(running_job) = &simulator.running_job {
+    if let Some(running_job) = simulator.running_job.clone() {
         // Cancel the termination event of the running_job (in the event queue)
-        simulator.event_queue.retain(|event| {
-            event.borrow().task().borrow().task.props().id
-                != running_job.borrow().task.borrow().task.props().id
-        });
+        simulator
+            .event_queue
+            .retain(|event| match &*event.borrow() {
+                SimulatorEvent::AgentTick(_) | SimulatorEvent::ModeChange(_, _) => true,
+                event => {
+                    event.task().borrow().task.props().id
+                        != running_job.borrow().task.borrow().task.props().id
+                }
+            });
 
         // Update the run time of the running_job
         running_job.borrow_mut().run_time += simulator.now - simulator.last_context_switch;
@@ -209,6 +336,13 @@ fn context_switch(job: Rc<RefCell<SimulatorJob>>, simulator: &mut Simulator) {
             panic!("Schedule is not feasible");
         }
 
+        simulator.preemptions += 1;
+        simulator.push_event(Rc::new(RefCell::new(SimulatorEvent::Preemption(
+            running_job.borrow().task.clone(),
+            job.borrow().task.clone(),
+            simulator.now,
+        ))));
+
         // Add the running_job to the ready jobs queue
         simulator.ready_jobs_queue.push(running_job.clone());
         // println!(
@@ -231,13 +365,23 @@ fn context_switch(job: Rc<RefCell<SimulatorJob>>, simulator: &mut Simulator) {
 }
 
 fn schedule_termination_event(job: &mut SimulatorJob, simulator: &mut Simulator) {
-    let (termination_time, reason) = if simulator.mode == SimulatorMode::LMode
+    // Without mixed criticality, only L-tasks have their budget enforced.
+    let budget_enforced =
+        simulator.mixed_criticality || matches!(job.task.borrow().task, Task::LTask(_));
+    let (termination_time, reason) = if !simulator.escalated(&job.task.borrow())
+        && budget_enforced
         && job.exec_time > job.task.borrow().task.props().wcet_l
     {
         (
             simulator.now + job.task.borrow().task.props().wcet_l - job.run_time,
             EndReason::BudgetExceedance,
         )
+    } else if job.exec_time > job.task.borrow().task.props().wcet_h {
+        // Even the H-mode budget is not enough: the job is aborted at its wcet_h.
+        (
+            simulator.now + job.task.borrow().task.props().wcet_h - job.run_time,
+            EndReason::BudgetExceedance,
+        )
     } else {
         (
             simulator.now + job.exec_time - job.run_time,
@@ -260,23 +404,33 @@ fn schedule_termination_event(job: &mut SimulatorJob, simulator: &mut Simulator)
     // );
 }
 
-fn change_mode(to_mode: SimulatorMode, simulator: &mut Simulator) {
+/// Escalates to the given criticality level, or returns to L-mode (level 0).
+/// Every level above 0 is in H-mode.
+fn change_mode(to_level: u8, simulator: &mut Simulator) {
+    let to_mode = if to_level == 0 {
+        SimulatorMode::LMode
+    } else {
+        SimulatorMode::HMode
+    };
     // println!("Changing mode to {:?}", to_mode);
 
+    simulator.advance_time_in_mode();
+    let from_level = simulator.criticality_level;
     simulator.mode = to_mode;
+    simulator.criticality_level = to_level;
     simulator.push_event(Rc::new(RefCell::new(SimulatorEvent::ModeChange(
         to_mode,
         simulator.now,
     ))));
 
-    if simulator.mode == SimulatorMode::LMode {
-        // Schedule the arrival of L-tasks.
+    if to_level < from_level {
+        // Schedule the arrival of the tasks dispensed with.
         //   println!("Scheduling L-tasks");
-        for task in simulator.tasks.iter() {
-            if let Task::LTask(_) = task.borrow().task {
+        for task in simulator.tasks.clone() {
+            if task.borrow().criticality_level < from_level && !simulator.dropped(&task.borrow()) {
                 let start_event = Rc::new(RefCell::new(SimulatorEvent::Start(
                     task.clone(),
-                    std::cmp::max(simulator.now, task.borrow().next_arrival),
+                    simulator.release_time(&task),
                 )));
                 simulator.event_queue.push(start_event);
                 // println!(
@@ -286,13 +440,25 @@ fn change_mode(to_mode: SimulatorMode, simulator: &mut Simulator) {
             }
         }
     } else {
-        // Dispense with the remaining L-tasks.
+        // Dispense with the remaining tasks below the new level.
         //  println!("Dispensing with L-tasks");
+        let tasks = simulator
+            .tasks
+            .iter()
+            .filter(|task| simulator.dropped(&task.borrow()))
+            .map(|task| task.borrow().task.props().id)
+            .collect::<Vec<_>>();
+        let dropped =
+            |task: &Rc<RefCell<SimulatorTask>>| tasks.contains(&task.borrow().task.props().id);
         simulator
             .event_queue
-            .retain(|event| matches!(event.borrow().task().borrow().task, Task::HTask(_)));
+            .retain(|event| match &*event.borrow() {
+                SimulatorEvent::AgentTick(_) => true,
+                SimulatorEvent::ModeChange(_, _) => false,
+                event => !dropped(&event.task()),
+            });
         simulator
             .ready_jobs_queue
-            .retain(|job| matches!(job.borrow().task.borrow().task, Task::HTask(_)));
+            .retain(|job| !dropped(&job.borrow().task));
     }
 }
@@ -1,21 +1,29 @@
-use task::TaskProps;
-use validation::response_time;
+use validation::{response_time, validate_task_set, AnalysisError, TaskSetError};
 
-use self::task::{SimulatorTask, TaskId, TimeUnit};
+use self::{
+    scheduler::{RateMonotonic, Scheduler},
+    task::{SimulatorTask, TaskId, TimeUnit},
+};
 use crate::{
     agent::{SimulatorAction, SimulatorAgent},
     generator::Runnable,
 };
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize, Serializer};
 use std::{
     cell::RefCell,
-    collections::{BinaryHeap, HashMap},
+    collections::{BTreeMap, BinaryHeap, HashMap},
+    io::{self, Write},
     rc::Rc,
     time,
 };
 
+pub mod batch;
 pub mod handlers;
+pub mod scheduler;
 pub mod task;
 pub mod validation;
+pub mod verification;
 
 const MAX_TASKS_SIZE: usize = 1000;
 
@@ -25,7 +33,6 @@ struct SimulatorJob {
     exec_time: TimeUnit,
     run_time: TimeUnit,
     event: Rc<RefCell<SimulatorEvent>>,
-    is_agent: bool,
 }
 
 impl PartialEq for SimulatorJob {
@@ -36,42 +43,69 @@ impl PartialEq for SimulatorJob {
 
 impl Eq for SimulatorJob {}
 
-impl Ord for SimulatorJob {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.task
-            .borrow()
-            .task
-            .props()
-            .id
-            .cmp(&other.task.borrow().task.props().id)
-            .reverse()
-    }
-}
-
-impl PartialOrd for SimulatorJob {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-#[derive(Debug, PartialEq, Copy, Clone)]
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
 pub enum SimulatorMode {
     LMode,
     HMode,
 }
 
+/// How long each job runs, unless replayed from a trace (see `Simulator::with_execution_trace`).
 #[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ExecutionTimeMode {
+    /// Sampled from the distribution of the task.
+    Random,
+    /// The ACET of the task.
+    Average,
+    /// The budget of the task in the given mode, for a fully pessimistic run.
+    Worst(SimulatorMode),
+}
+
+/// The former flag of the constructors: random execution times, or else average ones.
+impl From<bool> for ExecutionTimeMode {
+    fn from(random: bool) -> Self {
+        if random {
+            Self::Random
+        } else {
+            Self::Average
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
 pub enum EndReason {
     JobCompletion,
     BudgetExceedance,
 }
 
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum DegradationPolicy {
+    // The overrunning job is aborted; the next releases are kept.
+    Kill,
+    // The overrunning job is aborted and the following release is skipped,
+    // giving the remaining tasks some slack to recover.
+    SkipJob,
+    // The overrunning job is aborted and the task's period is multiplied
+    // by the given factor (elastic task model).
+    StretchPeriod { factor: f64 },
+}
+
 #[derive(Debug, Clone)]
 pub enum SimulatorEvent {
-    Start(Rc<RefCell<SimulatorTask>>, TimeUnit),
+    Start(Rc<RefCell<SimulatorTask>>, TimeUnit), // in the history, when the job first runs
+    Release(Rc<RefCell<SimulatorTask>>, TimeUnit), // a job became ready, after any jitter
     End(Rc<RefCell<SimulatorTask>>, TimeUnit, EndReason),
     TaskKill(Rc<RefCell<SimulatorTask>>, TimeUnit),
-    ModeChange(SimulatorMode, TimeUnit),
+    JobSkip(Rc<RefCell<SimulatorTask>>, TimeUnit),
+    PeriodStretch(Rc<RefCell<SimulatorTask>>, TimeUnit),
+    DeadlineMiss(Rc<RefCell<SimulatorTask>>, TimeUnit),
+    CriticalOverrun(Rc<RefCell<SimulatorTask>>, TimeUnit), // an H-job exceeded its wcet_h
+    Preemption(
+        Rc<RefCell<SimulatorTask>>,
+        Rc<RefCell<SimulatorTask>>,
+        TimeUnit,
+    ), // (preempted, by)
+    ModeChange(SimulatorMode, TimeUnit), // in the event queue, a delayed return to L-mode
+    AgentTick(TimeUnit),
 }
 
 impl SimulatorEvent {
@@ -83,14 +117,143 @@ impl SimulatorEvent {
     }
 }
 
+/// Serialized form of a `SimulatorEvent`, where tasks are referenced by id.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum SimulatorEventRecord {
+    Start {
+        task: TaskId,
+        time: TimeUnit,
+    },
+    Release {
+        task: TaskId,
+        time: TimeUnit,
+    },
+    End {
+        task: TaskId,
+        time: TimeUnit,
+        reason: EndReason,
+    },
+    TaskKill {
+        task: TaskId,
+        time: TimeUnit,
+    },
+    JobSkip {
+        task: TaskId,
+        time: TimeUnit,
+    },
+    PeriodStretch {
+        task: TaskId,
+        time: TimeUnit,
+    },
+    DeadlineMiss {
+        task: TaskId,
+        time: TimeUnit,
+    },
+    CriticalOverrun {
+        task: TaskId,
+        time: TimeUnit,
+    },
+    Preemption {
+        task: TaskId,
+        by: TaskId,
+        time: TimeUnit,
+    },
+    ModeChange {
+        mode: SimulatorMode,
+        time: TimeUnit,
+    },
+    AgentTick {
+        time: TimeUnit,
+    },
+}
+
+impl SimulatorEventRecord {
+    fn new(event: &SimulatorEvent, task_id: impl Fn(TaskId) -> TaskId) -> Self {
+        let id = |task: &Rc<RefCell<SimulatorTask>>| task_id(task.borrow().task.props().id);
+        match event {
+            SimulatorEvent::Start(task, time) => Self::Start {
+                task: id(task),
+                time: *time,
+            },
+            SimulatorEvent::Release(task, time) => Self::Release {
+                task: id(task),
+                time: *time,
+            },
+            SimulatorEvent::End(task, time, reason) => Self::End {
+                task: id(task),
+                time: *time,
+                reason: *reason,
+            },
+            SimulatorEvent::TaskKill(task, time) => Self::TaskKill {
+                task: id(task),
+                time: *time,
+            },
+            SimulatorEvent::JobSkip(task, time) => Self::JobSkip {
+                task: id(task),
+                time: *time,
+            },
+            SimulatorEvent::PeriodStretch(task, time) => Self::PeriodStretch {
+                task: id(task),
+                time: *time,
+            },
+            SimulatorEvent::DeadlineMiss(task, time) => Self::DeadlineMiss {
+                task: id(task),
+                time: *time,
+            },
+            SimulatorEvent::CriticalOverrun(task, time) => Self::CriticalOverrun {
+                task: id(task),
+                time: *time,
+            },
+            SimulatorEvent::Preemption(task, by, time) => Self::Preemption {
+                task: id(task),
+                by: id(by),
+                time: *time,
+            },
+            SimulatorEvent::ModeChange(mode, time) => Self::ModeChange {
+                mode: *mode,
+                time: *time,
+            },
+            SimulatorEvent::AgentTick(time) => Self::AgentTick { time: *time },
+        }
+    }
+}
+
+impl Serialize for SimulatorEvent {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SimulatorEventRecord::new(self, |id| id).serialize(serializer)
+    }
+}
+
 impl PartialEq for SimulatorEvent {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (SimulatorEvent::Start(task1, time1), SimulatorEvent::Start(task2, time2))
+            | (SimulatorEvent::Release(task1, time1), SimulatorEvent::Release(task2, time2))
             | (SimulatorEvent::End(task1, time1, _), SimulatorEvent::End(task2, time2, _))
-            | (SimulatorEvent::TaskKill(task1, time1), SimulatorEvent::TaskKill(task2, time2)) => {
-                task1.borrow().task.props().id == task2.borrow().task.props().id && time1 == time2
+            | (SimulatorEvent::TaskKill(task1, time1), SimulatorEvent::TaskKill(task2, time2))
+            | (SimulatorEvent::JobSkip(task1, time1), SimulatorEvent::JobSkip(task2, time2))
+            | (
+                SimulatorEvent::PeriodStretch(task1, time1),
+                SimulatorEvent::PeriodStretch(task2, time2),
+            )
+            | (
+                SimulatorEvent::DeadlineMiss(task1, time1),
+                SimulatorEvent::DeadlineMiss(task2, time2),
+            )
+            | (
+                SimulatorEvent::CriticalOverrun(task1, time1),
+                SimulatorEvent::CriticalOverrun(task2, time2),
+            ) => task1.borrow().task.props().id == task2.borrow().task.props().id && time1 == time2,
+            (
+                SimulatorEvent::Preemption(task1, by1, time1),
+                SimulatorEvent::Preemption(task2, by2, time2),
+            ) => {
+                task1.borrow().task.props().id == task2.borrow().task.props().id
+                    && by1.borrow().task.props().id == by2.borrow().task.props().id
+                    && time1 == time2
             }
+            (SimulatorEvent::AgentTick(time1), SimulatorEvent::AgentTick(time2)) => time1 == time2,
             _ => false,
         }
     }
@@ -118,14 +281,16 @@ impl Ord for SimulatorEvent {
                 }
             }
             (SimulatorEvent::Start(task1, time1), SimulatorEvent::Start(task2, time2))
-            | (SimulatorEvent::End(task1, time1, _), SimulatorEvent::End(task2, time2, _)) =>
-            {
+            | (SimulatorEvent::End(task1, time1, _), SimulatorEvent::End(task2, time2, _)) => {
                 #[allow(clippy::comparison_chain)]
                 if time1 < time2 {
                     std::cmp::Ordering::Greater
                 } else if time1 > time2 {
                     std::cmp::Ordering::Less
                 } else {
+                    // Simultaneous events are handled in priority order (a lower
+                    // encoded id means a higher priority), so that the highest priority
+                    // arrival is dispatched first and no spurious preemption happens.
                     task1
                         .borrow()
                         .task
@@ -135,6 +300,38 @@ impl Ord for SimulatorEvent {
                         .reverse()
                 }
             }
+            (SimulatorEvent::AgentTick(time1), other) => {
+                if *time1 < other.time() {
+                    std::cmp::Ordering::Greater
+                } else if *time1 > other.time() {
+                    std::cmp::Ordering::Less
+                } else {
+                    // The agent observes the system after pending terminations,
+                    // but before new arrivals are dispatched.
+                    match other {
+                        SimulatorEvent::End(_, _, _) => std::cmp::Ordering::Less,
+                        SimulatorEvent::Start(_, _) => std::cmp::Ordering::Greater,
+                        _ => std::cmp::Ordering::Equal,
+                    }
+                }
+            }
+            (_, SimulatorEvent::AgentTick(_)) => other.cmp(self).reverse(),
+            (SimulatorEvent::ModeChange(_, time1), other) => {
+                if *time1 < other.time() {
+                    std::cmp::Ordering::Greater
+                } else if *time1 > other.time() {
+                    std::cmp::Ordering::Less
+                } else {
+                    // The return to L-mode happens once the processor is idle,
+                    // and before the arrivals at the same instant.
+                    match other {
+                        SimulatorEvent::End(_, _, _) => std::cmp::Ordering::Less,
+                        SimulatorEvent::Start(_, _) => std::cmp::Ordering::Greater,
+                        _ => std::cmp::Ordering::Equal,
+                    }
+                }
+            }
+            (_, SimulatorEvent::ModeChange(_, _)) => other.cmp(self).reverse(),
             _ => std::cmp::Ordering::Equal,
         }
     }
@@ -150,9 +347,16 @@ impl SimulatorEvent {
     pub fn time(&self) -> TimeUnit {
         match self {
             SimulatorEvent::Start(_, time)
+            | SimulatorEvent::Release(_, time)
             | SimulatorEvent::End(_, time, _)
             | SimulatorEvent::TaskKill(_, time)
-            | SimulatorEvent::ModeChange(_, time) => *time,
+            | SimulatorEvent::JobSkip(_, time)
+            | SimulatorEvent::PeriodStretch(_, time)
+            | SimulatorEvent::DeadlineMiss(_, time)
+            | SimulatorEvent::CriticalOverrun(_, time)
+            | SimulatorEvent::Preemption(_, _, time)
+            | SimulatorEvent::ModeChange(_, time)
+            | SimulatorEvent::AgentTick(time) => *time,
         }
     }
 
@@ -164,97 +368,685 @@ impl SimulatorEvent {
             SimulatorEvent::End(task, time, reason) => {
                 handlers::handle_end_event(task.clone(), *time, *reason, simulator);
             }
+            SimulatorEvent::AgentTick(time) => {
+                handlers::handle_agent_tick_event(*time, simulator);
+            }
+            SimulatorEvent::ModeChange(SimulatorMode::LMode, time) => {
+                handlers::handle_lmode_return_event(*time, simulator);
+            }
             _ => unimplemented!("should not be called"),
         }
     }
 }
 
+/// A deep copy of the state of a running simulation, taken with `Simulator::checkpoint`.
+/// The agent, if any, is not part of the snapshot.
+#[derive(Debug)]
+pub struct SimulatorSnapshot {
+    tasks: Vec<Rc<RefCell<SimulatorTask>>>,
+    pending_agent_action: Option<SimulatorAction>,
+    elapsed_times: Vec<time::Duration>,
+    agent_times: Vec<time::Duration>,
+    memory_usage: Vec<(usize, usize)>,
+    time_in_lmode: TimeUnit,
+    time_in_hmode: TimeUnit,
+    busy_time: TimeUnit,
+    mode_change_offsets: Vec<(TaskId, TimeUnit)>,
+    preemptions: usize,
+    releases: HashMap<TaskId, usize>,
+    deadline_misses: HashMap<TaskId, usize>,
+    response_times: HashMap<TaskId, Vec<TimeUnit>>,
+    execution_trace: Vec<(TaskId, usize, TimeUnit)>,
+    jobs: HashMap<TaskId, Rc<RefCell<SimulatorJob>>>,
+    running_job: Option<Rc<RefCell<SimulatorJob>>>,
+    ready_jobs_queue: Vec<Rc<RefCell<SimulatorJob>>>,
+    event_queue: BinaryHeap<Rc<RefCell<SimulatorEvent>>>,
+    event_history: Vec<Rc<RefCell<SimulatorEvent>>>,
+    last_context_switch: TimeUnit,
+    now: TimeUnit,
+    mode: SimulatorMode,
+    criticality_level: u8,
+    last_mode_change: TimeUnit,
+    hmode_idle_since: TimeUnit,
+    running_history: Vec<Option<Rc<RefCell<SimulatorTask>>>>,
+    cached_response_times: HashMap<TaskId, f32>,
+}
+
+impl SimulatorSnapshot {
+    /// Clones the referenced tasks, jobs and events, preserving which of them
+    /// are shared, so that the copy does not alias the original state.
+    fn deep_clone(&self) -> Self {
+        let tasks = self
+            .tasks
+            .iter()
+            .map(|t| Rc::new(RefCell::new(t.borrow().clone())))
+            .collect::<Vec<_>>();
+        let task_map = self
+            .tasks
+            .iter()
+            .map(Rc::as_ptr)
+            .zip(tasks.iter().cloned())
+            .collect::<HashMap<_, _>>();
+        let clone_task = |task: &Rc<RefCell<SimulatorTask>>| task_map[&Rc::as_ptr(task)].clone();
+        let clone_event = |event: &Rc<RefCell<SimulatorEvent>>| {
+            let mut event = event.borrow().clone();
+            match &mut event {
+                SimulatorEvent::Start(task, _)
+                | SimulatorEvent::Release(task, _)
+                | SimulatorEvent::End(task, _, _)
+                | SimulatorEvent::TaskKill(task, _)
+                | SimulatorEvent::JobSkip(task, _)
+                | SimulatorEvent::PeriodStretch(task, _)
+                | SimulatorEvent::DeadlineMiss(task, _)
+                | SimulatorEvent::CriticalOverrun(task, _) => *task = clone_task(task),
+                SimulatorEvent::Preemption(task, by, _) => {
+                    *task = clone_task(task);
+                    *by = clone_task(by);
+                }
+                SimulatorEvent::ModeChange(_, _) | SimulatorEvent::AgentTick(_) => (),
+            }
+            Rc::new(RefCell::new(event))
+        };
+
+        let jobs = self
+            .jobs
+            .iter()
+            .map(|(id, job)| {
+                let job = job.borrow();
+                let job = SimulatorJob {
+                    task: clone_task(&job.task),
+                    exec_time: job.exec_time,
+                    run_time: job.run_time,
+                    event: clone_event(&job.event),
+                };
+                (*id, Rc::new(RefCell::new(job)))
+            })
+            .collect::<HashMap<_, _>>();
+        // The running job and the ready jobs are the same objects as the ones in the jobs map.
+        let clone_job = |job: &Rc<RefCell<SimulatorJob>>| {
+            let task = clone_task(&job.borrow().task);
+            jobs.values()
+                .find(|j| Rc::ptr_eq(&j.borrow().task, &task))
+                .unwrap()
+                .clone()
+        };
+
+        Self {
+            pending_agent_action: self.pending_agent_action.clone(),
+            elapsed_times: self.elapsed_times.clone(),
+            agent_times: self.agent_times.clone(),
+            memory_usage: self.memory_usage.clone(),
+            time_in_lmode: self.time_in_lmode,
+            time_in_hmode: self.time_in_hmode,
+            busy_time: self.busy_time,
+            mode_change_offsets: self.mode_change_offsets.clone(),
+            preemptions: self.preemptions,
+            releases: self.releases.clone(),
+            deadline_misses: self.deadline_misses.clone(),
+            response_times: self.response_times.clone(),
+            execution_trace: self.execution_trace.clone(),
+            running_job: self.running_job.as_ref().map(clone_job),
+            ready_jobs_queue: self.ready_jobs_queue.iter().map(clone_job).collect(),
+            event_queue: self.event_queue.iter().map(clone_event).collect(),
+            event_history: self.event_history.iter().map(clone_event).collect(),
+            last_context_switch: self.last_context_switch,
+            now: self.now,
+            mode: self.mode,
+            criticality_level: self.criticality_level,
+            last_mode_change: self.last_mode_change,
+            hmode_idle_since: self.hmode_idle_since,
+            running_history: self
+                .running_history
+                .iter()
+                .map(|t| t.as_ref().map(clone_task))
+                .collect(),
+            cached_response_times: self.cached_response_times.clone(),
+            jobs,
+            tasks,
+        }
+    }
+}
+
 pub struct Simulator {
     pub tasks: Vec<Rc<RefCell<SimulatorTask>>>,
-    pub random_execution_time: bool,
+    pub execution_time: ExecutionTimeMode,
     pub agent: Option<Rc<RefCell<SimulatorAgent>>>,
+    pub agent_period: TimeUnit,
+    pub degradation_policy: DegradationPolicy,
+    pub scheduler: Box<dyn Scheduler>,
+    // If false, the simulator never changes mode: H-tasks run up to completion,
+    // while L-tasks still have their budget enforced and are degraded on overrun.
+    pub mixed_criticality: bool,
+    // If HMode, the simulator starts in H-mode and never leaves it: L-tasks never run,
+    // and H-tasks always get their wcet_h budget. This is the pessimistic baseline.
+    pub initial_mode: SimulatorMode,
+    // Time the processor must stay idle in H-mode before returning to L-mode,
+    // so that bursts of H-activity do not cause repeated mode changes.
+    pub lmode_return_delay: TimeUnit,
     pub pending_agent_action: Option<SimulatorAction>,
+    event_log: Option<Box<dyn Write>>, // receives every pushed event as a JSON line
+    event_log_error: Option<io::Error>, // the write that stopped the streaming
+    rng: StdRng,                       // used to sample execution times
+    seed: Option<u64>,
+    replayed_trace: HashMap<(TaskId, usize), TimeUnit>, // (id given by the user, job) -> execution time
 
     // Stats.
-    pub elapsed_times: Vec<time::Duration>,
-    pub memory_usage: Vec<(usize, usize)>,
+    elapsed_times: Vec<time::Duration>, // wall-clock time spent handling each event
+    agent_times: Vec<time::Duration>,   // wall-clock time spent in each agent activation
+    memory_usage: Vec<(usize, usize)>,  // (replay memory size, agent event history length)
+    time_in_lmode: TimeUnit,
+    time_in_hmode: TimeUnit,
+    busy_time: TimeUnit, // time during which some job was running
+    mode_change_offsets: Vec<(TaskId, TimeUnit)>, // (overrunning H-task, time since its release)
+    preemptions: usize,
+    releases: HashMap<TaskId, usize>, // remapped id -> released jobs
+    deadline_misses: HashMap<TaskId, usize>, // remapped id -> jobs completed past their deadline
+    response_times: HashMap<TaskId, Vec<TimeUnit>>, // remapped id -> completed jobs, from their arrival
+    execution_trace: Vec<(TaskId, usize, TimeUnit)>, // (id given by the user, job, execution time)
 
     // Needed during simulation.
-    // Inited during constructor; should not reuse the same simulator for multiple simulations.
+    // Inited during constructor; call `reset` before reusing the simulator for another simulation.
+    initial_tasks: Vec<SimulatorTask>,
     jobs: HashMap<TaskId, Rc<RefCell<SimulatorJob>>>, // max 1 job per task
     running_job: Option<Rc<RefCell<SimulatorJob>>>,
-    ready_jobs_queue: BinaryHeap<Rc<RefCell<SimulatorJob>>>, // except the one that is currently running
-    event_queue: BinaryHeap<Rc<RefCell<SimulatorEvent>>>,    // only start and end events
-    event_history: Vec<Rc<RefCell<SimulatorEvent>>>,         // all events
+    ready_jobs_queue: Vec<Rc<RefCell<SimulatorJob>>>, // in the order they became ready, except the running one
+    event_queue: BinaryHeap<Rc<RefCell<SimulatorEvent>>>, // only start, end, agent tick and mode change events
+    event_history: Vec<Rc<RefCell<SimulatorEvent>>>,      // all events
     last_context_switch: TimeUnit,
     now: TimeUnit,
     mode: SimulatorMode,
+    criticality_level: u8, // tasks below it are dispensed with; 0 in L-mode
+    last_mode_change: TimeUnit,
+    hmode_idle_since: TimeUnit, // when the processor last became idle in H-mode
     running_history: Vec<Option<Rc<RefCell<SimulatorTask>>>>, // used if we want to return the full history
+    original_ids: HashMap<TaskId, TaskId>,                    // remapped id -> id given by the user
     pub cached_response_times: HashMap<TaskId, f32>,
 }
 
 impl Simulator {
+    /// The priority of each task is encoded in its id, which is remapped here.
+    /// Tasks that share the same custom priority (or period, under rate monotonic)
+    /// are deterministically ordered by their original id: the lowest id wins.
+    /// Ids must be unique, and the task set valid (see `try_new`).
     pub fn new(
-        mut tasks: Vec<SimulatorTask>,
-        random_execution_time: bool,
+        tasks: Vec<SimulatorTask>,
+        execution_time: impl Into<ExecutionTimeMode>,
         agent: Option<Rc<RefCell<SimulatorAgent>>>,
     ) -> Self {
+        Self::try_new(tasks, execution_time, agent).unwrap()
+    }
+
+    /// Like `new`, but rejects task sets that would not run as specified.
+    pub fn try_new(
+        mut tasks: Vec<SimulatorTask>,
+        execution_time: impl Into<ExecutionTimeMode>,
+        agent: Option<Rc<RefCell<SimulatorAgent>>>,
+    ) -> Result<Self, TaskSetError> {
+        validate_task_set(&tasks)?;
+
+        let mut original_ids = HashMap::new();
         for task in &mut tasks {
+            let original_id = task.task.props().id;
             if let Some(custom_priority) = task.custom_priority {
                 // The priority is based on the custom priority.
                 task.task.props_mut().id =
                     custom_priority * MAX_TASKS_SIZE as TaskId + task.task.props().id;
             } else {
                 // Default to rate monotonic priority.
-                task.task.props_mut().id =
-                    task.task.props().id + task.task.props().period * MAX_TASKS_SIZE as TaskId;
+                task.task.props_mut().id = task.task.props().id
+                    + task.task.props().period as TaskId * MAX_TASKS_SIZE as TaskId;
                 println!("Task id: {}", task.task.props().id);
             }
+            // Ids beyond MAX_TASKS_SIZE may still collide once remapped.
+            if original_ids
+                .insert(task.task.props().id, original_id)
+                .is_some()
+            {
+                return Err(TaskSetError::DuplicateId { task: original_id });
+            }
         }
 
-        Self {
+        let mut cached_response_times = HashMap::new();
+        for task in &tasks {
+            let id = task.task.props().id;
+            let response_time =
+                response_time(task, &tasks, SimulatorMode::LMode).map_err(|_| {
+                    TaskSetError::ResponseTimeDidNotConverge {
+                        task: original_ids[&id],
+                    }
+                })?;
+            cached_response_times.insert(id, response_time as f32);
+        }
+
+        Ok(Self {
             tasks: tasks
                 .iter()
                 .map(|t| Rc::new(RefCell::new(t.clone())))
                 .collect(),
-            random_execution_time,
+            initial_tasks: tasks.clone(),
+            execution_time: execution_time.into(),
             agent,
+            agent_period: Runnable::duration_to_time_unit(time::Duration::from_millis(10)),
+            degradation_policy: DegradationPolicy::Kill,
+            scheduler: Box::new(RateMonotonic),
+            mixed_criticality: true,
+            initial_mode: SimulatorMode::LMode,
+            lmode_return_delay: 0,
             elapsed_times: vec![],
+            agent_times: vec![],
             memory_usage: vec![],
+            time_in_lmode: 0,
+            time_in_hmode: 0,
+            busy_time: 0,
+            mode_change_offsets: vec![],
+            preemptions: 0,
+            releases: HashMap::new(),
+            deadline_misses: HashMap::new(),
+            response_times: HashMap::new(),
+            execution_trace: vec![],
             jobs: HashMap::new(),
             running_job: None,
-            ready_jobs_queue: BinaryHeap::new(),
+            ready_jobs_queue: Vec::new(),
             event_queue: BinaryHeap::new(),
             event_history: vec![],
             last_context_switch: 0,
             now: 0,
             mode: SimulatorMode::LMode,
+            criticality_level: 0,
+            last_mode_change: 0,
+            hmode_idle_since: 0,
             running_history: vec![],
+            original_ids,
             pending_agent_action: None,
-            cached_response_times: tasks
-                .iter()
-                .map(|t| {
-                    (
-                        t.task.props().id,
-                        response_time(t, &tasks, SimulatorMode::LMode).unwrap() as f32,
-                    )
-                })
-                .collect(),
+            event_log: None,
+            event_log_error: None,
+            rng: StdRng::from_entropy(),
+            seed: None,
+            replayed_trace: HashMap::new(),
+            cached_response_times,
+        })
+    }
+
+    pub fn reset(&mut self) {
+        self.tasks = self
+            .initial_tasks
+            .iter()
+            .map(|t| Rc::new(RefCell::new(t.clone())))
+            .collect();
+        self.pending_agent_action = None;
+        self.elapsed_times.clear();
+        self.agent_times.clear();
+        self.memory_usage.clear();
+        self.time_in_lmode = 0;
+        self.time_in_hmode = 0;
+        self.busy_time = 0;
+        self.mode_change_offsets.clear();
+        self.preemptions = 0;
+        self.releases.clear();
+        self.deadline_misses.clear();
+        self.response_times.clear();
+        self.execution_trace.clear();
+        self.jobs.clear();
+        self.running_job = None;
+        self.ready_jobs_queue.clear();
+        self.event_queue.clear();
+        self.event_history.clear();
+        self.last_context_switch = 0;
+        self.now = 0;
+        self.mode = SimulatorMode::LMode;
+        self.criticality_level = 0;
+        self.last_mode_change = 0;
+        self.hmode_idle_since = 0;
+        self.running_history.clear();
+        if let Some(seed) = self.seed {
+            self.rng = StdRng::seed_from_u64(seed);
+        }
+    }
+
+    /// Seeds the sampling of execution times and release jitter, so that runs are reproducible.
+    /// The seed is reapplied on `reset`.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// The level the simulator escalated to: 0 in L-mode, and in H-mode the highest level
+    /// of the H-tasks that overran their `wcet_l` since the last return to L-mode.
+    pub fn criticality_level(&self) -> u8 {
+        self.criticality_level
+    }
+
+    /// Whether the task runs up to its `wcet_h`, which H-tasks at or below the current
+    /// level do in H-mode. Starting in H-mode, all of them do.
+    fn escalated(&self, task: &SimulatorTask) -> bool {
+        self.mode == SimulatorMode::HMode
+            && (self.initial_mode == SimulatorMode::HMode
+                || task.criticality_level <= self.criticality_level)
+    }
+
+    /// Whether the jobs of the task are dispensed with at the current level.
+    fn dropped(&self, task: &SimulatorTask) -> bool {
+        task.criticality_level < self.criticality_level
+    }
+
+    pub fn time_in_mode(&self, mode: SimulatorMode) -> TimeUnit {
+        match mode {
+            SimulatorMode::LMode => self.time_in_lmode,
+            SimulatorMode::HMode => self.time_in_hmode,
+        }
+    }
+
+    /// Simulated time during which the processor had no job to run.
+    pub fn idle_time(&self) -> TimeUnit {
+        self.now - self.busy_time
+    }
+
+    /// Fraction of the simulated time during which the processor was running some job.
+    pub fn busy_fraction(&self) -> f64 {
+        if self.now == 0 {
+            return 0.0;
+        }
+        self.busy_time as f64 / self.now as f64
+    }
+
+    pub fn now(&self) -> TimeUnit {
+        self.now
+    }
+
+    pub fn busy_time(&self) -> TimeUnit {
+        self.busy_time
+    }
+
+    /// For each switch to H-mode, the H-task whose budget overrun caused it (by the id given
+    /// by the user) and the time since the release of the overrunning job. The AMC analysis
+    /// assumes the switch happens no later than the L-mode response time of that task.
+    pub fn mode_change_offsets(&self) -> Vec<(TaskId, TimeUnit)> {
+        self.mode_change_offsets
+            .iter()
+            .map(|(id, offset)| (*self.original_ids.get(id).unwrap_or(id), *offset))
+            .collect()
+    }
+
+    /// The task set given to the constructor, with the ids given by the user
+    /// rather than the ones encoding the priorities.
+    pub fn original_tasks(&self) -> Vec<SimulatorTask> {
+        self.initial_tasks
+            .iter()
+            .map(|task| {
+                let mut task = task.clone();
+                let id = task.task.props().id;
+                task.task.props_mut().id = self.original_ids[&id];
+                task
+            })
+            .collect()
+    }
+
+    /// Number of times a running job was displaced by a higher priority one.
+    pub fn preemptions(&self) -> usize {
+        self.preemptions
+    }
+
+    /// Number of jobs released so far, of all tasks.
+    pub fn releases(&self) -> usize {
+        self.releases.values().sum()
+    }
+
+    /// Number of released jobs waiting for the processor, excluding the running one.
+    pub fn ready_queue_len(&self) -> usize {
+        self.ready_jobs_queue.len()
+    }
+
+    /// Number of scheduled events not yet handled: job releases and terminations,
+    /// and agent ticks.
+    pub fn pending_event_count(&self) -> usize {
+        self.event_queue.len()
+    }
+
+    /// Execution time of every job released so far, as (id given by the user,
+    /// index of the job of that task, execution time), in release order.
+    pub fn execution_trace(&self) -> &[(TaskId, usize, TimeUnit)] {
+        &self.execution_trace
+    }
+
+    /// Replays the execution times of a trace recorded with `execution_trace` instead of
+    /// sampling them, so that different policies face the same workload. Jobs missing
+    /// from the trace fall back to the usual execution times. Kept on `reset`.
+    pub fn with_execution_trace(mut self, trace: &[(TaskId, usize, TimeUnit)]) -> Self {
+        self.replayed_trace = trace
+            .iter()
+            .map(|(id, job, exec_time)| ((*id, *job), *exec_time))
+            .collect();
+        self
+    }
+
+    /// Number of jobs that completed past their deadline so far, of all tasks.
+    pub fn deadline_misses(&self) -> usize {
+        self.deadline_misses.values().sum()
+    }
+
+    /// Fraction of the released jobs of the task (by the id given by the user) that
+    /// completed past their deadline. Zero if the task released no job yet.
+    pub fn deadline_miss_ratio(&self, task_id: TaskId) -> f64 {
+        let id = self.remapped_id(task_id);
+        match self.releases.get(&id) {
+            Some(releases) => {
+                self.deadline_misses.get(&id).copied().unwrap_or(0) as f64 / *releases as f64
+            }
+            None => 0.0,
+        }
+    }
+
+    /// Observed response times of the completed jobs of the task (by the id given by the user),
+    /// as (lower bound of the bucket, number of jobs) for the populated buckets, in order.
+    /// Response times are measured from the arrival, so they include the release jitter.
+    pub fn response_time_histogram(
+        &self,
+        task_id: TaskId,
+        bucket_size: TimeUnit,
+    ) -> Vec<(TimeUnit, usize)> {
+        assert!(bucket_size > 0, "The bucket size must be positive.");
+        let mut histogram = BTreeMap::new();
+        for response_time in self
+            .response_times
+            .get(&self.remapped_id(task_id))
+            .into_iter()
+            .flatten()
+        {
+            *histogram
+                .entry(response_time / bucket_size * bucket_size)
+                .or_insert(0) += 1;
+        }
+        histogram.into_iter().collect()
+    }
+
+    /// Time the running or ready job of the task (by the id given by the user) can still
+    /// be delayed at `now` and complete by its deadline, given the run time it had accounted
+    /// when it last left the processor. Decreases while the job runs as well as while it
+    /// waits; zero if it will miss its deadline. `None` if the task has no pending job.
+    pub fn current_slack(&self, task_id: TaskId) -> Option<TimeUnit> {
+        let job = self.jobs.get(&self.remapped_id(task_id))?;
+        let running = self
+            .running_job
+            .as_ref()
+            .is_some_and(|running| Rc::ptr_eq(running, job));
+        if !running && !self.ready_jobs_queue.iter().any(|j| Rc::ptr_eq(j, job)) {
+            return None;
+        }
+
+        let job = job.borrow();
+        let remaining = job.exec_time.saturating_sub(job.run_time);
+        // Deadlines are implicit, i.e. the next arrival of the task.
+        let deadline = job.task.borrow().next_arrival;
+        Some(deadline.saturating_sub(self.now + remaining))
+    }
+
+    /// Worst-case response time of the task (by the id given by the user) in the given mode,
+    /// for the task set given to the constructor. L-mode values are served from the cache.
+    pub fn analytic_response_time(
+        &self,
+        task_id: TaskId,
+        mode: SimulatorMode,
+    ) -> Result<TimeUnit, AnalysisError> {
+        let id = self.remapped_id(task_id);
+        if let (SimulatorMode::LMode, Some(response_time)) =
+            (mode, self.cached_response_times.get(&id))
+        {
+            return Ok(*response_time as TimeUnit);
+        }
+        let task = self
+            .initial_tasks
+            .iter()
+            .find(|t| t.task.props().id == id)
+            .unwrap();
+        response_time(task, &self.initial_tasks, mode)
+    }
+
+    /// The id encoding the priority of the task with the id given by the user.
+    fn remapped_id(&self, task_id: TaskId) -> TaskId {
+        let Some(id) = self
+            .original_ids
+            .iter()
+            .find_map(|(id, original_id)| (*original_id == task_id).then_some(*id))
+        else {
+            panic!("Unknown task id {}.", task_id);
+        };
+        id
+    }
+
+    pub fn elapsed_times(&self) -> &[time::Duration] {
+        &self.elapsed_times
+    }
+
+    /// Wall-clock time spent deciding and applying the action of each agent activation.
+    /// The agent is activated by a timer, so it takes no simulated processor time
+    /// from the task set; this is what it would cost on a real processor.
+    pub fn agent_activation_times(&self) -> &[time::Duration] {
+        &self.agent_times
+    }
+
+    /// Total time spent by the agent, i.e. the sum of `agent_activation_times`.
+    pub fn agent_cpu_time(&self) -> time::Duration {
+        self.agent_times.iter().sum()
+    }
+
+    pub fn memory_usage(&self) -> &[(usize, usize)] {
+        &self.memory_usage
+    }
+
+    /// Captures the current state of the simulation, so that it can be
+    /// restored later with `restore`. Continuing `fire` after restoring
+    /// yields the same events as an uninterrupted run, as long as the
+    /// execution times are not randomly sampled.
+    pub fn checkpoint(&self) -> SimulatorSnapshot {
+        SimulatorSnapshot {
+            tasks: self.tasks.clone(),
+            pending_agent_action: self.pending_agent_action.clone(),
+            elapsed_times: self.elapsed_times.clone(),
+            agent_times: self.agent_times.clone(),
+            memory_usage: self.memory_usage.clone(),
+            time_in_lmode: self.time_in_lmode,
+            time_in_hmode: self.time_in_hmode,
+            busy_time: self.busy_time,
+            mode_change_offsets: self.mode_change_offsets.clone(),
+            preemptions: self.preemptions,
+            releases: self.releases.clone(),
+            deadline_misses: self.deadline_misses.clone(),
+            response_times: self.response_times.clone(),
+            execution_trace: self.execution_trace.clone(),
+            jobs: self.jobs.clone(),
+            running_job: self.running_job.clone(),
+            ready_jobs_queue: self.ready_jobs_queue.clone(),
+            event_queue: self.event_queue.clone(),
+            event_history: self.event_history.clone(),
+            last_context_switch: self.last_context_switch,
+            now: self.now,
+            mode: self.mode,
+            criticality_level: self.criticality_level,
+            last_mode_change: self.last_mode_change,
+            hmode_idle_since: self.hmode_idle_since,
+            running_history: self.running_history.clone(),
+            cached_response_times: self.cached_response_times.clone(),
+        }
+        .deep_clone()
+    }
+
+    pub fn restore(&mut self, snapshot: &SimulatorSnapshot) {
+        let snapshot = snapshot.deep_clone();
+        self.tasks = snapshot.tasks;
+        self.pending_agent_action = snapshot.pending_agent_action;
+        self.elapsed_times = snapshot.elapsed_times;
+        self.agent_times = snapshot.agent_times;
+        self.memory_usage = snapshot.memory_usage;
+        self.time_in_lmode = snapshot.time_in_lmode;
+        self.time_in_hmode = snapshot.time_in_hmode;
+        self.busy_time = snapshot.busy_time;
+        self.mode_change_offsets = snapshot.mode_change_offsets;
+        self.preemptions = snapshot.preemptions;
+        self.releases = snapshot.releases;
+        self.deadline_misses = snapshot.deadline_misses;
+        self.response_times = snapshot.response_times;
+        self.execution_trace = snapshot.execution_trace;
+        self.jobs = snapshot.jobs;
+        self.running_job = snapshot.running_job;
+        self.ready_jobs_queue = snapshot.ready_jobs_queue;
+        self.event_queue = snapshot.event_queue;
+        self.event_history = snapshot.event_history;
+        self.last_context_switch = snapshot.last_context_switch;
+        self.now = snapshot.now;
+        self.mode = snapshot.mode;
+        self.criticality_level = snapshot.criticality_level;
+        self.last_mode_change = snapshot.last_mode_change;
+        self.hmode_idle_since = snapshot.hmode_idle_since;
+        self.running_history = snapshot.running_history;
+        self.cached_response_times = snapshot.cached_response_times;
+    }
+
+    fn advance_time_in_mode(&mut self) {
+        let elapsed = self.now - self.last_mode_change;
+        match self.mode {
+            SimulatorMode::LMode => self.time_in_lmode += elapsed,
+            SimulatorMode::HMode => self.time_in_hmode += elapsed,
         }
+        self.last_mode_change = self.now;
+    }
+
+    /// Streams every event to the given writer as it happens, one JSON object
+    /// per line (e.g. `{"type":"ModeChange","mode":"HMode","time":2}`).
+    /// Tasks are referenced by the ids given by the user. Streamed events are not kept
+    /// in the history returned by `fire`. If a write fails, the streaming stops, the
+    /// error is kept (see `event_log_error`) and the history resumes from that event.
+    pub fn set_event_log(&mut self, writer: Box<dyn Write>) {
+        self.event_log = Some(writer);
+        self.event_log_error = None;
+    }
+
+    /// The error that stopped the streaming of events, if any.
+    pub fn event_log_error(&self) -> Option<&io::Error> {
+        self.event_log_error.as_ref()
     }
 
     pub fn set_pending_agent_action(&mut self, action: Option<SimulatorAction>) {
         self.pending_agent_action = action;
     }
 
+    /// Time at which the next job of the task is released, never in the past.
+    fn release_time(&mut self, task: &Rc<RefCell<SimulatorTask>>) -> TimeUnit {
+        std::cmp::max(self.now, task.borrow().sample_release(&mut self.rng))
+    }
+
     fn init_event_queue(&mut self) {
-        for task in &self.tasks {
-            // Generate the first arrival event.
+        self.mode = self.initial_mode;
+        self.criticality_level = (self.mode == SimulatorMode::HMode) as u8;
+        for task in self.tasks.clone() {
+            // Generate the first arrival event, at the offset of the task.
             let event = Rc::new(RefCell::new(SimulatorEvent::Start(
                 task.clone(),
-                task.borrow().task.props().offset,
+                self.release_time(&task),
             )));
-            self.event_queue.push(event.clone());
+            // In H-mode, L-tasks are dispensed with from the start.
+            if !self.dropped(&task.borrow()) {
+                self.event_queue.push(event.clone());
+            }
 
             // Create a job for the task.
             let job = Rc::new(RefCell::new(SimulatorJob {
@@ -262,7 +1054,6 @@ impl Simulator {
                 exec_time: 0,
                 run_time: 0,
                 event,
-                is_agent: false,
             }));
 
             // Add the job to the jobs map.
@@ -270,57 +1061,53 @@ impl Simulator {
         }
 
         if self.agent.is_some() {
-            let max_id = self
-                .tasks
-                .iter()
-                .map(|t| t.borrow().task.props().id)
-                .max()
-                .unwrap();
-
-            // Create a task for the agent.
-            let task = Rc::new(RefCell::new(SimulatorTask::new(
-                task::Task::HTask(TaskProps {
-                    id: max_id + 1,
-                    wcet_l: Runnable::duration_to_time_unit(time::Duration::from_millis(1)),
-                    wcet_h: Runnable::duration_to_time_unit(time::Duration::from_millis(2)),
-                    offset: 0,
-                    period: Runnable::duration_to_time_unit(time::Duration::from_millis(10)),
-                }),
-                Runnable::duration_to_time_unit(time::Duration::from_micros(500)),
-                Runnable::duration_to_time_unit(time::Duration::from_micros(250)),
-            )));
-            self.tasks.push(task.clone());
-
-            // Create an arrival event for the agent.
-            let event = Rc::new(RefCell::new(SimulatorEvent::Start(task.clone(), 0)));
-            self.event_queue.push(event.clone());
-
-            // Create a job for the agent.
-            let job = Rc::new(RefCell::new(SimulatorJob {
-                task: task.clone(),
-                exec_time: 0,
-                run_time: 0,
-                event,
-                is_agent: true,
-            }));
-
-            // Add the job to the jobs map.
-            self.jobs.insert(task.borrow().task.props().id, job);
+            // The agent is activated periodically by a timer, so that it
+            // does not compete with the task set for the processor.
+            let event = Rc::new(RefCell::new(SimulatorEvent::AgentTick(0)));
+            self.event_queue.push(event);
         }
     }
 
     pub fn push_event(&mut self, event: Rc<RefCell<SimulatorEvent>>) {
+        if let Some(event_log) = &mut self.event_log {
+            let record = SimulatorEventRecord::new(&event.borrow(), |id| {
+                self.original_ids.get(&id).copied().unwrap_or(id)
+            });
+            let written = serde_json::to_writer(&mut *event_log, &record)
+                .map_err(io::Error::from)
+                .and_then(|_| writeln!(event_log));
+            if let Err(error) = written {
+                self.event_log = None;
+                self.event_log_error = Some(error);
+            }
+        }
+
         self.event_history.push(event.clone());
         if self.agent.is_some() {
             let event_cpy = match &*event.borrow() {
                 SimulatorEvent::Start(task, time) => SimulatorEvent::Start(task.clone(), *time),
+                SimulatorEvent::Release(task, time) => SimulatorEvent::Release(task.clone(), *time),
                 SimulatorEvent::End(task, time, reason) => {
                     SimulatorEvent::End(task.clone(), *time, *reason)
                 }
                 SimulatorEvent::TaskKill(task, time) => {
                     SimulatorEvent::TaskKill(task.clone(), *time)
                 }
+                SimulatorEvent::JobSkip(task, time) => SimulatorEvent::JobSkip(task.clone(), *time),
+                SimulatorEvent::PeriodStretch(task, time) => {
+                    SimulatorEvent::PeriodStretch(task.clone(), *time)
+                }
+                SimulatorEvent::DeadlineMiss(task, time) => {
+                    SimulatorEvent::DeadlineMiss(task.clone(), *time)
+                }
+                SimulatorEvent::CriticalOverrun(task, time) => {
+                    SimulatorEvent::CriticalOverrun(task.clone(), *time)
+                }
+                SimulatorEvent::Preemption(task, by, time) => {
+                    SimulatorEvent::Preemption(task.clone(), by.clone(), *time)
+                }
                 SimulatorEvent::ModeChange(mode, time) => SimulatorEvent::ModeChange(*mode, *time),
+                SimulatorEvent::AgentTick(time) => SimulatorEvent::AgentTick(*time),
             };
             self.agent
                 .as_ref()
@@ -330,54 +1117,123 @@ impl Simulator {
         }
     }
 
+    fn remap_task_ids(&mut self) {
+        // The tasks are kept in the same order as in the constructor.
+        for (task, initial_task) in self.tasks.iter().zip(&self.initial_tasks) {
+            task.borrow_mut().task.props_mut().id = initial_task.task.props().id;
+        }
+    }
+
     fn change_back_task_ids(&mut self) {
+        // The period may have changed during the simulation (e.g. if it was stretched),
+        // so we cannot decode the real id from the task properties.
         for task in &self.tasks {
-            let real_id = if let Some(custom_priority) = task.borrow().custom_priority {
-                task.borrow().task.props().id - custom_priority * MAX_TASKS_SIZE as TaskId
-            } else {
-                task.borrow().task.props().id
-                    - task.borrow().task.props().period * MAX_TASKS_SIZE as TaskId
-            };
+            let real_id = self.original_ids[&task.borrow().task.props().id];
             task.borrow_mut().task.props_mut().id = real_id;
         }
     }
 
+    fn begin_run(&mut self) {
+        if self.event_queue.is_empty() {
+            self.init_event_queue();
+        } else {
+            // Resuming a previous run, whose ids were changed back at the end.
+            self.remap_task_ids();
+        }
+    }
+
+    fn end_run(&mut self) {
+        self.advance_time_in_mode();
+        self.change_back_task_ids();
+    }
+
+    /// Streamed events were already written out, so the history does not grow with them.
+    fn discard_streamed_events(&mut self) {
+        if self.event_log.is_some() {
+            self.event_history.clear();
+        }
+    }
+
+    fn handle_next_event<const RETURN_FULL_HISTORY: bool>(&mut self) {
+        println!("instant: {}", self.now);
+        // println!(
+        //     "instant: {}; events in queue: {}; ready jobs queue: {:?}",
+        //     self.event_queue.peek().unwrap().borrow().time(),
+        //     self.event_queue.len(),
+        //     self.ready_jobs_queue
+        //         .iter()
+        //         .map(|j| j.borrow().task.borrow().task.props().id)
+        //         .collect::<Vec<_>>()
+        // );
+
+        let event = self.event_queue.pop().unwrap();
+        //  println!("Popped event: {:?}", event.borrow());
+
+        if RETURN_FULL_HISTORY {
+            for _ in self.now..(event.borrow().time()) {
+                self.running_history.push(
+                    self.running_job
+                        .as_ref()
+                        .map(|job| job.borrow().task.clone()),
+                );
+            }
+        }
+
+        if self.running_job.is_some() {
+            self.busy_time += event.borrow().time() - self.now;
+        }
+
+        self.now = event.borrow().time();
+        let handle_start = time::Instant::now();
+        event.borrow().handle(self);
+        self.elapsed_times.push(handle_start.elapsed());
+
+        if cfg!(debug_assertions) {
+            self.check_invariants();
+        }
+    }
+
+    /// Guards the interactions between the running job, the ready queue and the
+    /// event queue: the running job is never also ready, and each task has at most
+    /// one pending termination.
+    fn check_invariants(&self) {
+        if let Some(running_job) = &self.running_job {
+            let running_id = running_job.borrow().task.borrow().task.props().id;
+            debug_assert!(
+                self.ready_jobs_queue
+                    .iter()
+                    .all(|j| j.borrow().task.borrow().task.props().id != running_id),
+                "Running job {} is also in the ready queue.",
+                running_id
+            );
+        }
+
+        let mut pending_terminations = HashMap::new();
+        for event in &self.event_queue {
+            if let SimulatorEvent::End(task, _, _) = &*event.borrow() {
+                let count = pending_terminations
+                    .entry(task.borrow().task.props().id)
+                    .or_insert(0);
+                *count += 1;
+                debug_assert!(
+                    *count <= 1,
+                    "Task {} has more than one pending termination.",
+                    task.borrow().task.props().id
+                );
+            }
+        }
+    }
+
     pub fn fire<const RETURN_FULL_HISTORY: bool>(
         &mut self,
         duration: TimeUnit,
     ) -> (Vec<Option<TaskId>>, Vec<SimulatorEvent>) {
-        self.init_event_queue();
-
+        self.begin_run();
         while self.now < duration {
-            println!("instant: {}", self.now);
-            // println!(
-            //     "instant: {}; events in queue: {}; ready jobs queue: {:?}",
-            //     self.event_queue.peek().unwrap().borrow().time(),
-            //     self.event_queue.len(),
-            //     self.ready_jobs_queue
-            //         .iter()
-            //         .map(|j| j.borrow().task.borrow().task.props().id)
-            //         .collect::<Vec<_>>()
-            // );
-
-            let event = self.event_queue.pop().unwrap();
-            //  println!("Popped event: {:?}", event.borrow());
-
-            if RETURN_FULL_HISTORY {
-                for _ in self.now..(event.borrow().time()) {
-                    self.running_history.push(
-                        self.running_job
-                            .as_ref()
-                            .map(|job| job.borrow().task.clone()),
-                    );
-                }
-            }
-
-            self.now = event.borrow().time();
-            event.borrow().handle(self);
+            self.handle_next_event::<RETURN_FULL_HISTORY>();
+            self.discard_streamed_events();
         }
-
-        self.change_back_task_ids();
+        self.end_run();
 
         (
             self.running_history
@@ -390,15 +1246,78 @@ impl Simulator {
                 .collect(),
         )
     }
+
+    /// Like `fire`, but also stops at the first event handled once `wall_clock_budget`
+    /// of real time has elapsed. Returns whether the whole duration was simulated;
+    /// the simulation can be resumed either way.
+    pub fn fire_within(&mut self, duration: TimeUnit, wall_clock_budget: time::Duration) -> bool {
+        let start = time::Instant::now();
+        self.begin_run();
+        while self.now < duration && start.elapsed() < wall_clock_budget {
+            self.handle_next_event::<false>();
+            self.discard_streamed_events();
+        }
+        self.end_run();
+        self.now >= duration
+    }
+
+    /// Advances the simulation until an event matching `pred` is pushed, returning it,
+    /// or until `max_time` is reached. The simulation can be resumed afterwards with
+    /// `fire` or another `run_until`.
+    pub fn run_until<F: Fn(&SimulatorEvent) -> bool>(
+        &mut self,
+        max_time: TimeUnit,
+        pred: F,
+    ) -> Option<SimulatorEvent> {
+        self.begin_run();
+        let mut matched = None;
+        while matched.is_none() && self.now < max_time {
+            let checked_events = self.event_history.len();
+            self.handle_next_event::<false>();
+            matched = self.event_history[checked_events..]
+                .iter()
+                .find(|e| pred(&e.borrow()))
+                .map(|e| e.borrow().clone());
+            self.discard_streamed_events();
+        }
+        self.end_run();
+        matched
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::{cell::RefCell, rc::Rc};
 
-    use crate::simulator::SimulatorEvent;
-
-    use super::{task::TaskProps, Simulator, SimulatorTask};
+    use crate::{
+        agent::{
+            dqn::ActivationFunction, SimulatorAgent, DEFAULT_GAMMA, DEFAULT_LEARNING_RATE,
+            DEFAULT_MEM_SIZE, DEFAULT_MIN_MEM_SIZE, DEFAULT_SAMPLE_BATCH_SIZE, DEFAULT_UPDATE_FREQ,
+        },
+        simulator::SimulatorEvent,
+    };
+
+    use super::{
+        task::{TaskProps, TimeUnit},
+        validation::TaskSetError,
+        Simulator, SimulatorEventRecord, SimulatorTask,
+    };
+
+    fn placebo_agent(tasks: &[SimulatorTask]) -> Rc<RefCell<SimulatorAgent>> {
+        let agent = Rc::new(RefCell::new(SimulatorAgent::new(
+            DEFAULT_MEM_SIZE,
+            DEFAULT_MIN_MEM_SIZE,
+            DEFAULT_GAMMA,
+            DEFAULT_UPDATE_FREQ,
+            DEFAULT_LEARNING_RATE,
+            vec![4],
+            DEFAULT_SAMPLE_BATCH_SIZE,
+            ActivationFunction::ReLU,
+            tasks,
+        )));
+        agent.borrow_mut().placebo_mode();
+        agent
+    }
 
     fn assert_events_eq(events: Vec<SimulatorEvent>, expected: Vec<SimulatorEvent>) {
         let events_with_stripped_start_end = events
@@ -406,7 +1325,10 @@ mod tests {
             .filter(|e| {
                 !matches!(
                     e,
-                    SimulatorEvent::Start(_, _) | SimulatorEvent::End(_, _, _)
+                    SimulatorEvent::Start(_, _)
+                        | SimulatorEvent::Release(_, _)
+                        | SimulatorEvent::End(_, _, _)
+                        | SimulatorEvent::Preemption(_, _, _)
                 )
             })
             .cloned()
@@ -481,6 +1403,10 @@ mod tests {
         );
 
         assert_events_eq(events, vec![]);
+
+        let idle_instants = tasks.iter().filter(|t| t.is_none()).count();
+        assert_eq!(simulator.idle_time(), idle_instants as TimeUnit);
+        assert_eq!(simulator.busy_fraction(), 0.8);
     }
 
     #[test]
@@ -584,6 +1510,21 @@ mod tests {
             ]
         );
 
+        // Task 1 preempts task 2 on each of its arrivals.
+        assert_eq!(simulator.preemptions(), 3);
+        let preemptions = events
+            .iter()
+            .filter_map(|e| match e {
+                SimulatorEvent::Preemption(task, by, time) => Some((
+                    task.borrow().task.props().id,
+                    by.borrow().task.props().id,
+                    *time,
+                )),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(preemptions, vec![(2, 1, 1), (2, 1, 4), (2, 1, 7)]);
+
         assert_events_eq(events, vec![]);
     }
 
@@ -668,7 +1609,7 @@ mod tests {
             2,
         );
 
-        let mut simulator = Simulator::new(vec![task1, task2], false, None);
+        let mut simulator = Simulator::new(vec![task1.clone(), task2.clone()], false, None);
         let (tasks, events) = simulator.fire::<true>(12);
 
         assert_eq!(
@@ -700,5 +1641,1607 @@ mod tests {
                 SimulatorEvent::ModeChange(crate::simulator::SimulatorMode::LMode, 12),
             ],
         );
+
+        assert_eq!(
+            simulator.time_in_mode(crate::simulator::SimulatorMode::LMode)
+                + simulator.time_in_mode(crate::simulator::SimulatorMode::HMode),
+            12
+        );
+        assert_eq!(
+            simulator.time_in_mode(crate::simulator::SimulatorMode::HMode),
+            0
+        );
+
+        // Returning to L-mode one unit after going idle, each job of task 1 leaves
+        // an H-mode interval of one unit, at [2, 3) and [7, 8).
+        let mut simulator = Simulator::new(vec![task1, task2], false, None);
+        simulator.lmode_return_delay = 1;
+        let (_, events) = simulator.fire::<true>(12);
+        let mode_changes = events
+            .iter()
+            .filter_map(|e| match e {
+                SimulatorEvent::ModeChange(mode, time) => Some((*mode, *time)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            mode_changes,
+            vec![
+                (crate::simulator::SimulatorMode::HMode, 2),
+                (crate::simulator::SimulatorMode::LMode, 3),
+                (crate::simulator::SimulatorMode::HMode, 7),
+                (crate::simulator::SimulatorMode::LMode, 8),
+                (crate::simulator::SimulatorMode::HMode, 12),
+            ]
+        );
+        assert_eq!(
+            simulator.time_in_mode(crate::simulator::SimulatorMode::HMode),
+            2
+        );
+        assert_eq!(
+            simulator.time_in_mode(crate::simulator::SimulatorMode::LMode),
+            10
+        );
+    }
+
+    #[test]
+    fn agent_does_not_perturb_schedule() {
+        let task1 = SimulatorTask::new_with_custom_priority(
+            super::task::Task::LTask(TaskProps {
+                id: 1,
+                wcet_l: 2,
+                wcet_h: 2,
+                offset: 1,
+                period: 5,
+            }),
+            2,
+            2,
+        );
+        let task2 = SimulatorTask::new_with_custom_priority(
+            super::task::Task::HTask(TaskProps {
+                id: 2,
+                wcet_l: 2,
+                wcet_h: 2,
+                offset: 0,
+                period: 5,
+            }),
+            3,
+            2,
+        );
+        let task3 = SimulatorTask::new_with_custom_priority(
+            super::task::Task::LTask(TaskProps {
+                id: 3,
+                wcet_l: 1,
+                wcet_h: 1,
+                offset: 1,
+                period: 5,
+            }),
+            1,
+            1,
+        );
+        let tasks = vec![task1, task2, task3];
+
+        let agent = placebo_agent(&tasks);
+
+        let mut baseline = Simulator::new(tasks.clone(), false, None);
+        let (baseline_history, baseline_events) = baseline.fire::<true>(20);
+
+        let mut with_agent = Simulator::new(tasks.clone(), false, Some(agent));
+        with_agent.agent_period = 3;
+        let (agent_history, agent_events) = with_agent.fire::<true>(20);
+
+        assert_eq!(baseline.tasks.len(), tasks.len());
+        assert_eq!(with_agent.tasks.len(), tasks.len());
+        assert_eq!(baseline_history, agent_history);
+
+        let starts = |events: &[SimulatorEvent], id| {
+            events
+                .iter()
+                .filter(|e| match e {
+                    SimulatorEvent::Start(task, _) => task.borrow().task.props().id == id,
+                    _ => false,
+                })
+                .count()
+        };
+        for id in 1..=3 {
+            assert_eq!(starts(&baseline_events, id), starts(&agent_events, id));
+        }
+    }
+
+    fn overrunning_ltask_history(policy: super::DegradationPolicy) -> Vec<Option<u64>> {
+        let task1 = SimulatorTask::new_with_custom_priority(
+            super::task::Task::LTask(TaskProps {
+                id: 1,
+                wcet_l: 2,
+                wcet_h: 0,
+                offset: 0,
+                period: 5,
+            }),
+            1,
+            3,
+        );
+        let task2 = SimulatorTask::new_with_custom_priority(
+            super::task::Task::HTask(TaskProps {
+                id: 2,
+                wcet_l: 2,
+                wcet_h: 3,
+                offset: 2,
+                period: 5,
+            }),
+            2,
+            2,
+        );
+
+        let mut simulator = Simulator::new(vec![task1, task2], false, None);
+        simulator.degradation_policy = policy;
+        let (tasks, events) = simulator.fire::<true>(12);
+
+        let expected_events = events
+            .iter()
+            .filter(|e| match policy {
+                super::DegradationPolicy::Kill => matches!(e, SimulatorEvent::TaskKill(_, _)),
+                super::DegradationPolicy::SkipJob => matches!(e, SimulatorEvent::JobSkip(_, _)),
+                super::DegradationPolicy::StretchPeriod { .. } => {
+                    matches!(e, SimulatorEvent::PeriodStretch(_, _))
+                }
+            })
+            .count();
+        assert!(expected_events > 0);
+
+        tasks
+    }
+
+    #[test]
+    fn degradation_policies() {
+        let kill = overrunning_ltask_history(super::DegradationPolicy::Kill);
+        let skip = overrunning_ltask_history(super::DegradationPolicy::SkipJob);
+        let stretch =
+            overrunning_ltask_history(super::DegradationPolicy::StretchPeriod { factor: 1.5 });
+
+        assert_eq!(
+            kill,
+            vec![
+                Some(1),
+                Some(1),
+                Some(2),
+                Some(2),
+                None,
+                Some(1),
+                Some(1),
+                Some(2),
+                Some(2),
+                None,
+                Some(1),
+                Some(1),
+            ]
+        );
+        assert_eq!(
+            skip,
+            vec![
+                Some(1),
+                Some(1),
+                Some(2),
+                Some(2),
+                None,
+                None,
+                None,
+                Some(2),
+                Some(2),
+                None,
+                Some(1),
+                Some(1),
+            ]
+        );
+        assert_eq!(
+            stretch,
+            vec![
+                Some(1),
+                Some(1),
+                Some(2),
+                Some(2),
+                None,
+                None,
+                None,
+                Some(1),
+                Some(1),
+                Some(2),
+                Some(2),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn memory_usage_and_elapsed_times() {
+        let task1 = SimulatorTask::new_with_custom_priority(
+            super::task::Task::LTask(TaskProps {
+                id: 1,
+                wcet_l: 1,
+                wcet_h: 1,
+                offset: 1,
+                period: 4,
+            }),
+            1,
+            1,
+        );
+        let task2 = SimulatorTask::new_with_custom_priority(
+            super::task::Task::LTask(TaskProps {
+                id: 2,
+                wcet_l: 2,
+                wcet_h: 2,
+                offset: 0,
+                period: 4,
+            }),
+            2,
+            2,
+        );
+        let tasks = vec![task1, task2];
+
+        let mut simulator = Simulator::new(tasks.clone(), false, Some(placebo_agent(&tasks)));
+        simulator.agent_period = 5;
+        simulator.fire::<false>(20);
+
+        // One sample per agent activation, at instants 0, 5, 10, 15 and 20.
+        assert_eq!(simulator.memory_usage().len(), 5);
+        assert!(simulator
+            .memory_usage()
+            .windows(2)
+            .all(|w| w[0].1 <= w[1].1));
+        assert!(simulator.memory_usage().last().unwrap().1 > 0);
+        assert!(simulator
+            .memory_usage()
+            .iter()
+            .all(|(replay, _)| *replay == 0));
+
+        // One sample per handled event.
+        assert!(simulator.elapsed_times().len() > simulator.memory_usage().len());
+    }
+
+    #[test]
+    fn agent_cpu_time() {
+        let task = SimulatorTask::new_with_custom_priority(
+            super::task::Task::LTask(TaskProps {
+                id: 1,
+                wcet_l: 1,
+                wcet_h: 1,
+                offset: 0,
+                period: 4,
+            }),
+            1,
+            1,
+        );
+
+        let mut simulator = Simulator::new(vec![task.clone()], false, None);
+        simulator.fire::<false>(20);
+        assert!(simulator.agent_activation_times().is_empty());
+        assert_eq!(simulator.agent_cpu_time(), std::time::Duration::ZERO);
+
+        let agent = placebo_agent(std::slice::from_ref(&task));
+        let mut simulator = Simulator::new(vec![task], false, Some(agent));
+        simulator.agent_period = 5;
+        let wall_clock = std::time::Instant::now();
+        simulator.fire::<false>(20);
+        let wall_clock = wall_clock.elapsed();
+
+        // One sample per activation, all spent within the run.
+        let activations = simulator.agent_activation_times();
+        assert_eq!(activations.len(), 5); // at 0, 5, 10, 15 and 20
+        assert_eq!(activations.len(), simulator.memory_usage().len());
+        assert_eq!(
+            simulator.agent_cpu_time(),
+            activations.iter().sum::<std::time::Duration>()
+        );
+        assert!(simulator.agent_cpu_time() > std::time::Duration::ZERO);
+        assert!(simulator.agent_cpu_time() <= wall_clock);
+        assert!(
+            simulator.agent_cpu_time()
+                <= simulator
+                    .elapsed_times()
+                    .iter()
+                    .sum::<std::time::Duration>()
+        );
+
+        simulator.reset();
+        assert_eq!(simulator.agent_cpu_time(), std::time::Duration::ZERO);
+    }
+
+    #[test]
+    fn staggered_offsets() {
+        let task1 = |offset| {
+            SimulatorTask::new_with_custom_priority(
+                super::task::Task::LTask(TaskProps {
+                    id: 1,
+                    wcet_l: 1,
+                    wcet_h: 1,
+                    offset,
+                    period: 4,
+                }),
+                1,
+                1,
+            )
+        };
+        let task2 = SimulatorTask::new_with_custom_priority(
+            super::task::Task::LTask(TaskProps {
+                id: 2,
+                wcet_l: 2,
+                wcet_h: 2,
+                offset: 0,
+                period: 4,
+            }),
+            2,
+            2,
+        );
+
+        let mut simulator = Simulator::new(vec![task1(0), task2.clone()], false, None);
+        let (tasks, _) = simulator.fire::<true>(8);
+        assert_eq!(
+            tasks,
+            vec![
+                Some(1),
+                Some(2),
+                Some(2),
+                None,
+                Some(1),
+                Some(2),
+                Some(2),
+                None,
+            ]
+        );
+
+        let mut simulator = Simulator::new(vec![task1(2), task2], false, None);
+        let (tasks, _) = simulator.fire::<true>(8);
+        assert_eq!(
+            tasks,
+            vec![
+                Some(2),
+                Some(2),
+                Some(1),
+                None,
+                Some(2),
+                Some(2),
+                Some(1),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn reset_and_reuse() {
+        let task1 = SimulatorTask::new_with_custom_priority(
+            super::task::Task::HTask(TaskProps {
+                id: 1,
+                wcet_l: 2,
+                wcet_h: 3,
+                offset: 0,
+                period: 5,
+            }),
+            1,
+            3,
+        );
+        let task2 = SimulatorTask::new_with_custom_priority(
+            super::task::Task::LTask(TaskProps {
+                id: 2,
+                wcet_l: 2,
+                wcet_h: 3,
+                offset: 2,
+                period: 5,
+            }),
+            2,
+            2,
+        );
+        let tasks = vec![task1, task2];
+        let summary = |(history, events): (Vec<Option<u64>>, Vec<SimulatorEvent>)| {
+            (
+                history,
+                events
+                    .iter()
+                    .map(|e| (std::mem::discriminant(e), e.time()))
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        let fresh_first = summary(Simulator::new(tasks.clone(), false, None).fire::<true>(12));
+        let fresh_second = summary(Simulator::new(tasks.clone(), false, None).fire::<true>(12));
+
+        let mut simulator = Simulator::new(tasks, false, None);
+        let reused_first = summary(simulator.fire::<true>(12));
+        simulator.reset();
+        let reused_second = summary(simulator.fire::<true>(12));
+
+        assert_eq!(fresh_first, reused_first);
+        assert_eq!(fresh_second, reused_second);
+        assert_eq!(simulator.time_in_mode(super::SimulatorMode::LMode), 12);
+    }
+
+    #[test]
+    fn checkpoint_and_restore() {
+        let tasks = vec![
+            SimulatorTask::new_with_custom_priority(
+                super::task::Task::HTask(TaskProps {
+                    id: 1,
+                    wcet_l: 2,
+                    wcet_h: 3,
+                    offset: 0,
+                    period: 5,
+                }),
+                1,
+                3,
+            ),
+            SimulatorTask::new_with_custom_priority(
+                super::task::Task::LTask(TaskProps {
+                    id: 2,
+                    wcet_l: 2,
+                    wcet_h: 3,
+                    offset: 2,
+                    period: 5,
+                }),
+                2,
+                2,
+            ),
+        ];
+
+        let mut uninterrupted = Simulator::new(tasks.clone(), false, None);
+        let expected = uninterrupted.fire::<true>(30);
+
+        let mut simulator = Simulator::new(tasks, false, None);
+        simulator.fire::<true>(13);
+        let snapshot = simulator.checkpoint();
+
+        // Running past the checkpoint must not leak into the snapshot.
+        simulator.fire::<true>(21);
+        simulator.restore(&snapshot);
+        let (running_history, events) = simulator.fire::<true>(30);
+
+        assert_eq!(running_history, expected.0);
+        assert_eq!(format!("{:?}", events), format!("{:?}", expected.1));
+    }
+
+    #[test]
+    fn event_log() {
+        struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+        impl std::io::Write for SharedBuffer {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.borrow_mut().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let task1 = SimulatorTask::new_with_custom_priority(
+            super::task::Task::HTask(TaskProps {
+                id: 1,
+                wcet_l: 2,
+                wcet_h: 3,
+                offset: 0,
+                period: 5,
+            }),
+            1,
+            3,
+        );
+        let task2 = SimulatorTask::new_with_custom_priority(
+            super::task::Task::LTask(TaskProps {
+                id: 2,
+                wcet_l: 2,
+                wcet_h: 3,
+                offset: 2,
+                period: 5,
+            }),
+            2,
+            2,
+        );
+
+        let tasks = vec![task1, task2];
+        let (_, events) = Simulator::new(tasks.clone(), false, None).fire::<false>(12);
+
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        let mut simulator = Simulator::new(tasks.clone(), false, None);
+        simulator.set_event_log(Box::new(SharedBuffer(buffer.clone())));
+        // Streamed events are not kept in memory.
+        assert!(simulator.fire::<false>(12).1.is_empty());
+        assert!(simulator.event_log_error().is_none());
+
+        let log = String::from_utf8(buffer.borrow().clone()).unwrap();
+        let lines = log.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), events.len());
+        assert!(lines.contains(&r#"{"type":"ModeChange","mode":"HMode","time":2}"#));
+
+        let records = lines
+            .iter()
+            .map(|line| serde_json::from_str::<SimulatorEventRecord>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            records[0],
+            SimulatorEventRecord::Release { task: 1, time: 0 }
+        );
+        assert_eq!(records[1], SimulatorEventRecord::Start { task: 1, time: 0 });
+        for (record, event) in records.iter().zip(&events) {
+            assert_eq!(
+                *record,
+                serde_json::from_value(serde_json::to_value(event).unwrap()).unwrap()
+            );
+        }
+
+        // A failing writer stops the streaming without stopping the simulation,
+        // and the events it did not receive are kept instead.
+        struct FullDisk;
+
+        impl std::io::Write for FullDisk {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("disk full"))
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut simulator = Simulator::new(tasks, false, None);
+        simulator.set_event_log(Box::new(FullDisk));
+        let (_, streamed) = simulator.fire::<false>(12);
+        assert_eq!(
+            simulator.event_log_error().unwrap().to_string(),
+            "disk full"
+        );
+        assert_eq!(streamed.len(), events.len());
+    }
+
+    #[test]
+    fn deadline_miss() {
+        // The set is overloaded: task 2 is preempted at 4, while still running
+        // its first job, and only completes it at 8.
+        let task1 = SimulatorTask::new_with_custom_priority(
+            super::task::Task::LTask(TaskProps {
+                id: 1,
+                wcet_l: 3,
+                wcet_h: 3,
+                offset: 0,
+                period: 4,
+            }),
+            1,
+            3,
+        );
+        let task2 = SimulatorTask::new_with_custom_priority(
+            super::task::Task::LTask(TaskProps {
+                id: 2,
+                wcet_l: 2,
+                wcet_h: 2,
+                offset: 0,
+                period: 4,
+            }),
+            2,
+            2,
+        );
+
+        let mut simulator = Simulator::new(vec![task1, task2], false, None);
+        let (_, events) = simulator.fire::<false>(8);
+
+        let misses = events
+            .iter()
+            .filter_map(|e| match e {
+                SimulatorEvent::DeadlineMiss(task, time) => {
+                    Some((task.borrow().task.props().id, *time))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(misses, vec![(2, 8)]);
+    }
+
+    #[test]
+    fn deadline_miss_ratio() {
+        // The set fully loads the processor, but task 2 is not schedulable under fixed
+        // priorities: in each hyperperiod, its first job completes at 7, past its
+        // deadline at 6, while its second job, released late, completes on time at 12.
+        let task = |id, wcet, period| {
+            SimulatorTask::new_with_custom_priority(
+                super::task::Task::LTask(TaskProps {
+                    id,
+                    wcet_l: wcet,
+                    wcet_h: wcet,
+                    offset: 0,
+                    period,
+                }),
+                id,
+                wcet,
+            )
+        };
+
+        let mut simulator = Simulator::new(vec![task(1, 2, 4), task(2, 3, 6)], false, None);
+        simulator.fire::<false>(120);
+
+        assert_eq!(simulator.deadline_miss_ratio(1), 0.0);
+        let ratio = simulator.deadline_miss_ratio(2);
+        assert!((0.4..=0.6).contains(&ratio), "ratio: {}", ratio);
+        assert_eq!(simulator.deadline_misses(), 10);
+        assert_eq!(simulator.releases(), 30 + 20);
+
+        simulator.reset();
+        assert_eq!(simulator.deadline_miss_ratio(2), 0.0);
+    }
+
+    #[test]
+    fn equal_custom_priorities() {
+        // Both tasks have the same custom priority, so the lowest id runs first.
+        let task = |id| {
+            SimulatorTask::new_with_custom_priority(
+                super::task::Task::LTask(TaskProps {
+                    id,
+                    wcet_l: 1,
+                    wcet_h: 1,
+                    offset: 0,
+                    period: 4,
+                }),
+                1,
+                1,
+            )
+        };
+
+        let mut simulator = Simulator::new(vec![task(2), task(1)], false, None);
+        let (tasks, _) = simulator.fire::<true>(4);
+        assert_eq!(tasks, vec![Some(1), Some(2), None, None]);
+    }
+
+    #[test]
+    fn duplicate_ids() {
+        let task = |id, priority| {
+            SimulatorTask::new_with_custom_priority(
+                super::task::Task::LTask(TaskProps {
+                    id,
+                    wcet_l: 1,
+                    wcet_h: 1,
+                    offset: 0,
+                    period: 4,
+                }),
+                priority,
+                1,
+            )
+        };
+        assert_eq!(
+            Simulator::try_new(vec![task(1, 1), task(1, 2)], false, None).err(),
+            Some(TaskSetError::DuplicateId { task: 1 })
+        );
+        // Both are remapped to 2001 with MAX_TASKS_SIZE = 1000.
+        assert_eq!(
+            Simulator::try_new(vec![task(1001, 1), task(1, 2)], false, None).err(),
+            Some(TaskSetError::DuplicateId { task: 1 })
+        );
+    }
+
+    #[test]
+    fn response_time_did_not_converge() {
+        // Five unit jobs every 4 instants overload the processor.
+        let tasks = (1..=5)
+            .map(|id| {
+                SimulatorTask::new_with_custom_priority(
+                    super::task::Task::LTask(TaskProps {
+                        id,
+                        wcet_l: 1,
+                        wcet_h: 1,
+                        offset: 0,
+                        period: 4,
+                    }),
+                    id,
+                    1,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert!(Simulator::try_new(tasks[..4].to_vec(), false, None).is_ok());
+        assert_eq!(
+            Simulator::try_new(tasks, false, None).err(),
+            Some(TaskSetError::ResponseTimeDidNotConverge { task: 5 })
+        );
+    }
+
+    #[test]
+    fn offset_not_below_period() {
+        let task = |offset| {
+            SimulatorTask::new(
+                super::task::Task::LTask(TaskProps {
+                    id: 1,
+                    wcet_l: 1,
+                    wcet_h: 1,
+                    offset,
+                    period: 4,
+                }),
+                1,
+                1,
+            )
+        };
+
+        assert!(Simulator::try_new(vec![task(3)], false, None).is_ok());
+        assert_eq!(
+            Simulator::try_new(vec![task(4)], false, None).err(),
+            Some(TaskSetError::OffsetNotBelowPeriod {
+                task: 1,
+                offset: 4,
+                period: 4
+            })
+        );
+    }
+
+    #[test]
+    fn hbudget_below_lbudget() {
+        let props = TaskProps {
+            id: 1,
+            wcet_l: 3,
+            wcet_h: 2,
+            offset: 0,
+            period: 10,
+        };
+
+        // Only L-tasks may have a smaller wcet_h, which they never use as a budget.
+        let ltask = SimulatorTask::new(super::task::Task::LTask(props), 1, 1);
+        assert!(Simulator::try_new(vec![ltask], false, None).is_ok());
+        let htask = SimulatorTask::new(super::task::Task::HTask(props), 1, 1);
+        assert_eq!(
+            Simulator::try_new(vec![htask], false, None).err(),
+            Some(TaskSetError::HBudgetBelowLBudget {
+                task: 1,
+                wcet_l: 3,
+                wcet_h: 2
+            })
+        );
+    }
+
+    #[test]
+    fn simultaneous_arrivals() {
+        // Both tasks arrive at 0; the one with the lowest period has the highest priority,
+        // regardless of the order in which the tasks are given.
+        let task = |id, period| {
+            SimulatorTask::new(
+                super::task::Task::LTask(TaskProps {
+                    id,
+                    wcet_l: 2,
+                    wcet_h: 2,
+                    offset: 0,
+                    period,
+                }),
+                2,
+                2,
+            )
+        };
+
+        let mut simulator = Simulator::new(vec![task(1, 10), task(2, 5)], false, None);
+        let (tasks, _) = simulator.fire::<true>(4);
+        assert_eq!(tasks, vec![Some(2), Some(2), Some(1), Some(1)]);
+    }
+
+    #[test]
+    fn run_until() {
+        let task1 = SimulatorTask::new_with_custom_priority(
+            super::task::Task::HTask(TaskProps {
+                id: 1,
+                wcet_l: 2,
+                wcet_h: 3,
+                offset: 0,
+                period: 5,
+            }),
+            1,
+            3,
+        );
+        let task2 = SimulatorTask::new_with_custom_priority(
+            super::task::Task::LTask(TaskProps {
+                id: 2,
+                wcet_l: 2,
+                wcet_h: 3,
+                offset: 2,
+                period: 5,
+            }),
+            2,
+            2,
+        );
+
+        let mut simulator = Simulator::new(vec![task1, task2], false, None);
+        let event = simulator.run_until(12, |e| matches!(e, SimulatorEvent::ModeChange(_, _)));
+        assert!(matches!(
+            event,
+            Some(SimulatorEvent::ModeChange(super::SimulatorMode::HMode, 2))
+        ));
+        assert_eq!(simulator.now(), 2);
+
+        // The simulation can be resumed, and the next mode change is found.
+        let event = simulator.run_until(
+            12,
+            |e| matches!(e, SimulatorEvent::ModeChange(_, time) if *time > 2),
+        );
+        assert!(matches!(
+            event,
+            Some(SimulatorEvent::ModeChange(super::SimulatorMode::HMode, 7))
+        ));
+
+        // No kill happens in this scenario.
+        let event = simulator.run_until(20, |e| matches!(e, SimulatorEvent::TaskKill(_, _)));
+        assert!(event.is_none());
+        assert!(simulator.now() >= 20);
+    }
+
+    #[test]
+    fn mode_change_offsets() {
+        let task1 = SimulatorTask::new_with_custom_priority(
+            super::task::Task::HTask(TaskProps {
+                id: 1,
+                wcet_l: 2,
+                wcet_h: 3,
+                offset: 0,
+                period: 5,
+            }),
+            1,
+            3,
+        );
+        let task2 = SimulatorTask::new_with_custom_priority(
+            super::task::Task::LTask(TaskProps {
+                id: 2,
+                wcet_l: 2,
+                wcet_h: 3,
+                offset: 2,
+                period: 5,
+            }),
+            2,
+            2,
+        );
+
+        let mut simulator = Simulator::new(vec![task1, task2], false, None);
+        simulator.fire::<false>(12);
+
+        // Task 1 overruns its L-mode budget 2 units after each release (at 0, 5 and 10).
+        assert_eq!(
+            simulator.mode_change_offsets(),
+            vec![(1, 2), (1, 2), (1, 2)]
+        );
+
+        // The switch happens within the L-mode response time assumed by the analysis.
+        let response_time_lo = simulator.cached_response_times[&1001];
+        assert!(simulator
+            .mode_change_offsets()
+            .iter()
+            .all(|(_, offset)| *offset as f32 <= response_time_lo));
+    }
+
+    #[test]
+    fn analytic_response_times() {
+        let task = |id, criticality: fn(TaskProps) -> super::task::Task, period| {
+            SimulatorTask::new_with_custom_priority(
+                criticality(TaskProps {
+                    id,
+                    wcet_l: 1,
+                    wcet_h: 2,
+                    offset: 0,
+                    period,
+                }),
+                id,
+                1,
+            )
+        };
+        let tasks = vec![
+            task(1, super::task::Task::HTask, 5),
+            task(2, super::task::Task::LTask, 10),
+            task(3, super::task::Task::HTask, 20),
+        ];
+        let simulator = Simulator::new(tasks.clone(), false, None);
+
+        let fresh = |id, mode| {
+            let task = simulator
+                .initial_tasks
+                .iter()
+                .find(|t| t.task.props().id == simulator.remapped_id(id))
+                .unwrap();
+            super::validation::response_time(task, &simulator.initial_tasks, mode).unwrap()
+        };
+        for id in 1..=3 {
+            assert_eq!(
+                simulator
+                    .analytic_response_time(id, super::SimulatorMode::LMode)
+                    .unwrap(),
+                fresh(id, super::SimulatorMode::LMode)
+            );
+        }
+        assert_eq!(
+            simulator
+                .analytic_response_time(3, super::SimulatorMode::LMode)
+                .unwrap(),
+            3
+        );
+        // In H-mode, task 1 preempts task 3 twice within 8 units.
+        assert_eq!(
+            simulator
+                .analytic_response_time(3, super::SimulatorMode::HMode)
+                .unwrap(),
+            8
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn invariants_hold() {
+        // Preemptions, budget overruns, kills and mode changes all interleave here.
+        let task = |id, criticality: fn(TaskProps) -> super::task::Task, period, acet| {
+            SimulatorTask::new_with_custom_priority(
+                criticality(TaskProps {
+                    id,
+                    wcet_l: 2,
+                    wcet_h: 4,
+                    offset: id as TimeUnit - 1,
+                    period,
+                }),
+                id,
+                acet,
+            )
+        };
+        let tasks = vec![
+            task(1, super::task::Task::HTask, 7, 3),
+            task(2, super::task::Task::LTask, 9, 3),
+            task(3, super::task::Task::HTask, 13, 2),
+            task(4, super::task::Task::LTask, 17, 1),
+        ];
+
+        let mut simulator = Simulator::new(tasks, false, None);
+        let (_, events) = simulator.fire::<false>(500);
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, SimulatorEvent::TaskKill(_, _))));
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, SimulatorEvent::ModeChange(_, _))));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "is also in the ready queue")]
+    fn invariants_violated() {
+        // The checks behind `invariants_hold` do catch a corrupted state.
+        let task = SimulatorTask::new(
+            super::task::Task::LTask(TaskProps {
+                id: 1,
+                wcet_l: 2,
+                wcet_h: 2,
+                offset: 0,
+                period: 5,
+            }),
+            2,
+            2,
+        );
+        let mut simulator = Simulator::new(vec![task.clone()], false, Some(placebo_agent(&[task])));
+        simulator.agent_period = 1;
+        simulator.fire::<false>(1);
+        let running_job = simulator.running_job.clone().unwrap();
+        simulator.ready_jobs_queue.push(running_job);
+        simulator.check_invariants();
+    }
+
+    #[test]
+    fn single_mode() {
+        let task1 = SimulatorTask::new_with_custom_priority(
+            super::task::Task::HTask(TaskProps {
+                id: 1,
+                wcet_l: 2,
+                wcet_h: 3,
+                offset: 0,
+                period: 5,
+            }),
+            1,
+            3,
+        );
+        let task2 = SimulatorTask::new_with_custom_priority(
+            super::task::Task::LTask(TaskProps {
+                id: 2,
+                wcet_l: 1,
+                wcet_h: 3,
+                offset: 0,
+                period: 5,
+            }),
+            2,
+            2,
+        );
+
+        let mut simulator = Simulator::new(vec![task1, task2], false, None);
+        simulator.mixed_criticality = false;
+        let (tasks, events) = simulator.fire::<true>(5);
+
+        // The H-task overruns its L budget without a mode change, and the L-task is killed.
+        assert_eq!(tasks, vec![Some(1), Some(1), Some(1), Some(2), None]);
+        assert!(!events
+            .iter()
+            .any(|e| matches!(e, SimulatorEvent::ModeChange(_, _))));
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, SimulatorEvent::TaskKill(_, 4))));
+        assert_eq!(simulator.time_in_mode(super::SimulatorMode::HMode), 0);
+    }
+
+    #[test]
+    fn always_hmode() {
+        let task1 = SimulatorTask::new_with_custom_priority(
+            super::task::Task::HTask(TaskProps {
+                id: 1,
+                wcet_l: 1,
+                wcet_h: 3,
+                offset: 0,
+                period: 5,
+            }),
+            1,
+            3,
+        );
+        let task2 = SimulatorTask::new_with_custom_priority(
+            super::task::Task::LTask(TaskProps {
+                id: 2,
+                wcet_l: 1,
+                wcet_h: 1,
+                offset: 0,
+                period: 5,
+            }),
+            2,
+            1,
+        );
+
+        let mut simulator = Simulator::new(vec![task1, task2], false, None);
+        simulator.initial_mode = super::SimulatorMode::HMode;
+        let (tasks, events) = simulator.fire::<true>(20);
+
+        // The H-task runs with its full wcet_h budget, and the L-task never runs.
+        assert!(tasks.iter().flatten().all(|id| *id == 1));
+        assert_eq!(tasks.iter().flatten().count(), 3 * 4);
+        assert!(!events
+            .iter()
+            .any(|e| matches!(e, SimulatorEvent::ModeChange(_, _))));
+        assert_eq!(simulator.time_in_mode(super::SimulatorMode::HMode), 20);
+    }
+
+    #[test]
+    fn execution_trace() {
+        let tasks = vec![
+            SimulatorTask::new(
+                super::task::Task::HTask(TaskProps {
+                    id: 1,
+                    wcet_l: 3,
+                    wcet_h: 8,
+                    offset: 0,
+                    period: 20,
+                }),
+                3,
+                1,
+            ),
+            SimulatorTask::new(
+                super::task::Task::LTask(TaskProps {
+                    id: 2,
+                    wcet_l: 6,
+                    wcet_h: 6,
+                    offset: 1,
+                    period: 10,
+                }),
+                4,
+                2,
+            ),
+        ];
+
+        let mut recorded = Simulator::new(tasks.clone(), true, None);
+        let (running, _) = recorded.fire::<true>(1000);
+        let trace = recorded.execution_trace().to_vec();
+        assert_eq!(trace.len(), recorded.releases());
+        assert!(trace.iter().any(|(id, job, _)| *id == 2 && *job > 0));
+
+        // Another sampler would draw other execution times, but the trace prevails.
+        let mut replayed = Simulator::new(tasks, true, None).with_execution_trace(&trace);
+        replayed.set_seed(7);
+        assert_eq!(replayed.fire::<true>(1000).0, running);
+        assert_eq!(replayed.execution_trace(), trace);
+
+        replayed.reset();
+        assert!(replayed.execution_trace().is_empty());
+        assert_eq!(replayed.fire::<true>(1000).0, running);
+    }
+
+    #[test]
+    fn release_jitter() {
+        let mut task = SimulatorTask::new(
+            super::task::Task::LTask(TaskProps {
+                id: 1,
+                wcet_l: 2,
+                wcet_h: 2,
+                offset: 0,
+                period: 10,
+            }),
+            1,
+            1,
+        );
+        task.release_jitter = 4;
+
+        let mut simulator = Simulator::new(vec![task.clone()], false, None);
+        simulator.set_seed(0);
+        let (_, events) = simulator.fire::<true>(500);
+        let delays = events
+            .iter()
+            .filter_map(|e| match e {
+                SimulatorEvent::Start(_, time) => Some(time % 10),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert!(delays.len() >= 49);
+        assert!(delays.iter().all(|delay| *delay <= 4));
+        assert!(delays.iter().any(|delay| *delay != delays[0]));
+
+        task.release_jitter = 10;
+        assert_eq!(
+            Simulator::try_new(vec![task], false, None).err(),
+            Some(TaskSetError::JitterNotBelowPeriod {
+                task: 1,
+                jitter: 10,
+                period: 10
+            })
+        );
+    }
+
+    #[test]
+    fn lmode_return_delay() {
+        // Each job of the H-task overruns its L-mode budget after 1 unit, switching
+        // to H-mode, and leaves the processor idle until its next release.
+        let task = || {
+            SimulatorTask::new_with_custom_priority(
+                super::task::Task::HTask(TaskProps {
+                    id: 1,
+                    wcet_l: 1,
+                    wcet_h: 3,
+                    offset: 0,
+                    period: 5,
+                }),
+                1,
+                2,
+            )
+        };
+        let mode_changes = |delay| {
+            let mut simulator = Simulator::new(vec![task()], false, None);
+            simulator.lmode_return_delay = delay;
+            let (_, events) = simulator.fire::<false>(50);
+            events
+                .iter()
+                .filter_map(|e| match e {
+                    SimulatorEvent::ModeChange(mode, time) => Some((*mode, *time)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Without a delay, the system returns to L-mode as soon as it is idle.
+        let immediate = mode_changes(0);
+        assert_eq!(immediate.len(), 2 * 10);
+        assert_eq!(immediate[1], (super::SimulatorMode::LMode, 1));
+
+        // The return is postponed by the delay.
+        let delayed = mode_changes(2);
+        assert_eq!(delayed.len(), 2 * 10);
+        assert_eq!(delayed[1], (super::SimulatorMode::LMode, 3));
+
+        // The next release comes before the delay elapses, so the system stays in H-mode,
+        // where the jobs no longer overrun.
+        let sticky = mode_changes(5);
+        assert_eq!(sticky, vec![(super::SimulatorMode::HMode, 1)]);
+    }
+
+    #[test]
+    fn original_tasks() {
+        let props = |id, period| TaskProps {
+            id,
+            wcet_l: 1,
+            wcet_h: 2,
+            offset: 0,
+            period,
+        };
+        let tasks = vec![
+            SimulatorTask::htask(props(7, 10), 1, 1),
+            SimulatorTask::ltask(props(3, 5), 1, 1),
+            SimulatorTask::new_with_custom_priority(super::task::Task::HTask(props(5, 20)), 1, 1),
+        ];
+
+        let mut simulator = Simulator::new(tasks.clone(), false, None);
+        assert!(simulator
+            .initial_tasks
+            .iter()
+            .all(|t| ![7, 3, 5].contains(&t.task.props().id)));
+        assert_eq!(simulator.original_tasks(), tasks);
+
+        simulator.fire::<false>(10);
+        assert_eq!(simulator.original_tasks(), tasks);
+    }
+
+    #[test]
+    fn critical_overrun() {
+        let task = |id| {
+            SimulatorTask::new_with_custom_priority(
+                super::task::Task::HTask(TaskProps {
+                    id,
+                    wcet_l: 1,
+                    wcet_h: 2,
+                    offset: 0,
+                    period: 10,
+                }),
+                id,
+                3,
+            )
+        };
+
+        // Task 1 overruns its L budget at 1, switching to H-mode. Task 2 then runs
+        // in H-mode, and is aborted once it exceeds even its H budget.
+        let mut simulator = Simulator::new(vec![task(1), task(2)], false, None);
+        let (tasks, events) = simulator.fire::<true>(4);
+        assert_eq!(tasks[..4], [Some(1), Some(2), Some(2), None]);
+
+        let overruns = events
+            .iter()
+            .filter_map(|e| match e {
+                SimulatorEvent::CriticalOverrun(task, time) => {
+                    Some((task.borrow().task.props().id, *time))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(overruns, vec![(2, 3)]);
+    }
+
+    #[test]
+    fn event_ordering() {
+        use super::EndReason;
+        use std::collections::BinaryHeap;
+
+        let task = |id| {
+            Rc::new(RefCell::new(SimulatorTask::new_with_custom_priority(
+                super::task::Task::LTask(TaskProps::new_empty(id)),
+                id,
+                1,
+            )))
+        };
+        let (task1, task2) = (task(1), task(2));
+        let pop_order = |events: Vec<SimulatorEvent>| {
+            let mut heap = events.into_iter().collect::<BinaryHeap<_>>();
+            std::iter::from_fn(|| heap.pop()).collect::<Vec<_>>()
+        };
+
+        // Earlier events first, regardless of their type.
+        assert_eq!(
+            pop_order(vec![
+                SimulatorEvent::Start(task1.clone(), 5),
+                SimulatorEvent::End(task1.clone(), 3, EndReason::JobCompletion),
+                SimulatorEvent::Start(task2.clone(), 1),
+            ]),
+            vec![
+                SimulatorEvent::Start(task2.clone(), 1),
+                SimulatorEvent::End(task1.clone(), 3, EndReason::JobCompletion),
+                SimulatorEvent::Start(task1.clone(), 5),
+            ]
+        );
+
+        // At the same time, terminations come before arrivals, in whichever order they are pushed.
+        for events in [
+            vec![
+                SimulatorEvent::Start(task1.clone(), 2),
+                SimulatorEvent::End(task2.clone(), 2, EndReason::JobCompletion),
+            ],
+            vec![
+                SimulatorEvent::End(task2.clone(), 2, EndReason::JobCompletion),
+                SimulatorEvent::Start(task1.clone(), 2),
+            ],
+        ] {
+            assert_eq!(
+                pop_order(events),
+                vec![
+                    SimulatorEvent::End(task2.clone(), 2, EndReason::JobCompletion),
+                    SimulatorEvent::Start(task1.clone(), 2),
+                ]
+            );
+        }
+
+        // Events of the same type and time are handled by priority (lowest id first).
+        assert_eq!(
+            pop_order(vec![
+                SimulatorEvent::Start(task2.clone(), 2),
+                SimulatorEvent::Start(task1.clone(), 2),
+            ]),
+            vec![
+                SimulatorEvent::Start(task1.clone(), 2),
+                SimulatorEvent::Start(task2.clone(), 2),
+            ]
+        );
+        assert_eq!(
+            pop_order(vec![
+                SimulatorEvent::End(task2.clone(), 2, EndReason::JobCompletion),
+                SimulatorEvent::End(task1.clone(), 2, EndReason::JobCompletion),
+            ]),
+            vec![
+                SimulatorEvent::End(task1.clone(), 2, EndReason::JobCompletion),
+                SimulatorEvent::End(task2.clone(), 2, EndReason::JobCompletion),
+            ]
+        );
+
+        // The agent acts after terminations, but before arrivals.
+        assert_eq!(
+            pop_order(vec![
+                SimulatorEvent::Start(task1.clone(), 2),
+                SimulatorEvent::AgentTick(2),
+                SimulatorEvent::End(task2.clone(), 2, EndReason::JobCompletion),
+            ]),
+            vec![
+                SimulatorEvent::End(task2.clone(), 2, EndReason::JobCompletion),
+                SimulatorEvent::AgentTick(2),
+                SimulatorEvent::Start(task1.clone(), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn ready_queue() {
+        // Four jobs are released at once, then one per period.
+        let tasks = (1..=5)
+            .map(|id| {
+                SimulatorTask::new(
+                    super::task::Task::LTask(TaskProps {
+                        id,
+                        wcet_l: 3,
+                        wcet_h: 3,
+                        offset: if id == 5 { 15 } else { 0 },
+                        period: if id == 5 { 20 } else { 40 },
+                    }),
+                    3,
+                    3,
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut simulator = Simulator::new(tasks, false, None);
+        assert_eq!(simulator.ready_queue_len(), 0);
+
+        // At time 3, the first job ended, the second runs and two others wait.
+        simulator.fire::<false>(1);
+        assert_eq!(simulator.now(), 3);
+        assert_eq!(simulator.ready_queue_len(), 2);
+        simulator.fire::<false>(4);
+        assert_eq!(simulator.ready_queue_len(), 1);
+
+        // The backlog is cleared before the periodic task arrives.
+        simulator.fire::<false>(16);
+        assert_eq!(simulator.ready_queue_len(), 0);
+        assert_eq!(simulator.pending_event_count(), 5);
+    }
+
+    #[test]
+    fn response_time_histogram() {
+        let task = SimulatorTask::new(
+            super::task::Task::LTask(TaskProps {
+                id: 1,
+                wcet_l: 8,
+                wcet_h: 8,
+                offset: 0,
+                period: 10,
+            }),
+            2,
+            2,
+        );
+        // Every fourth job is slow.
+        let trace = (0..20)
+            .map(|job| (1, job, if job % 4 == 3 { 8 } else { 2 }))
+            .collect::<Vec<_>>();
+        let mut simulator = Simulator::new(vec![task], false, None).with_execution_trace(&trace);
+        simulator.fire::<false>(200);
+
+        assert_eq!(
+            simulator.response_time_histogram(1, 5),
+            vec![(0, 15), (5, 5)]
+        );
+        assert_eq!(
+            simulator.response_time_histogram(1, 1),
+            vec![(2, 15), (8, 5)]
+        );
+
+        simulator.reset();
+        assert!(simulator.response_time_histogram(1, 5).is_empty());
+    }
+
+    #[test]
+    fn wall_clock_budget() {
+        let tasks = vec![SimulatorTask::new(
+            super::task::Task::LTask(TaskProps {
+                id: 1,
+                wcet_l: 2,
+                wcet_h: 2,
+                offset: 0,
+                period: 10,
+            }),
+            2,
+            2,
+        )];
+        let agent = Rc::new(RefCell::new(SimulatorAgent::new(
+            DEFAULT_MEM_SIZE,
+            DEFAULT_MIN_MEM_SIZE,
+            DEFAULT_GAMMA,
+            DEFAULT_UPDATE_FREQ,
+            DEFAULT_LEARNING_RATE,
+            vec![4],
+            DEFAULT_SAMPLE_BATCH_SIZE,
+            ActivationFunction::ReLU,
+            &tasks,
+        )));
+        let mut simulator = Simulator::new(tasks, false, Some(agent));
+        simulator.agent_period = 5;
+
+        // Out of time before the first event.
+        assert!(!simulator.fire_within(1000, std::time::Duration::ZERO));
+        assert_eq!(simulator.now(), 0);
+
+        // A run that ends early resumes where it stopped.
+        assert!(!simulator.fire_within(1000, std::time::Duration::from_nanos(1)));
+        assert!(simulator.now() < 1000);
+        assert!(simulator.fire_within(1000, std::time::Duration::from_secs(60)));
+        assert!(simulator.now() >= 1000);
+    }
+
+    #[test]
+    fn worst_execution_times() {
+        let task = |id, wcet_l, wcet_h, period| {
+            SimulatorTask::new(
+                super::task::Task::HTask(TaskProps {
+                    id,
+                    wcet_l,
+                    wcet_h,
+                    offset: 0,
+                    period,
+                }),
+                1,
+                1,
+            )
+        };
+        let tasks = vec![task(1, 2, 3, 10), task(2, 2, 4, 15), task(3, 3, 5, 30)];
+        let mut simulator = Simulator::new(
+            tasks,
+            super::ExecutionTimeMode::Worst(super::SimulatorMode::HMode),
+            None,
+        );
+        // Overrunning H-jobs end at the mode change, so start where the analysis assumes.
+        simulator.initial_mode = super::SimulatorMode::HMode;
+        simulator.fire::<false>(300);
+
+        for (id, _, exec_time) in simulator.execution_trace() {
+            let wcet_h = simulator.original_tasks()[*id as usize - 1]
+                .task
+                .props()
+                .wcet_h;
+            assert_eq!(*exec_time, wcet_h);
+        }
+
+        // All tasks arrive at once, so the analysis is tight.
+        for id in 1..=3 {
+            let worst = simulator.response_time_histogram(id, 1).last().unwrap().0;
+            assert_eq!(
+                Ok(worst),
+                simulator.analytic_response_time(id, super::SimulatorMode::HMode)
+            );
+        }
+    }
+
+    #[test]
+    fn current_slack() {
+        let task = |id, wcet, period| {
+            SimulatorTask::new(
+                super::task::Task::LTask(TaskProps {
+                    id,
+                    wcet_l: wcet,
+                    wcet_h: wcet,
+                    offset: 0,
+                    period,
+                }),
+                wcet,
+                wcet,
+            )
+        };
+        let tasks = vec![task(1, 5, 20), task(2, 2, 10)];
+        // The agent does nothing, but its ticks stop the simulation at every instant.
+        let mut simulator = Simulator::new(tasks.clone(), false, Some(placebo_agent(&tasks)));
+        simulator.agent_period = 1;
+
+        // Task 1 waits for task 2 until 2, then runs until 7, losing slack all along.
+        let mut slacks = vec![];
+        for now in 1..=8 {
+            simulator.fire::<false>(now);
+            assert_eq!(simulator.now(), now);
+            slacks.push(simulator.current_slack(1));
+        }
+        assert_eq!(
+            slacks,
+            vec![
+                Some(14),
+                Some(13),
+                Some(12),
+                Some(11),
+                Some(10),
+                Some(9),
+                None,
+                None
+            ]
+        );
+        assert!(slacks[..6].windows(2).all(|w| w[1] < w[0]));
+        assert_eq!(simulator.current_slack(2), None);
+    }
+
+    #[test]
+    fn criticality_levels() {
+        let htask = |id, period, level| {
+            let mut task = SimulatorTask::new(
+                super::task::Task::HTask(TaskProps {
+                    id,
+                    wcet_l: 2,
+                    wcet_h: 4,
+                    offset: 0,
+                    period,
+                }),
+                2,
+                2,
+            );
+            task.criticality_level = level;
+            task
+        };
+        let ltask = SimulatorTask::new(
+            super::task::Task::LTask(TaskProps {
+                id: 1,
+                wcet_l: 1,
+                wcet_h: 1,
+                offset: 0,
+                period: 10,
+            }),
+            1,
+            1,
+        );
+        let tasks = vec![ltask, htask(2, 20, 1), htask(3, 40, 2)];
+        assert_eq!(
+            super::validation::feasible_at_criticality_level(&tasks, 2, 100),
+            Ok(true)
+        );
+        assert_eq!(
+            Simulator::try_new(vec![htask(2, 20, 0)], true, None).err(),
+            Some(TaskSetError::CriticalityLevelMismatch { task: 2, level: 0 })
+        );
+
+        // Both H-tasks overrun their first L-mode budget.
+        let mut simulator = Simulator::new(tasks.clone(), true, Some(placebo_agent(&tasks)))
+            .with_execution_trace(&[(2, 0, 3), (3, 0, 3)]);
+        simulator.agent_period = 1;
+        simulator.lmode_return_delay = 10;
+        let mut levels = vec![];
+        for now in [2, 4, 6] {
+            simulator.fire::<false>(now);
+            levels.push(simulator.criticality_level());
+        }
+        let events = simulator.fire::<true>(16).1;
+        levels.push(simulator.criticality_level());
+        assert_eq!(levels, vec![0, 1, 2, 0]);
+
+        // Task 2 escalates to level 1 at 3, dropping only task 1: task 3 keeps its
+        // L-mode budget until it overruns it in turn at 5, dropping task 2.
+        let ends = events
+            .iter()
+            .filter_map(|e| match e {
+                SimulatorEvent::End(task, time, reason) => {
+                    Some((task.borrow().task.props().id, *time, *reason))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ends,
+            vec![
+                (1, 1, super::EndReason::JobCompletion),
+                (2, 3, super::EndReason::BudgetExceedance),
+                (3, 5, super::EndReason::BudgetExceedance),
+                (1, 16, super::EndReason::JobCompletion),
+            ]
+        );
+        // Task 1 resumes once back in L-mode, at 15.
+        let starts = events
+            .iter()
+            .filter_map(|e| match e {
+                SimulatorEvent::Start(task, time) if task.borrow().task.props().id == 1 => {
+                    Some(*time)
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(starts, vec![0, 15]);
     }
 }
@@ -0,0 +1,134 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::task::{SimulatorTask, TaskId, TimeUnit};
+
+/// Decides which job runs on the processor. Jobs are given by their tasks, whose ids
+/// encode their fixed priorities during a run (a lower id means a higher priority).
+/// The schedulability analyses in `validation` assume the default `RateMonotonic`.
+pub trait Scheduler {
+    /// Whether a job of `released`, just released, displaces the running job of `running`.
+    fn should_preempt(&self, running: &SimulatorTask, released: &SimulatorTask) -> bool;
+
+    /// Index of the job to run next among the ready ones, in the order they became ready.
+    /// `ready` is never empty.
+    fn select_next(&self, ready: &[Rc<RefCell<SimulatorTask>>]) -> usize;
+}
+
+/// Fixed priorities: by period, unless the tasks were given custom priorities.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct RateMonotonic;
+
+impl Scheduler for RateMonotonic {
+    fn should_preempt(&self, running: &SimulatorTask, released: &SimulatorTask) -> bool {
+        released.task.props().id < running.task.props().id
+    }
+
+    fn select_next(&self, ready: &[Rc<RefCell<SimulatorTask>>]) -> usize {
+        (0..ready.len())
+            .min_by_key(|i| ready[*i].borrow().task.props().id)
+            .unwrap()
+    }
+}
+
+/// Dynamic priorities: the job with the earliest absolute deadline runs first.
+/// Deadlines are implicit, i.e. the next arrival of each task.
+/// Ties are broken by the fixed priorities.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct EarliestDeadlineFirst;
+
+impl EarliestDeadlineFirst {
+    fn key(task: &SimulatorTask) -> (TimeUnit, TaskId) {
+        (task.next_arrival, task.task.props().id)
+    }
+}
+
+impl Scheduler for EarliestDeadlineFirst {
+    fn should_preempt(&self, running: &SimulatorTask, released: &SimulatorTask) -> bool {
+        Self::key(released) < Self::key(running)
+    }
+
+    fn select_next(&self, ready: &[Rc<RefCell<SimulatorTask>>]) -> usize {
+        (0..ready.len())
+            .min_by_key(|i| Self::key(&ready[*i].borrow()))
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::{EarliestDeadlineFirst, Scheduler};
+    use crate::simulator::{
+        task::{SimulatorTask, Task, TaskId, TaskProps, TimeUnit},
+        Simulator,
+    };
+
+    /// Runs jobs to completion, in the order they became ready.
+    struct Fifo;
+
+    impl Scheduler for Fifo {
+        fn should_preempt(&self, _: &SimulatorTask, _: &SimulatorTask) -> bool {
+            false
+        }
+
+        fn select_next(&self, _: &[Rc<RefCell<SimulatorTask>>]) -> usize {
+            0
+        }
+    }
+
+    fn task(id: TaskId, wcet: TimeUnit, offset: TimeUnit, period: TimeUnit) -> SimulatorTask {
+        SimulatorTask::new(
+            Task::LTask(TaskProps {
+                id,
+                wcet_l: wcet,
+                wcet_h: wcet,
+                offset,
+                period,
+            }),
+            wcet,
+            wcet,
+        )
+    }
+
+    fn tasks() -> Vec<SimulatorTask> {
+        vec![task(1, 3, 0, 10), task(2, 1, 1, 5), task(3, 1, 2, 4)]
+    }
+
+    #[test]
+    fn rate_monotonic() {
+        let mut simulator = Simulator::new(tasks(), false, None);
+        let (running, _) = simulator.fire::<true>(6);
+        assert_eq!(
+            running,
+            vec![Some(1), Some(2), Some(3), Some(1), Some(1), None]
+        );
+    }
+
+    #[test]
+    fn fifo() {
+        let mut simulator = Simulator::new(tasks(), false, None);
+        simulator.scheduler = Box::new(Fifo);
+        let (running, _) = simulator.fire::<true>(6);
+        assert_eq!(
+            running,
+            vec![Some(1), Some(1), Some(1), Some(2), Some(3), None]
+        );
+        assert_eq!(simulator.preemptions(), 0);
+    }
+
+    #[test]
+    fn earliest_deadline_first() {
+        // Task 2 has the shorter period, but its deadline (11) is later than task 1's (10).
+        let tasks = vec![task(1, 3, 0, 10), task(2, 1, 2, 9)];
+
+        let mut simulator = Simulator::new(tasks.clone(), false, None);
+        let (running, _) = simulator.fire::<true>(5);
+        assert_eq!(running[..5], [Some(1), Some(1), Some(2), Some(1), None]);
+
+        let mut simulator = Simulator::new(tasks, false, None);
+        simulator.scheduler = Box::new(EarliestDeadlineFirst);
+        let (running, _) = simulator.fire::<true>(5);
+        assert_eq!(running[..5], [Some(1), Some(1), Some(1), Some(2), None]);
+    }
+}
@@ -1,11 +1,69 @@
+use std::{
+    fmt::Debug,
+    iter::Sum,
+    ops::{Add, Div, Mul, Rem, Sub},
+};
+
 use crate::generator::Runnable;
+use rand::{distributions::Distribution, Rng};
+use statrs::distribution::Beta;
 
 use super::SimulatorMode;
 
 pub type TaskId = u64;
+/// The time type of the simulator and the analysis; any `TimeValue` may take its place.
 pub type TimeUnit = u64;
 
-#[derive(Clone, Debug)]
+/// An unsigned integer time type. The response time analysis is generic over it,
+/// so that it can be checked with wider types than `TimeUnit`.
+pub trait TimeValue:
+    Copy
+    + Ord
+    + Debug
+    + Sum
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Rem<Output = Self>
+{
+    const ZERO: Self;
+    const ONE: Self;
+
+    /// `ceil(self / rhs)`, without going through floats.
+    fn ceil_div(self, rhs: Self) -> Self {
+        self / rhs
+            + if self % rhs == Self::ZERO {
+                Self::ZERO
+            } else {
+                Self::ONE
+            }
+    }
+}
+
+macro_rules! impl_time_value {
+    ($($t:ty),*) => {
+        $(impl TimeValue for $t {
+            const ZERO: Self = 0;
+            const ONE: Self = 1;
+        })*
+    };
+}
+
+impl_time_value!(u32, u64, u128);
+
+/// Time units that make up a second, i.e. a precision of 10^-2 us.
+/// A coarser scale allows longer simulations before the time overflows.
+pub const TIME_UNITS_PER_SECOND: TimeUnit = 100_000_000;
+
+/// Shape of the PERT distribution used when the task has no runnables;
+/// 4 is the classical choice, and larger values concentrate samples around the ACET.
+pub const DEFAULT_PERT_LAMBDA: f64 = 4.0;
+
+/// H-tasks must have `wcet_h >= wcet_l` (see `validation::validate_task_set`).
+/// L-tasks are dropped in H-mode, so their `wcet_h` is no budget: it only bounds how far
+/// the agent may raise their `wcet_l`, and may even be below it.
+#[derive(Clone, Debug, PartialEq)]
 pub enum Task {
     LTask(TaskProps),
     HTask(TaskProps),
@@ -61,19 +119,25 @@ impl TaskProps {
         }
     }
 
+    /// Utilization with the H-mode budget, `wcet_h / period`.
+    /// See `SimulatorTask::utilization` for other modes.
     pub fn utilization(&self) -> f64 {
         self.wcet_h as f64 / self.period as f64
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct SimulatorTask {
     pub task: Task,
-    pub custom_priority: Option<u64>,
+    pub custom_priority: Option<TaskId>,
     pub acet: TimeUnit,
     pub bcet: TimeUnit,
     pub next_arrival: TimeUnit,
     pub runnables: Option<Vec<Runnable>>,
+    pub wcet_l_floor: TimeUnit, // WCET decreases by the agent never go below it
+    pub pert_lambda: f64,
+    pub release_jitter: TimeUnit, // each job is released up to this late after its arrival
+    pub criticality_level: u8,    // 0 for L-tasks; H-tasks default to 1, the level they escalate to
 }
 
 impl SimulatorTask {
@@ -87,9 +151,23 @@ impl SimulatorTask {
             bcet,
             next_arrival: task.props().offset,
             runnables: None,
+            wcet_l_floor: 1,
+            pert_lambda: DEFAULT_PERT_LAMBDA,
+            release_jitter: 0,
+            criticality_level: matches!(task, Task::HTask(_)) as u8,
         }
     }
 
+    /// Shorthand for `SimulatorTask::new(Task::HTask(props), acet, bcet)`.
+    pub fn htask(props: TaskProps, acet: TimeUnit, bcet: TimeUnit) -> Self {
+        Self::new(Task::HTask(props), acet, bcet)
+    }
+
+    /// Shorthand for `SimulatorTask::new(Task::LTask(props), acet, bcet)`.
+    pub fn ltask(props: TaskProps, acet: TimeUnit, bcet: TimeUnit) -> Self {
+        Self::new(Task::LTask(props), acet, bcet)
+    }
+
     pub fn new_with_runnables(task: Task, runnables: Vec<Runnable>) -> Self {
         Self {
             task: task.clone(),
@@ -98,10 +176,14 @@ impl SimulatorTask {
             bcet: runnables.iter().map(|r| r.bcet).sum(),
             next_arrival: task.props().offset,
             runnables: Some(runnables),
+            wcet_l_floor: 1,
+            pert_lambda: DEFAULT_PERT_LAMBDA,
+            release_jitter: 0,
+            criticality_level: matches!(task, Task::HTask(_)) as u8,
         }
     }
 
-    pub fn new_with_custom_priority(task: Task, priority: TimeUnit, acet: TimeUnit) -> Self {
+    pub fn new_with_custom_priority(task: Task, priority: TaskId, acet: TimeUnit) -> Self {
         assert!(acet > 0, "Execution time must be greater than 0.");
         Self {
             task: task.clone(),
@@ -110,18 +192,118 @@ impl SimulatorTask {
             bcet: acet,
             next_arrival: task.props().offset,
             runnables: None,
+            wcet_l_floor: 1,
+            pert_lambda: DEFAULT_PERT_LAMBDA,
+            release_jitter: 0,
+            criticality_level: matches!(task, Task::HTask(_)) as u8,
         }
     }
 
-    pub fn sample_execution_time(&self) -> TimeUnit {
+    pub fn sample_execution_time(&self, rng: &mut impl Rng) -> TimeUnit {
         if let Some(runnables) = &self.runnables {
-            runnables.iter().map(|r| r.sample_exec_time()).sum::<f64>() as TimeUnit
+            runnables
+                .iter()
+                .map(|r| r.sample_exec_time(rng))
+                .sum::<f64>() as TimeUnit
+        } else {
+            let bcet = self.bcet.min(self.acet);
+            let wcet = self.task.props().wcet_h.max(self.acet);
+            sample_pert(bcet, self.acet, wcet, self.pert_lambda, rng).round() as TimeUnit
+        }
+    }
+
+    /// Release of the next job: its arrival, delayed by up to `release_jitter`.
+    pub fn sample_release(&self, rng: &mut impl Rng) -> TimeUnit {
+        if self.release_jitter == 0 {
+            self.next_arrival
         } else {
-            self.acet
+            self.next_arrival + rng.gen_range(0..=self.release_jitter)
         }
     }
 
-    pub fn priority(&self) -> TimeUnit {
+    /// Fraction of the processor the task may demand with the budget of the given mode.
+    /// Since deadlines are implicit, this is also the task density.
+    pub fn utilization(&self, mode: SimulatorMode) -> f64 {
+        self.task.props().wcet_in_mode(mode) as f64 / self.task.props().period as f64
+    }
+
+    pub fn priority(&self) -> TaskId {
         self.custom_priority.unwrap_or_else(|| self.task.props().id)
     }
 }
+
+/// Samples a PERT distribution over `[min, max]` with the given mode,
+/// i.e. a Beta distribution rescaled to that interval.
+pub fn sample_pert(
+    min: TimeUnit,
+    mode: TimeUnit,
+    max: TimeUnit,
+    lambda: f64,
+    rng: &mut impl Rng,
+) -> f64 {
+    if min >= max {
+        return mode as f64;
+    }
+
+    let range = (max - min) as f64;
+    let alpha = 1.0 + lambda * (mode - min) as f64 / range;
+    let beta = 1.0 + lambda * (max - mode) as f64 / range;
+    min as f64 + Beta::new(alpha, beta).unwrap().sample(rng) * range
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SimulatorTask, Task, TaskProps, TimeUnit};
+
+    #[test]
+    fn pert_samples() {
+        let mut task = SimulatorTask::new(
+            Task::LTask(TaskProps {
+                id: 1,
+                wcet_l: 25,
+                wcet_h: 30,
+                offset: 0,
+                period: 100,
+            }),
+            20,
+            10,
+        );
+        let rng = &mut rand::thread_rng();
+
+        for lambda in [4.0, 10.0] {
+            task.pert_lambda = lambda;
+            let samples = (0..10000)
+                .map(|_| task.sample_execution_time(rng))
+                .collect::<Vec<_>>();
+            assert!(samples.iter().all(|s| (10..=30).contains(s)));
+
+            // The distribution is symmetric, so its mean is the ACET.
+            let average = samples.iter().sum::<TimeUnit>() as f64 / samples.len() as f64;
+            assert!((average - 20.0).abs() < 0.5);
+        }
+    }
+
+    #[test]
+    fn typed_constructors() {
+        let props = TaskProps {
+            id: 1,
+            wcet_l: 2,
+            wcet_h: 4,
+            offset: 1,
+            period: 10,
+        };
+
+        assert_eq!(
+            SimulatorTask::htask(props, 3, 1),
+            SimulatorTask::new(Task::HTask(props), 3, 1)
+        );
+        assert_eq!(
+            SimulatorTask::ltask(props, 3, 1),
+            SimulatorTask::new(Task::LTask(props), 3, 1)
+        );
+        assert_ne!(
+            SimulatorTask::htask(props, 3, 1),
+            SimulatorTask::ltask(props, 3, 1)
+        );
+    }
+}
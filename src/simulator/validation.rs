@@ -1,15 +1,149 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
 use super::{
-    task::{Task, TaskId, TimeUnit},
+    task::{Task, TaskId, TimeUnit, TimeValue},
     SimulatorMode, SimulatorTask,
 };
 
+/// Number of iterations after which the response time recurrences give up.
+/// Utilizations close to 1 may need more.
+pub const DEFAULT_MAX_ITERATIONS: usize = 100;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum AnalysisError {
+    /// The recurrence did not reach a fixed point within the iteration cap.
+    DidNotConverge,
+    /// The mode change analysis only applies to H-tasks.
+    NotHighCriticality,
+}
+
+/// A task set the simulator cannot run as specified. Tasks are referenced by the ids given by the user.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum TaskSetError {
+    /// The first release would come after the second period, breaking periodicity.
+    OffsetNotBelowPeriod {
+        task: TaskId,
+        offset: TimeUnit,
+        period: TimeUnit,
+    },
+    /// Jobs could be released after the arrival of the next one.
+    JitterNotBelowPeriod {
+        task: TaskId,
+        jitter: TimeUnit,
+        period: TimeUnit,
+    },
+    /// An H-task whose H-mode budget is smaller than its L-mode one; the mode change
+    /// would shrink the budget it was just found to exceed.
+    HBudgetBelowLBudget {
+        task: TaskId,
+        wcet_l: TimeUnit,
+        wcet_h: TimeUnit,
+    },
+    /// An L-task above level 0, or an H-task at level 0; the level is what gets them dropped.
+    CriticalityLevelMismatch { task: TaskId, level: u8 },
+    /// Two tasks share an id, so they could not be told apart.
+    DuplicateId { task: TaskId },
+    /// The L-mode response time of the task, cached for the online analysis, did not converge.
+    ResponseTimeDidNotConverge { task: TaskId },
+}
+
+/// Checks the task set against the assumptions of the simulator and the analysis.
+pub fn validate_task_set(tasks: &[SimulatorTask]) -> Result<(), TaskSetError> {
+    let mut ids = HashSet::new();
+    for task in tasks {
+        let props = task.task.props();
+        if !ids.insert(props.id) {
+            return Err(TaskSetError::DuplicateId { task: props.id });
+        }
+        if props.offset >= props.period {
+            return Err(TaskSetError::OffsetNotBelowPeriod {
+                task: props.id,
+                offset: props.offset,
+                period: props.period,
+            });
+        }
+        if task.release_jitter >= props.period {
+            return Err(TaskSetError::JitterNotBelowPeriod {
+                task: props.id,
+                jitter: task.release_jitter,
+                period: props.period,
+            });
+        }
+        if matches!(task.task, Task::HTask(_)) && props.wcet_h < props.wcet_l {
+            return Err(TaskSetError::HBudgetBelowLBudget {
+                task: props.id,
+                wcet_l: props.wcet_l,
+                wcet_h: props.wcet_h,
+            });
+        }
+        if matches!(task.task, Task::HTask(_)) != (task.criticality_level > 0) {
+            return Err(TaskSetError::CriticalityLevelMismatch {
+                task: props.id,
+                level: task.criticality_level,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Offsets are not taken into account by the analysis: it assumes the critical
+/// instant, where all tasks are released simultaneously. Release offsets can only
+/// reduce the interference a task suffers, so the result is still a safe
+/// (if pessimistic) answer for task sets with offsets.
+/// A set whose analysis does not converge is deemed not feasible.
 pub fn feasible_schedule_design_time(tasks: &[SimulatorTask]) -> bool {
+    feasible_schedule_design_time_with_max_iterations(tasks, DEFAULT_MAX_ITERATIONS)
+        .unwrap_or(false)
+}
+
+pub fn feasible_schedule_design_time_with_max_iterations(
+    tasks: &[SimulatorTask],
+    max_iterations: usize,
+) -> Result<bool, AnalysisError> {
     // At design time, we assess the full recurrence for testing the AMC feasibility.
-    feasible_in_mode(tasks, SimulatorMode::LMode)
-        && feasible_in_mode(tasks, SimulatorMode::HMode)
-        && feasible_mode_changes::<false>(tasks, &HashMap::new())
+    Ok(
+        feasible_in_mode(tasks, SimulatorMode::LMode, max_iterations)?
+            && feasible_in_mode(tasks, SimulatorMode::HMode, max_iterations)?
+            && feasible_mode_changes::<false>(tasks, &HashMap::new(), max_iterations)?,
+    )
+}
+
+/// Feasibility when the simulator never changes mode (see `Simulator::mixed_criticality`):
+/// H-tasks may run up to their `wcet_h` alongside the L-tasks, which are bounded by `wcet_l`.
+pub fn feasible_schedule_single_mode(tasks: &[SimulatorTask]) -> bool {
+    let tasks = tasks
+        .iter()
+        .map(|t| {
+            let mut t = t.clone();
+            if let Task::HTask(props) = &mut t.task {
+                props.wcet_l = props.wcet_h;
+            }
+            t
+        })
+        .collect::<Vec<_>>();
+    feasible_in_mode(&tasks, SimulatorMode::LMode, DEFAULT_MAX_ITERATIONS).unwrap_or(false)
+}
+
+/// Design time feasibility on a processor running at `speed` times the reference speed,
+/// on which every execution time is divided by `speed`.
+pub fn feasible_schedule_design_time_at_speed(tasks: &[SimulatorTask], speed: f64) -> bool {
+    assert!(speed > 0.0, "The processor speed must be positive.");
+    let scale = |wcet: TimeUnit| (wcet as f64 / speed).ceil() as TimeUnit;
+    let scaled_tasks = tasks
+        .iter()
+        .map(|t| {
+            let mut t = t.clone();
+            let props = t.task.props_mut();
+            props.wcet_l = scale(props.wcet_l);
+            props.wcet_h = scale(props.wcet_h);
+            t
+        })
+        .collect::<Vec<_>>();
+    feasible_schedule_design_time(&scaled_tasks)
 }
 
 pub fn feasible_schedule_online(
@@ -19,39 +153,263 @@ pub fn feasible_schedule_online(
     // At runtime, we have no "time" to calculate the full recurrence.
     // Therefore, we assume Ri=Ti which is the worst case scenario.
     let tasks = tasks.iter().map(|t| t.borrow().clone()).collect::<Vec<_>>();
-    feasible_in_mode(&tasks, SimulatorMode::LMode)
-        && feasible_mode_changes::<true>(&tasks, cached_response_times)
+    let feasible = || -> Result<bool, AnalysisError> {
+        Ok(
+            feasible_in_mode(&tasks, SimulatorMode::LMode, DEFAULT_MAX_ITERATIONS)?
+                && feasible_mode_changes::<true>(
+                    &tasks,
+                    cached_response_times,
+                    DEFAULT_MAX_ITERATIONS,
+                )?,
+        )
+    };
+    feasible().unwrap_or(false)
+}
+
+/// The full analysis at runtime: slower than `feasible_schedule_online`, but it takes
+/// into account the response times of the current task set instead of the cached ones.
+pub fn feasible_schedule_online_exact(tasks: &[Rc<RefCell<SimulatorTask>>]) -> bool {
+    let tasks = tasks.iter().map(|t| t.borrow().clone()).collect::<Vec<_>>();
+    let feasible = || -> Result<bool, AnalysisError> {
+        Ok(
+            feasible_in_mode(&tasks, SimulatorMode::LMode, DEFAULT_MAX_ITERATIONS)?
+                && feasible_mode_changes::<false>(&tasks, &HashMap::new(), DEFAULT_MAX_ITERATIONS)?,
+        )
+    };
+    feasible().unwrap_or(false)
 }
 
 pub fn response_time(
     task: &SimulatorTask,
     tasks: &[SimulatorTask],
     mode: SimulatorMode,
-) -> Option<TimeUnit> {
-    let wcet = task.task.props().wcet_in_mode(mode);
-    let mut response_time = wcet as f32;
+) -> Result<TimeUnit, AnalysisError> {
+    response_time_with_max_iterations(task, tasks, mode, DEFAULT_MAX_ITERATIONS)
+}
 
-    for _ in 0..100 {
-        let higher_priority_tasks = tasks.iter().filter(|t| t.priority() < task.priority());
-        let interference = higher_priority_tasks
-            .map(|t| {
-                (response_time / t.task.props().period as f32).ceil()
-                    * t.task.props().wcet_in_mode(mode) as f32
-            })
-            .sum::<f32>();
+/// With release jitter, higher priority jobs may be released closer together than their
+/// periods, and the response time is measured from the arrival of the job, before its release.
+pub fn response_time_with_max_iterations(
+    task: &SimulatorTask,
+    tasks: &[SimulatorTask],
+    mode: SimulatorMode,
+    max_iterations: usize,
+) -> Result<TimeUnit, AnalysisError> {
+    let interferers = tasks
+        .iter()
+        .filter(|t| t.priority() < task.priority())
+        .map(|t| {
+            (
+                t.task.props().wcet_in_mode(mode),
+                t.task.props().period,
+                t.release_jitter,
+            )
+        })
+        .collect::<Vec<_>>();
+    let response_time = response_time_recurrence(
+        task.task.props().wcet_in_mode(mode),
+        &interferers,
+        max_iterations,
+    )?;
+    Ok(response_time + task.release_jitter)
+}
+
+/// Least fixed point of `R = wcet + sum(ceil((R + J_j) / T_j) * C_j)`, where the
+/// interferers are the (C_j, T_j, J_j) of the higher priority tasks. Exact in any time type.
+pub fn response_time_recurrence<T: TimeValue>(
+    wcet: T,
+    interferers: &[(T, T, T)],
+    max_iterations: usize,
+) -> Result<T, AnalysisError> {
+    let mut response_time = wcet;
+
+    for _ in 0..max_iterations {
+        let interference = interferers
+            .iter()
+            .map(|&(wcet_j, period, jitter)| (response_time + jitter).ceil_div(period) * wcet_j)
+            .sum::<T>();
 
-        let new_response_time = wcet as f32 + interference;
+        let new_response_time = wcet + interference;
         if new_response_time == response_time {
-            return Some(new_response_time.ceil() as TimeUnit);
+            return Ok(new_response_time);
         } else {
             response_time = new_response_time;
         }
     }
 
+    Err(AnalysisError::DidNotConverge)
+}
+
+/// Length of the level-i busy period of `task`, i.e. the longest interval
+/// during which the processor is kept busy by `task` and tasks of higher priority.
+/// Jittered releases are bunched at its start, as in `response_time_recurrence`.
+fn level_busy_period(
+    task: &SimulatorTask,
+    tasks: &[SimulatorTask],
+    mode: SimulatorMode,
+    max_iterations: usize,
+) -> Result<TimeUnit, AnalysisError> {
+    let level_tasks = tasks
+        .iter()
+        .filter(|t| t.priority() <= task.priority())
+        .collect::<Vec<_>>();
+    let mut busy_period = level_tasks
+        .iter()
+        .map(|t| t.task.props().wcet_in_mode(mode))
+        .sum::<TimeUnit>();
+
+    for _ in 0..max_iterations {
+        let new_busy_period = level_tasks
+            .iter()
+            .map(|t| {
+                (busy_period + t.release_jitter).div_ceil(t.task.props().period)
+                    * t.task.props().wcet_in_mode(mode)
+            })
+            .sum::<TimeUnit>();
+        if new_busy_period == busy_period {
+            return Ok(busy_period);
+        } else {
+            busy_period = new_busy_period;
+        }
+    }
+
+    Err(AnalysisError::DidNotConverge)
+}
+
+/// As per "Fixed priority scheduling of periodic task sets with arbitrary deadlines" (1990).
+/// When the response time may exceed the period, several jobs of the task can be
+/// pending within the level-i busy period, and the worst case is not necessarily
+/// the first one. We therefore analyse every job released in the busy period.
+/// As in `response_time_with_max_iterations`, release jitter is accounted for.
+pub fn busy_period_response_time(
+    task: &SimulatorTask,
+    tasks: &[SimulatorTask],
+    mode: SimulatorMode,
+) -> Result<TimeUnit, AnalysisError> {
+    busy_period_response_time_with_max_iterations(task, tasks, mode, DEFAULT_MAX_ITERATIONS)
+}
+
+pub fn busy_period_response_time_with_max_iterations(
+    task: &SimulatorTask,
+    tasks: &[SimulatorTask],
+    mode: SimulatorMode,
+    max_iterations: usize,
+) -> Result<TimeUnit, AnalysisError> {
+    let wcet = task.task.props().wcet_in_mode(mode);
+    let period = task.task.props().period;
+    let busy_period = level_busy_period(task, tasks, mode, max_iterations)?;
+    let number_of_jobs = (busy_period + task.release_jitter).div_ceil(period);
+    let mut worst_response_time = 0;
+
+    for job in 0..number_of_jobs {
+        let mut completion_time = (job + 1) * wcet;
+        let mut converged = false;
+
+        for _ in 0..max_iterations {
+            let interference = tasks
+                .iter()
+                .filter(|t| t.priority() < task.priority())
+                .map(|t| {
+                    (completion_time + t.release_jitter).div_ceil(t.task.props().period)
+                        * t.task.props().wcet_in_mode(mode)
+                })
+                .sum::<TimeUnit>();
+
+            let new_completion_time = (job + 1) * wcet + interference;
+            if new_completion_time == completion_time {
+                converged = true;
+                break;
+            } else {
+                completion_time = new_completion_time;
+            }
+        }
+
+        if !converged {
+            return Err(AnalysisError::DidNotConverge);
+        }
+        worst_response_time =
+            worst_response_time.max(completion_time + task.release_jitter - job * period);
+    }
+
+    Ok(worst_response_time)
+}
+
+/// Least common multiple of the periods, after which the release pattern repeats.
+pub fn hyperperiod(tasks: &[SimulatorTask]) -> TimeUnit {
+    fn gcd(a: TimeUnit, b: TimeUnit) -> TimeUnit {
+        if b == 0 {
+            a
+        } else {
+            gcd(b, a % b)
+        }
+    }
+
+    tasks
+        .iter()
+        .map(|t| t.task.props().period)
+        .fold(1, |lcm, period| {
+            (lcm / gcd(lcm, period))
+                .checked_mul(period)
+                .expect("The hyperperiod does not fit in a TimeUnit.")
+        })
+}
+
+/// Rounds the duration up to a whole number of hyperperiods, so that a simulation
+/// covers every release pattern of the task set.
+pub fn round_to_hyperperiod(duration: TimeUnit, tasks: &[SimulatorTask]) -> TimeUnit {
+    let hyperperiod = hyperperiod(tasks);
+    duration.div_ceil(hyperperiod).max(1) * hyperperiod
+}
+
+/// Sum of the utilizations of the tasks that run in the given mode:
+/// L-tasks are not accounted for in H-mode, since they are dropped.
+pub fn total_utilization(tasks: &[SimulatorTask], mode: SimulatorMode) -> f64 {
+    tasks
+        .iter()
+        .filter(|t| mode == SimulatorMode::LMode || matches!(t.task, Task::HTask(_)))
+        .map(|t| t.utilization(mode))
+        .sum()
+}
+
+/// As per "Scheduling Algorithms for Multiprogramming in a Hard-Real-Time Environment" (1973).
+/// A cheap utilization test that is conclusive for many task sets:
+/// a total utilization above 1 can never be scheduled, and rate monotonic
+/// sets under the `n(2^(1/n)-1)` bound always are. Returns `None` otherwise,
+/// and whenever a release is jittered, which the bound does not account for.
+pub fn utilization_bound_feasible(tasks: &[SimulatorTask], mode: SimulatorMode) -> Option<bool> {
+    let eligible_tasks = tasks
+        .iter()
+        .filter(|t| mode == SimulatorMode::LMode || matches!(t.task, Task::HTask(_)))
+        .collect::<Vec<_>>();
+    if eligible_tasks.is_empty() {
+        return None;
+    }
+
+    let utilization = total_utilization(tasks, mode);
+    if utilization > 1.0 {
+        return Some(false);
+    }
+
+    // The bound only holds if shorter periods have higher priority.
+    let rate_monotonic = eligible_tasks.iter().all(|t1| {
+        eligible_tasks.iter().all(|t2| {
+            t1.task.props().period >= t2.task.props().period || t1.priority() < t2.priority()
+        })
+    });
+    let n = eligible_tasks.len() as f64;
+    let jitter_free = eligible_tasks.iter().all(|t| t.release_jitter == 0);
+    if rate_monotonic && jitter_free && utilization <= n * (2f64.powf(1.0 / n) - 1.0) {
+        return Some(true);
+    }
+
     None
 }
 
-fn feasible_in_mode(tasks: &[SimulatorTask], mode: SimulatorMode) -> bool {
+fn feasible_in_mode(
+    tasks: &[SimulatorTask],
+    mode: SimulatorMode,
+    max_iterations: usize,
+) -> Result<bool, AnalysisError> {
     let eligible_tasks = match mode {
         SimulatorMode::LMode => tasks.to_vec(),
         SimulatorMode::HMode => tasks
@@ -61,21 +419,49 @@ fn feasible_in_mode(tasks: &[SimulatorTask], mode: SimulatorMode) -> bool {
             .collect::<Vec<_>>(),
     };
 
-    for task in &eligible_tasks {
-        if task.task.props().wcet_in_mode(mode) == 0 {
-            return false;
-        }
+    if eligible_tasks
+        .iter()
+        .any(|t| t.task.props().wcet_in_mode(mode) == 0)
+    {
+        return Ok(false);
+    }
 
-        if let Some(response_time) = response_time(task, &eligible_tasks, mode) {
-            if response_time > task.task.props().period {
-                return false;
-            }
-        } else {
-            return false;
+    // Skip the response time analysis if the utilization alone is conclusive.
+    if let Some(feasible) = utilization_bound_feasible(&eligible_tasks, mode) {
+        return Ok(feasible);
+    }
+
+    for task in &eligible_tasks {
+        let response_time =
+            response_time_with_max_iterations(task, &eligible_tasks, mode, max_iterations)?;
+        if response_time > task.task.props().period {
+            return Ok(false);
         }
     }
 
-    true
+    Ok(true)
+}
+
+/// Steady state feasibility once the simulator escalated to `level`: the tasks below it are
+/// dropped, those at it run up to their `wcet_h` and those above it are still bounded by
+/// `wcet_l`. Level 0 is the L-mode, and with two levels, level 1 is the H-mode.
+pub fn feasible_at_criticality_level(
+    tasks: &[SimulatorTask],
+    level: u8,
+    max_iterations: usize,
+) -> Result<bool, AnalysisError> {
+    let tasks = tasks
+        .iter()
+        .filter(|t| t.criticality_level >= level)
+        .map(|t| {
+            let mut t = t.clone();
+            if level > 0 && t.criticality_level == level {
+                t.task.props_mut().wcet_l = t.task.props().wcet_h;
+            }
+            t
+        })
+        .collect::<Vec<_>>();
+    feasible_in_mode(&tasks, SimulatorMode::LMode, max_iterations)
 }
 
 /// As per "Response-Time Analysis for Mixed Criticality Systems" (2011).
@@ -85,51 +471,59 @@ fn response_time_in_mode_changes<const APPROXIMATE: bool>(
     task: &SimulatorTask,
     tasks: &[SimulatorTask],
     cached_response_times: &HashMap<TaskId, f32>,
-) -> Option<TimeUnit> {
+    max_iterations: usize,
+) -> Result<TimeUnit, AnalysisError> {
     if !matches!(task.task, Task::HTask(_)) {
-        return None;
+        return Err(AnalysisError::NotHighCriticality);
     }
 
     let interference_by_ltasks = tasks
         .iter()
         .filter(|t| !matches!(t.task, Task::HTask(_)) && t.priority() < task.priority())
         .map(|t| {
-            let response_t =
-                if let Some(response_time) = cached_response_times.get(&t.task.props().id) {
-                    *response_time
-                } else {
-                    response_time(t, tasks, SimulatorMode::LMode).unwrap() as f32
-                };
-            (response_t / t.task.props().period as f32).ceil() as TimeUnit
-                * t.task.props().wcet_in_mode(SimulatorMode::LMode)
+            let response_t = if let Some(response_time) =
+                cached_response_times.get(&t.task.props().id)
+            {
+                *response_time
+            } else {
+                response_time_with_max_iterations(t, tasks, SimulatorMode::LMode, max_iterations)?
+                    as f32
+            };
+            Ok(
+                ((response_t + t.release_jitter as f32) / t.task.props().period as f32).ceil()
+                    as TimeUnit
+                    * t.task.props().wcet_in_mode(SimulatorMode::LMode),
+            )
         })
-        .sum::<TimeUnit>();
+        .sum::<Result<TimeUnit, AnalysisError>>()?;
 
     if APPROXIMATE {
         let interference_by_htasks = tasks
             .iter()
             .filter(|t| matches!(t.task, Task::HTask(_)) && t.priority() < task.priority())
             .map(|t| {
-                (task.task.props().period as f32 / t.task.props().period as f32).ceil() as TimeUnit
+                ((task.task.props().period + t.release_jitter) as f32
+                    / t.task.props().period as f32)
+                    .ceil() as TimeUnit
                     * t.task.props().wcet_in_mode(SimulatorMode::HMode)
             })
             .sum::<TimeUnit>();
 
-        return Some(
-            task.task.props().wcet_in_mode(SimulatorMode::HMode)
-                + interference_by_ltasks
-                + interference_by_htasks,
-        );
+        return Ok(task.task.props().wcet_in_mode(SimulatorMode::HMode)
+            + interference_by_ltasks
+            + interference_by_htasks
+            + task.release_jitter);
     }
 
     let mut total_response_time = task.task.props().wcet_in_mode(SimulatorMode::HMode);
 
-    for _ in 0..100 {
+    for _ in 0..max_iterations {
         let interference_by_htasks = tasks
             .iter()
             .filter(|t| matches!(t.task, Task::HTask(_)) && t.priority() < task.priority())
             .map(|t| {
-                (total_response_time as f32 / t.task.props().period as f32).ceil() as TimeUnit
+                ((total_response_time + t.release_jitter) as f32 / t.task.props().period as f32)
+                    .ceil() as TimeUnit
                     * t.task.props().wcet_in_mode(SimulatorMode::HMode)
             })
             .sum::<TimeUnit>();
@@ -139,19 +533,20 @@ fn response_time_in_mode_changes<const APPROXIMATE: bool>(
             + interference_by_ltasks;
 
         if new_total_response_time == total_response_time {
-            return Some(new_total_response_time);
+            return Ok(new_total_response_time + task.release_jitter);
         } else {
             total_response_time = new_total_response_time;
         }
     }
 
-    None
+    Err(AnalysisError::DidNotConverge)
 }
 
 fn feasible_mode_changes<const APPROXIMATE: bool>(
     tasks: &[SimulatorTask],
     cached_response_times: &HashMap<TaskId, f32>,
-) -> bool {
+    max_iterations: usize,
+) -> Result<bool, AnalysisError> {
     let eligible_tasks = tasks
         .iter()
         .filter(|t| matches!(t.task, Task::HTask(_)))
@@ -170,42 +565,52 @@ fn feasible_mode_changes<const APPROXIMATE: bool>(
                     {
                         *response_time
                     } else {
-                        response_time(t, tasks, SimulatorMode::LMode).unwrap() as f32
+                        response_time_with_max_iterations(
+                            t,
+                            tasks,
+                            SimulatorMode::LMode,
+                            max_iterations,
+                        )? as f32
                     };
-                    (t_response_time_lo / t.task.props().period as f32).ceil() as TimeUnit
-                        * t.task.props().wcet_in_mode(SimulatorMode::LMode)
+                    Ok(
+                        (t_response_time_lo / t.task.props().period as f32).ceil() as TimeUnit
+                            * t.task.props().wcet_in_mode(SimulatorMode::LMode),
+                    )
                 })
-                .sum::<TimeUnit>();
+                .sum::<Result<TimeUnit, AnalysisError>>()?;
             let response_time_lo =
                 if let Some(response_time) = cached_response_times.get(&task.task.props().id) {
                     *response_time
                 } else {
-                    response_time(task, tasks, SimulatorMode::LMode).unwrap() as f32
+                    response_time_with_max_iterations(
+                        task,
+                        tasks,
+                        SimulatorMode::LMode,
+                        max_iterations,
+                    )? as f32
                 };
             if task.task.props().wcet_in_mode(SimulatorMode::LMode) + interference
                 > response_time_lo as TimeUnit
             {
-                return false;
+                return Ok(false);
             }
         }
     }
 
     // AMC-rtb (eq. 6)
     for task in &eligible_tasks {
-        if let Some(response_time) = response_time_in_mode_changes::<APPROXIMATE>(
+        let response_time = response_time_in_mode_changes::<APPROXIMATE>(
             task,
             eligible_tasks.as_slice(),
             cached_response_times,
-        ) {
-            if response_time > task.task.props().period {
-                return false;
-            }
-        } else {
-            return false;
+            max_iterations,
+        )?;
+        if response_time > task.task.props().period {
+            return Ok(false);
         }
     }
 
-    true
+    Ok(true)
 }
 
 #[cfg(test)]
@@ -215,7 +620,12 @@ mod tests {
     use crate::simulator::{
         task::{TaskProps, TimeUnit},
         validation::{
-            feasible_in_mode, feasible_mode_changes, response_time, response_time_in_mode_changes,
+            busy_period_response_time, busy_period_response_time_with_max_iterations,
+            feasible_in_mode, feasible_mode_changes, feasible_schedule_design_time_at_speed,
+            feasible_schedule_single_mode, hyperperiod, response_time,
+            response_time_in_mode_changes, response_time_recurrence,
+            response_time_with_max_iterations, round_to_hyperperiod, total_utilization,
+            utilization_bound_feasible, AnalysisError, DEFAULT_MAX_ITERATIONS,
         },
         SimulatorTask,
     };
@@ -262,21 +672,23 @@ mod tests {
 
         assert_eq!(
             response_time(&task1, &tasks, crate::simulator::SimulatorMode::LMode),
-            Some(4)
+            Ok(4)
         );
         assert_eq!(
             response_time(&task2, &tasks, crate::simulator::SimulatorMode::LMode),
-            Some(6)
+            Ok(6)
         );
         assert_eq!(
             response_time(&task3, &tasks, crate::simulator::SimulatorMode::LMode),
-            Some(8)
+            Ok(8)
         );
 
         assert!(feasible_in_mode(
             &tasks,
-            crate::simulator::SimulatorMode::LMode
-        ));
+            crate::simulator::SimulatorMode::LMode,
+            DEFAULT_MAX_ITERATIONS
+        )
+        .unwrap());
     }
 
     #[test]
@@ -319,21 +731,23 @@ mod tests {
 
         assert_eq!(
             response_time(&task1, &tasks, crate::simulator::SimulatorMode::LMode),
-            Some(4)
+            Ok(4)
         );
         assert_eq!(
             response_time(&task2, &tasks, crate::simulator::SimulatorMode::LMode),
-            Some(6)
+            Ok(6)
         );
         assert_eq!(
             response_time(&task3, &tasks, crate::simulator::SimulatorMode::LMode),
-            Some(15)
+            Ok(15)
         );
 
         assert!(!feasible_in_mode(
             &tasks,
-            crate::simulator::SimulatorMode::LMode
-        ));
+            crate::simulator::SimulatorMode::LMode,
+            DEFAULT_MAX_ITERATIONS
+        )
+        .unwrap());
     }
 
     #[test]
@@ -404,43 +818,47 @@ mod tests {
 
         assert_eq!(
             response_time(&task1, &tasks, crate::simulator::SimulatorMode::HMode),
-            Some(1)
+            Ok(1)
         );
         assert_eq!(
             response_time(&task2, &tasks, crate::simulator::SimulatorMode::HMode),
-            Some(2)
+            Ok(2)
         );
 
         assert_eq!(
             response_time(&task1, &tasks, crate::simulator::SimulatorMode::LMode),
-            Some(1)
+            Ok(1)
         );
         assert_eq!(
             response_time(&task2, &tasks, crate::simulator::SimulatorMode::LMode),
-            Some(2)
+            Ok(2)
         );
         assert_eq!(
             response_time(&task3, &tasks, crate::simulator::SimulatorMode::LMode),
-            Some(6)
+            Ok(6)
         );
         assert_eq!(
             response_time(&task4, &tasks, crate::simulator::SimulatorMode::LMode),
-            Some(8)
+            Ok(8)
         );
         assert_eq!(
             response_time(&task5, &tasks, crate::simulator::SimulatorMode::LMode),
-            Some(29)
+            Ok(29)
         );
 
         assert!(feasible_in_mode(
             &tasks,
-            crate::simulator::SimulatorMode::HMode
-        ));
+            crate::simulator::SimulatorMode::HMode,
+            DEFAULT_MAX_ITERATIONS
+        )
+        .unwrap());
 
         assert!(!feasible_in_mode(
             &tasks,
-            crate::simulator::SimulatorMode::LMode
-        ));
+            crate::simulator::SimulatorMode::LMode,
+            DEFAULT_MAX_ITERATIONS
+        )
+        .unwrap());
     }
 
     #[test]
@@ -482,11 +900,19 @@ mod tests {
         let tasks = vec![task1.clone(), task2.clone(), task3.clone()];
 
         assert_eq!(
-            response_time_in_mode_changes::<false>(&task1, &tasks, &HashMap::new()),
-            Some(8)
+            response_time_in_mode_changes::<false>(
+                &task1,
+                &tasks,
+                &HashMap::new(),
+                DEFAULT_MAX_ITERATIONS
+            ),
+            Ok(8)
         );
 
-        assert!(feasible_mode_changes::<false>(&tasks, &HashMap::new()));
+        assert!(
+            feasible_mode_changes::<false>(&tasks, &HashMap::new(), DEFAULT_MAX_ITERATIONS)
+                .unwrap()
+        );
     }
 
     #[test]
@@ -528,14 +954,386 @@ mod tests {
         let tasks = vec![task1.clone(), task2.clone(), task3.clone()];
 
         assert_eq!(
-            response_time_in_mode_changes::<false>(&task1, &tasks, &HashMap::new()),
-            Some(8)
+            response_time_in_mode_changes::<false>(
+                &task1,
+                &tasks,
+                &HashMap::new(),
+                DEFAULT_MAX_ITERATIONS
+            ),
+            Ok(8)
+        );
+        assert_eq!(
+            response_time_in_mode_changes::<false>(
+                &task2,
+                &tasks,
+                &HashMap::new(),
+                DEFAULT_MAX_ITERATIONS
+            ),
+            Ok(2)
+        );
+
+        assert!(
+            feasible_mode_changes::<false>(&tasks, &HashMap::new(), DEFAULT_MAX_ITERATIONS)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn response_time_exceeding_period() {
+        let task1 = SimulatorTask::new_with_custom_priority(
+            crate::simulator::task::Task::LTask(TaskProps {
+                id: 1,
+                wcet_l: 26,
+                wcet_h: 26,
+                offset: 0,
+                period: 70,
+            }),
+            1,
+            UNUSED_TIME,
+        );
+        let task2 = SimulatorTask::new_with_custom_priority(
+            crate::simulator::task::Task::LTask(TaskProps {
+                id: 2,
+                wcet_l: 62,
+                wcet_h: 62,
+                offset: 0,
+                period: 100,
+            }),
+            2,
+            UNUSED_TIME,
+        );
+
+        let tasks = vec![task1.clone(), task2.clone()];
+
+        assert_eq!(
+            busy_period_response_time(&task1, &tasks, crate::simulator::SimulatorMode::LMode),
+            Ok(26)
+        );
+
+        // The first job is not the worst case: the fifth job in the busy period is.
+        assert_eq!(
+            response_time(&task2, &tasks, crate::simulator::SimulatorMode::LMode),
+            Ok(114)
+        );
+        assert_eq!(
+            busy_period_response_time(&task2, &tasks, crate::simulator::SimulatorMode::LMode),
+            Ok(118)
+        );
+    }
+
+    #[test]
+    fn response_time_with_jitter() {
+        let task = |id, wcet, period, jitter| {
+            let mut task = SimulatorTask::new_with_custom_priority(
+                crate::simulator::task::Task::LTask(TaskProps {
+                    id,
+                    wcet_l: wcet,
+                    wcet_h: wcet,
+                    offset: 0,
+                    period,
+                }),
+                id,
+                wcet,
+            );
+            task.release_jitter = jitter;
+            task
+        };
+        let mode = crate::simulator::SimulatorMode::LMode;
+
+        let tasks = vec![task(1, 1, 4, 0), task(2, 2, 8, 0)];
+        assert_eq!(response_time(&tasks[1], &tasks, mode), Ok(3));
+
+        // Two jobs of task 1 may now be released within the 3 units task 2 needs.
+        let tasks = vec![task(1, 1, 4, 2), task(2, 2, 8, 0)];
+        assert_eq!(response_time(&tasks[0], &tasks, mode), Ok(3));
+        assert_eq!(response_time(&tasks[1], &tasks, mode), Ok(4));
+
+        // The own jitter of a task delays its completion after the arrival.
+        let tasks = vec![task(1, 1, 4, 2), task(2, 2, 8, 1)];
+        assert_eq!(response_time(&tasks[1], &tasks, mode), Ok(5));
+
+        // U = 0.375 is under the utilization bound, but the jitter alone misses the deadline.
+        let tasks = vec![task(1, 1, 4, 0), task(2, 1, 8, 7)];
+        assert_eq!(utilization_bound_feasible(&tasks, mode), None);
+        assert_eq!(response_time(&tasks[1], &tasks, mode), Ok(9));
+        assert_eq!(busy_period_response_time(&tasks[1], &tasks, mode), Ok(9));
+        assert_eq!(
+            feasible_in_mode(&tasks, mode, DEFAULT_MAX_ITERATIONS),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn alternative_time_types() {
+        // (wcet, period, jitter) of the higher priority tasks.
+        let interferers = [(1, 4, 2), (2, 8, 0)];
+        assert_eq!(response_time_recurrence::<u64>(2, &interferers, 100), Ok(6));
+        assert_eq!(
+            response_time_recurrence::<u32>(2, &[(1, 4, 2), (2, 8, 0)], 100),
+            Ok(6)
+        );
+
+        // Beyond the range of u64, and of what f32 can represent exactly.
+        let unit = 1u128 << 80;
+        let interferers = [(unit, 4 * unit + 1, 0), (2 * unit, 8 * unit + 1, 0)];
+        assert_eq!(
+            response_time_recurrence(2 * unit, &interferers, 100),
+            Ok(6 * unit)
+        );
+        assert_eq!(
+            response_time_recurrence(2 * unit, &[(5 * unit, 4 * unit, 0)], 100),
+            Err(AnalysisError::DidNotConverge)
+        );
+    }
+
+    #[test]
+    fn utilization_bound() {
+        let task = |id, wcet, period| {
+            SimulatorTask::new_with_custom_priority(
+                crate::simulator::task::Task::LTask(TaskProps {
+                    id,
+                    wcet_l: wcet,
+                    wcet_h: wcet,
+                    offset: 0,
+                    period,
+                }),
+                id,
+                wcet,
+            )
+        };
+
+        // U = 0.5, under the bound for two tasks (0.83).
+        let tasks = vec![task(1, 1, 4), task(2, 2, 8)];
+        assert_eq!(
+            utilization_bound_feasible(&tasks, crate::simulator::SimulatorMode::LMode),
+            Some(true)
+        );
+        assert!(feasible_in_mode(
+            &tasks,
+            crate::simulator::SimulatorMode::LMode,
+            DEFAULT_MAX_ITERATIONS
+        )
+        .unwrap());
+
+        // U = 1.25, no scheduler can cope with it.
+        let tasks = vec![task(1, 3, 4), task(2, 4, 8)];
+        assert_eq!(
+            utilization_bound_feasible(&tasks, crate::simulator::SimulatorMode::LMode),
+            Some(false)
+        );
+
+        // U = 1, between the bound and full utilization: the response time analysis decides.
+        let tasks = vec![task(1, 2, 4), task(2, 4, 8)];
+        assert_eq!(
+            utilization_bound_feasible(&tasks, crate::simulator::SimulatorMode::LMode),
+            None
+        );
+        assert!(feasible_in_mode(
+            &tasks,
+            crate::simulator::SimulatorMode::LMode,
+            DEFAULT_MAX_ITERATIONS
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn slow_convergence() {
+        // The higher priority tasks leave almost no slack, so the
+        // response time of the last task grows by a few units per iteration.
+        let tasks = [(1, 2), (1, 3), (1, 7), (1, 43), (1, 1807)]
+            .iter()
+            .enumerate()
+            .map(|(i, &(wcet, period))| {
+                SimulatorTask::new_with_custom_priority(
+                    crate::simulator::task::Task::LTask(TaskProps {
+                        id: i as u64 + 1,
+                        wcet_l: wcet,
+                        wcet_h: wcet,
+                        offset: 0,
+                        period,
+                    }),
+                    i as u64 + 1,
+                    wcet,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            response_time(&tasks[4], &tasks, crate::simulator::SimulatorMode::LMode),
+            Err(AnalysisError::DidNotConverge)
+        );
+        assert_eq!(
+            feasible_in_mode(
+                &tasks,
+                crate::simulator::SimulatorMode::LMode,
+                DEFAULT_MAX_ITERATIONS
+            ),
+            Err(AnalysisError::DidNotConverge)
+        );
+
+        assert_eq!(
+            response_time_with_max_iterations(
+                &tasks[4],
+                &tasks,
+                crate::simulator::SimulatorMode::LMode,
+                1000
+            ),
+            Ok(1806)
         );
         assert_eq!(
-            response_time_in_mode_changes::<false>(&task2, &tasks, &HashMap::new()),
-            Some(2)
+            feasible_in_mode(&tasks, crate::simulator::SimulatorMode::LMode, 1000),
+            Ok(true)
         );
 
-        assert!(feasible_mode_changes::<false>(&tasks, &HashMap::new()));
+        // The busy period analysis takes the same cap.
+        assert_eq!(
+            busy_period_response_time(&tasks[4], &tasks, crate::simulator::SimulatorMode::LMode),
+            Err(AnalysisError::DidNotConverge)
+        );
+        assert_eq!(
+            busy_period_response_time_with_max_iterations(
+                &tasks[4],
+                &tasks,
+                crate::simulator::SimulatorMode::LMode,
+                1000
+            ),
+            Ok(1806)
+        );
+    }
+
+    #[test]
+    fn processor_speed() {
+        let task1 = SimulatorTask::new_with_custom_priority(
+            crate::simulator::task::Task::HTask(TaskProps {
+                id: 1,
+                wcet_l: 2,
+                wcet_h: 3,
+                offset: 0,
+                period: 10,
+            }),
+            1,
+            2,
+        );
+        let task2 = SimulatorTask::new_with_custom_priority(
+            crate::simulator::task::Task::LTask(TaskProps {
+                id: 2,
+                wcet_l: 4,
+                wcet_h: 4,
+                offset: 0,
+                period: 10,
+            }),
+            2,
+            4,
+        );
+        let tasks = vec![task1, task2];
+
+        assert!(feasible_schedule_design_time_at_speed(&tasks, 1.0));
+        assert!(feasible_schedule_design_time_at_speed(&tasks, 2.0));
+        // At half the speed, the L-mode utilization is 1.2.
+        assert!(!feasible_schedule_design_time_at_speed(&tasks, 0.5));
+    }
+
+    #[test]
+    fn single_mode() {
+        let task = |id, criticality: fn(TaskProps) -> crate::simulator::task::Task, wcet_h| {
+            SimulatorTask::new_with_custom_priority(
+                criticality(TaskProps {
+                    id,
+                    wcet_l: 2,
+                    wcet_h,
+                    offset: 0,
+                    period: 10,
+                }),
+                id,
+                2,
+            )
+        };
+
+        // Only the H-tasks run up to their wcet_h: 4 + 4 + 2.
+        let tasks = vec![
+            task(1, crate::simulator::task::Task::HTask, 4),
+            task(2, crate::simulator::task::Task::HTask, 4),
+            task(3, crate::simulator::task::Task::LTask, 8),
+        ];
+        assert!(feasible_schedule_single_mode(&tasks));
+
+        let tasks = vec![
+            task(1, crate::simulator::task::Task::HTask, 4),
+            task(2, crate::simulator::task::Task::HTask, 5),
+            task(3, crate::simulator::task::Task::LTask, 8),
+        ];
+        assert!(!feasible_schedule_single_mode(&tasks));
+    }
+
+    #[test]
+    fn total_utilizations() {
+        let task1 = SimulatorTask::new_with_custom_priority(
+            crate::simulator::task::Task::HTask(TaskProps {
+                id: 1,
+                wcet_l: 1,
+                wcet_h: 3,
+                offset: 0,
+                period: 4,
+            }),
+            1,
+            1,
+        );
+        let task2 = SimulatorTask::new_with_custom_priority(
+            crate::simulator::task::Task::LTask(TaskProps {
+                id: 2,
+                wcet_l: 2,
+                wcet_h: 4,
+                offset: 0,
+                period: 8,
+            }),
+            2,
+            2,
+        );
+        let tasks = vec![task1, task2];
+
+        assert_eq!(
+            tasks[1].utilization(crate::simulator::SimulatorMode::LMode),
+            0.25
+        );
+        assert_eq!(
+            tasks[1].utilization(crate::simulator::SimulatorMode::HMode),
+            0.5
+        );
+        // 1/4 + 2/8
+        assert_eq!(
+            total_utilization(&tasks, crate::simulator::SimulatorMode::LMode),
+            0.5
+        );
+        // 3/4, since the L-task does not run in H-mode.
+        assert_eq!(
+            total_utilization(&tasks, crate::simulator::SimulatorMode::HMode),
+            0.75
+        );
+    }
+
+    #[test]
+    fn hyperperiods() {
+        let tasks = [4, 6, 8]
+            .iter()
+            .enumerate()
+            .map(|(i, &period)| {
+                SimulatorTask::new_with_custom_priority(
+                    crate::simulator::task::Task::LTask(TaskProps {
+                        id: i as u64 + 1,
+                        wcet_l: 1,
+                        wcet_h: 1,
+                        offset: 0,
+                        period,
+                    }),
+                    i as u64 + 1,
+                    1,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(hyperperiod(&tasks), 24);
+        assert_eq!(round_to_hyperperiod(0, &tasks), 24);
+        assert_eq!(round_to_hyperperiod(24, &tasks), 24);
+        assert_eq!(round_to_hyperperiod(25, &tasks), 48);
     }
 }
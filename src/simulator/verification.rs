@@ -0,0 +1,414 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use super::{
+    task::{SimulatorTask, Task, TaskId, TimeUnit},
+    EndReason, SimulatorEvent, SimulatorMode,
+};
+
+/// A property of a run broken by the simulator. Tasks are referenced by the ids given by the user.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Violation {
+    /// Another job started or ended while `running` was running, without preempting it.
+    Overlap {
+        running: TaskId,
+        other: TaskId,
+        time: TimeUnit,
+    },
+    /// A job of an H-task ended after its deadline.
+    HTaskDeadlineMiss { task: TaskId, time: TimeUnit },
+    /// The simulator switched to H-mode without any H-task exceeding its budget.
+    UnexplainedModeChange { time: TimeUnit },
+    /// An H-task was killed; only L-tasks may be degraded.
+    HTaskKill { task: TaskId, time: TimeUnit },
+}
+
+/// An interval during which `running` ran while a job of `blocked`, of higher priority,
+/// was pending. Tasks are referenced by the ids given by the user.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct InversionReport {
+    pub blocked: TaskId,
+    pub running: TaskId,
+    pub start: TimeUnit,
+    pub end: TimeUnit,
+}
+
+/// Checks the events of a run, as returned by `Simulator::fire::<true>`, against the
+/// semantics of the simulator. `tasks` is the task set given to the simulator.
+/// Jobs resuming after a preemption push no event, so overlaps are only detected
+/// when a job starts or ends.
+pub fn verify_schedule(
+    events: &[SimulatorEvent],
+    tasks: &[SimulatorTask],
+) -> Result<(), Vec<Violation>> {
+    let tasks = tasks
+        .iter()
+        .map(|t| (t.task.props().id, t))
+        .collect::<HashMap<_, _>>();
+    let id = |task: &Rc<RefCell<SimulatorTask>>| task.borrow().task.props().id;
+    let is_htask = |id: TaskId| matches!(tasks[&id].task, Task::HTask(_));
+
+    let mut violations = vec![];
+    let mut running = None;
+    let mut releases = HashMap::new(); // task -> release of its last job
+    let mut last_exceedance = None; // time of the last budget exceedance of an H-task
+
+    for (i, event) in events.iter().enumerate() {
+        match event {
+            SimulatorEvent::Start(task, time) => {
+                let task = id(task);
+                if let Some(running) = running {
+                    // The preemption is pushed right after the start of the preempting job.
+                    let preempted = matches!(
+                        events.get(i + 1),
+                        Some(SimulatorEvent::Preemption(preempted, by, t))
+                            if id(preempted) == running && id(by) == task && t == time
+                    );
+                    if !preempted {
+                        violations.push(Violation::Overlap {
+                            running,
+                            other: task,
+                            time: *time,
+                        });
+                    }
+                }
+                running = Some(task);
+            }
+            SimulatorEvent::Release(task, time) => {
+                releases.insert(id(task), *time);
+            }
+            SimulatorEvent::End(task, time, reason) => {
+                let task = id(task);
+                if let Some(running) = running.filter(|running| *running != task) {
+                    violations.push(Violation::Overlap {
+                        running,
+                        other: task,
+                        time: *time,
+                    });
+                }
+                running = None;
+
+                if !is_htask(task) {
+                    continue;
+                }
+                if let Some(release) = releases.get(&task) {
+                    // Deadlines are implicit, i.e. one period after the arrival,
+                    // which precedes the release by less than a period of jitter.
+                    let props = tasks[&task].task.props();
+                    let arrival = release - (release - props.offset) % props.period;
+                    if *time > arrival + props.period {
+                        violations.push(Violation::HTaskDeadlineMiss { task, time: *time });
+                    }
+                }
+                if *reason == EndReason::BudgetExceedance {
+                    last_exceedance = Some(*time);
+                }
+            }
+            SimulatorEvent::ModeChange(SimulatorMode::HMode, time)
+                if last_exceedance != Some(*time) =>
+            {
+                violations.push(Violation::UnexplainedModeChange { time: *time });
+            }
+            SimulatorEvent::TaskKill(task, time) if is_htask(id(task)) => {
+                violations.push(Violation::HTaskKill {
+                    task: id(task),
+                    time: *time,
+                });
+            }
+            _ => (),
+        }
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations)
+    }
+}
+
+/// Finds the priority inversions in a run, as returned by `Simulator::fire::<true>`,
+/// ordered by their start. Priorities are the fixed ones of the simulator: custom if given,
+/// else by period. Jobs are pending from their release, after any jitter. Only jobs that
+/// ended are considered, since those dispensed with in H-mode push no event.
+pub fn detect_priority_inversions(
+    running: &[Option<TaskId>],
+    events: &[SimulatorEvent],
+    tasks: &[SimulatorTask],
+) -> Vec<InversionReport> {
+    let tasks = tasks
+        .iter()
+        .map(|t| (t.task.props().id, t))
+        .collect::<HashMap<_, _>>();
+    let priority = |id: TaskId| {
+        let task = tasks[&id];
+        let priority = task
+            .custom_priority
+            .unwrap_or(task.task.props().period as TaskId);
+        (priority, id)
+    };
+    let run_end = running.len() as TimeUnit;
+
+    let mut reports: Vec<InversionReport> = vec![];
+    for (i, event) in events.iter().enumerate() {
+        let SimulatorEvent::Release(task, release) = event else {
+            continue;
+        };
+        let blocked = task.borrow().task.props().id;
+        // The job is pending until it ends, unless the next one is released first.
+        let next = events[i + 1..].iter().find(|e| {
+            matches!(e, SimulatorEvent::Release(task, _) | SimulatorEvent::End(task, _, _)
+                if task.borrow().task.props().id == blocked)
+        });
+        let Some(SimulatorEvent::End(_, end, _)) = next else {
+            continue;
+        };
+
+        for time in *release..(*end).min(run_end) {
+            let Some(other) = running[time as usize] else {
+                continue;
+            };
+            if priority(other) <= priority(blocked) {
+                continue;
+            }
+            match reports.last_mut() {
+                Some(report)
+                    if report.blocked == blocked
+                        && report.running == other
+                        && report.end == time =>
+                {
+                    report.end += 1;
+                }
+                _ => reports.push(InversionReport {
+                    blocked,
+                    running: other,
+                    start: time,
+                    end: time + 1,
+                }),
+            }
+        }
+    }
+
+    reports.sort_by_key(|report| (report.start, report.blocked));
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::{detect_priority_inversions, verify_schedule, InversionReport, Violation};
+    use crate::simulator::{
+        scheduler::Scheduler,
+        task::{SimulatorTask, Task, TaskProps},
+        EndReason, Simulator, SimulatorEvent,
+    };
+
+    fn tasks() -> Vec<SimulatorTask> {
+        vec![
+            SimulatorTask::new_with_custom_priority(
+                Task::HTask(TaskProps {
+                    id: 1,
+                    wcet_l: 2,
+                    wcet_h: 3,
+                    offset: 0,
+                    period: 10,
+                }),
+                1,
+                3,
+            ),
+            SimulatorTask::new_with_custom_priority(
+                Task::LTask(TaskProps {
+                    id: 2,
+                    wcet_l: 1,
+                    wcet_h: 1,
+                    offset: 3,
+                    period: 5,
+                }),
+                2,
+                1,
+            ),
+            SimulatorTask::new_with_custom_priority(
+                Task::LTask(TaskProps {
+                    id: 3,
+                    wcet_l: 5,
+                    wcet_h: 5,
+                    offset: 4,
+                    period: 10,
+                }),
+                3,
+                5,
+            ),
+        ]
+    }
+
+    fn events() -> Vec<SimulatorEvent> {
+        let mut simulator = Simulator::new(tasks(), false, None);
+        simulator.fire::<true>(20).1
+    }
+
+    #[test]
+    fn correct_run() {
+        let events = events();
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, SimulatorEvent::ModeChange(_, _))));
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, SimulatorEvent::Preemption(_, _, _))));
+        assert_eq!(verify_schedule(&events, &tasks()), Ok(()));
+    }
+
+    #[test]
+    fn corrupted_runs() {
+        let task = |events: &[SimulatorEvent], id| {
+            events
+                .iter()
+                .find_map(|e| match e {
+                    SimulatorEvent::Start(task, _) if task.borrow().task.props().id == id => {
+                        Some(task.clone())
+                    }
+                    _ => None,
+                })
+                .unwrap()
+        };
+
+        // Task 2 starts while task 1 is running, without preempting it.
+        let mut events = events();
+        let htask = task(&events, 1);
+        let ltask = task(&events, 2);
+        events.insert(2, SimulatorEvent::Start(ltask.clone(), 0));
+        assert_eq!(
+            verify_schedule(&events, &tasks()),
+            Err(vec![
+                Violation::Overlap {
+                    running: 1,
+                    other: 2,
+                    time: 0
+                },
+                Violation::Overlap {
+                    running: 2,
+                    other: 1,
+                    time: 2
+                }
+            ])
+        );
+
+        // The mode change is not caused by a budget exceedance.
+        let mut events = self::events();
+        for event in events.iter_mut() {
+            if let SimulatorEvent::End(_, _, reason) = event {
+                *reason = EndReason::JobCompletion;
+            }
+        }
+        assert_eq!(
+            verify_schedule(&events, &tasks()),
+            Err(vec![
+                Violation::UnexplainedModeChange { time: 2 },
+                Violation::UnexplainedModeChange { time: 12 },
+            ])
+        );
+
+        // An H-task is killed, and one of its jobs ends past its deadline.
+        let mut events = self::events();
+        events.push(SimulatorEvent::TaskKill(htask.clone(), 20));
+        events.push(SimulatorEvent::Release(htask.clone(), 20));
+        events.push(SimulatorEvent::Start(htask.clone(), 20));
+        events.push(SimulatorEvent::End(
+            htask.clone(),
+            31,
+            EndReason::JobCompletion,
+        ));
+        assert_eq!(
+            verify_schedule(&events, &tasks()),
+            Err(vec![
+                Violation::HTaskKill { task: 1, time: 20 },
+                Violation::HTaskDeadlineMiss { task: 1, time: 31 },
+            ])
+        );
+
+        // A job first runs more than a period after its release, ending within the next one.
+        let mut events = self::events();
+        events.push(SimulatorEvent::Release(htask.clone(), 20));
+        events.push(SimulatorEvent::Start(htask.clone(), 31));
+        events.push(SimulatorEvent::End(htask, 32, EndReason::JobCompletion));
+        assert_eq!(
+            verify_schedule(&events, &tasks()),
+            Err(vec![Violation::HTaskDeadlineMiss { task: 1, time: 32 }])
+        );
+    }
+
+    /// Never preempts, and then runs the job of highest priority.
+    struct NonPreemptive;
+
+    impl Scheduler for NonPreemptive {
+        fn should_preempt(&self, _: &SimulatorTask, _: &SimulatorTask) -> bool {
+            false
+        }
+
+        fn select_next(&self, ready: &[Rc<RefCell<SimulatorTask>>]) -> usize {
+            (0..ready.len())
+                .min_by_key(|i| ready[*i].borrow().task.props().id)
+                .unwrap()
+        }
+    }
+
+    #[test]
+    fn priority_inversions() {
+        let (running, events) = Simulator::new(tasks(), false, None).fire::<true>(20);
+        assert!(detect_priority_inversions(&running, &events, &tasks()).is_empty());
+
+        let task = |id, wcet, offset, period| {
+            SimulatorTask::new(
+                Task::LTask(TaskProps {
+                    id,
+                    wcet_l: wcet,
+                    wcet_h: wcet,
+                    offset,
+                    period,
+                }),
+                wcet,
+                wcet,
+            )
+        };
+        // Task 1 is released at 1 while task 2 runs until 4, and again at 6 on an idle processor.
+        let tasks = vec![task(1, 1, 1, 5), task(2, 4, 0, 20)];
+        let mut simulator = Simulator::new(tasks.clone(), false, None);
+        simulator.scheduler = Box::new(NonPreemptive);
+        let (running, events) = simulator.fire::<true>(10);
+        assert_eq!(
+            detect_priority_inversions(&running, &events, &tasks),
+            vec![InversionReport {
+                blocked: 1,
+                running: 2,
+                start: 1,
+                end: 4
+            }]
+        );
+
+        // With jitter, task 1 only waits from its actual release.
+        let mut tasks = tasks;
+        tasks[0].release_jitter = 2;
+        let mut simulator = Simulator::new(tasks.clone(), false, None);
+        simulator.scheduler = Box::new(NonPreemptive);
+        simulator.set_seed(0);
+        let (running, events) = simulator.fire::<true>(10);
+        let release = events
+            .iter()
+            .find_map(|e| match e {
+                SimulatorEvent::Release(task, time) if task.borrow().task.props().id == 1 => {
+                    Some(*time)
+                }
+                _ => None,
+            })
+            .unwrap();
+        assert!(release > 1);
+        assert_eq!(
+            detect_priority_inversions(&running, &events, &tasks),
+            vec![InversionReport {
+                blocked: 1,
+                running: 2,
+                start: release,
+                end: 4
+            }]
+        );
+    }
+}